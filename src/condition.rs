@@ -0,0 +1,57 @@
+use parser::SExpr;
+use parser::SExprs;
+use serr::SErr;
+
+/// An error object, created by `error` or converted from a `SErr` when it
+/// is raised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionData {
+    pub kind: ConditionKind,
+    pub message: String,
+    pub irritants: SExprs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConditionKind {
+    Error,
+    FileError,
+    ReadError
+}
+
+impl ConditionData {
+    pub fn new(message: String, irritants: SExprs) -> ConditionData {
+        ConditionData { kind: ConditionKind::Error, message, irritants }
+    }
+
+    /// Converts an error into an object that can be passed to exception
+    /// handlers. Raised objects are returned as they are.
+    pub fn from_serr(err: SErr) -> SExpr {
+        // Handlers get the error itself, not where it happened.
+        let err = match err {
+            SErr::Located(_, x) => *x,
+            x => x
+        };
+        let message = err.to_string();
+        let (kind, irritants) = match err {
+            SErr::Raise(x) => return x,
+            SErr::IOErr(_) => (ConditionKind::FileError, vec![]),
+            SErr::FoundNothing
+                | SErr::ReadError(_) => (ConditionKind::ReadError, vec![]),
+            SErr::UnexpectedForm(x)
+                | SErr::Cast(_, x)
+                | SErr::NotAProcedure(x)
+                | SErr::TypeMismatch(_, x) => (ConditionKind::Error, vec![x]),
+            _ => (ConditionKind::Error, vec![])
+        };
+
+        SExpr::Condition(ConditionData { kind, message, irritants })
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            ConditionKind::Error => "error",
+            ConditionKind::FileError => "file-error",
+            ConditionKind::ReadError => "read-error"
+        }
+    }
+}
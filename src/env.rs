@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use parser::SExpr;
@@ -66,6 +66,13 @@ impl EnvRef {
             .define(key, val);
     }
 
+    pub fn declare(&self, key: String) {
+        self.0.borrow_mut()
+            .as_mut()
+            .expect("Can't find environment")
+            .declare(key);
+    }
+
     pub fn set(&self, key: String, val: SExpr) -> SResult<SExpr> {
         self.0.borrow_mut()
             .as_mut()
@@ -85,18 +92,17 @@ impl EnvRef {
 pub struct Env {
     parent: EnvRef,
     values: EnvValues,
+    /// Variables that are bound but don't have a value yet.
+    unassigned: HashSet<VarName>,
 }
 
 impl Env {
     pub fn new(parent: EnvRef) -> Env {
-        Env {
-            parent,
-            values: HashMap::new(),
-        }
+        Env::with_values(parent, HashMap::new())
     }
 
     pub fn with_values(parent: EnvRef, values: EnvValues) -> Env {
-        Env { parent, values }
+        Env { parent, values, unassigned: HashSet::new() }
     }
 
     /// Converts `Env` into a `EnvRef`.
@@ -110,6 +116,7 @@ impl Env {
 
     pub fn get(&self, name: &str) -> SResult<SExpr> {
         if self.values.contains_key(name) {
+            self.check_assigned(name)?;
             Ok(self.values[name].clone())
         } else if self.parent.is_some() {
             self.parent.get(name)
@@ -121,6 +128,7 @@ impl Env {
     pub fn with_ref<F,T>(&self, name: &str, mut f: F) -> SResult<T>
     where F: FnMut(&SExpr)->SResult<T> {
         if self.values.contains_key(name) {
+            self.check_assigned(name)?;
             let sexpr = &self.values[name];
             f(sexpr)
         } else if self.parent.is_some() {
@@ -133,6 +141,7 @@ impl Env {
     pub fn with_mut_ref<F,T>(&mut self, name: &str, mut f: F) -> SResult<T>
     where F: FnMut(&mut SExpr)->SResult<T>{
         if self.values.contains_key(name) {
+            self.check_assigned(name)?;
             let sexpr = self.values.get_mut(name).unwrap();
             f(sexpr)
         } else if self.parent.is_some() {
@@ -143,11 +152,31 @@ impl Env {
     }
 
     pub fn define(&mut self, key: String, val: SExpr) {
+        if !self.unassigned.is_empty() {
+            self.unassigned.remove(&key);
+        }
         self.values.insert(key, val);
     }
 
+    /// Binds `key` without giving it a value, using it is an error until
+    /// it is defined. `letrec` declares its variables this way.
+    pub fn declare(&mut self, key: String) {
+        self.values.insert(key.clone(), SExpr::Unspecified);
+        self.unassigned.insert(key);
+    }
+
+    fn check_assigned(&self, name: &str) -> SResult<()> {
+        if !self.unassigned.is_empty() && self.unassigned.contains(name) {
+            bail!(UnassignedVar => name)
+        }
+        Ok(())
+    }
+
     pub fn set(&mut self, key: String, val: SExpr) -> SResult<SExpr> {
         if self.values.contains_key(&key) {
+            if !self.unassigned.is_empty() {
+                self.unassigned.remove(&key);
+            }
             self.values.insert(key.clone(), val)
                 .ok_or_else(|| SErr::new_unbound_var(&key))
         } else if self.parent.is_some() {
@@ -1,11 +1,19 @@
+use std::cell::{Cell, RefCell};
 use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 use std::vec::IntoIter;
 
-use lexer::Token;
+use lexer::{Token, Span};
 use parser::SExpr;
 use parser::SExprs;
-use env::EnvRef;
-use procedure::ProcedureData;
+use env::{Env, EnvRef};
+use procedure::{ProcedureData, Param};
+use condition::ConditionData;
+use promise::{Promise, PromiseData};
+use parameter::{self, ParameterData, Parameterization};
+use primitives::equivalence::eqv;
+use primitives::hashtable::{self, Lookup};
+use primitives::list::{self, Rows};
 use serr::{SErr, SResult};
 
 pub fn eval_mut_ref<F,T>(sexpr: &SExpr, env: &EnvRef, mut f: F) -> SResult<T>
@@ -32,107 +40,1160 @@ where F: FnMut(&SExpr)->SResult<T> {
     }
 }
 
-pub fn eval(sexpr_: &SExpr, env_: &EnvRef) -> SResult<SExpr> {
-    let mut sexpr = sexpr_.clone();
-    let mut env = env_.clone_ref();
+/// Evaluates `sexpr` in `env`.
+pub fn eval(sexpr: &SExpr, env: &EnvRef) -> SResult<SExpr> {
+    Machine::run(State::Eval(sexpr.clone(), env.clone_ref()))
+}
+
+/// Calls `procedure` with `args`, for primitives that take procedures.
+pub fn apply(procedure: &SExpr, args: SExprs, env: &EnvRef) -> SResult<SExpr> {
+    Machine::run(State::Apply(procedure.as_proc()?.clone(), args, env.clone_ref()))
+}
+
+//
+// Control stack
+//
+
+/// Procedures that need access to the control stack, so they are handled
+/// by the evaluator itself instead of being primitives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Any,
+    Apply,
+    CallCC,
+    CallEC,
+    CallWithValues,
+    DynamicWind,
+    Every,
+    Fold,
+    ForEach,
+    Force,
+    /// `hash-table-ref`, which calls its `failure` or `success` procedure.
+    HashTableRef,
+    MakeParameter,
+    Map,
+    /// Called as `(parameterize-control p1 v1 ... thunk)` by `parameterize`,
+    /// it's not bound to any name.
+    Parameterize,
+    Raise,
+    RaiseContinuable,
+    WithExceptionHandler,
+}
+
+/// A `let`, `let*`, `letrec` or `letrec*` whose values are being
+/// evaluated.
+#[derive(Debug, Clone)]
+pub struct Let {
+    /// The remaining bindings.
+    bindings: IntoIter<(Target, SExpr)>,
+    body: SExprs,
+    /// The environment that the variables are bound in.
+    env: EnvRef,
+    /// The environment that the values are evaluated in.
+    init_env: EnvRef,
+    /// `letrec` assigns its variables only after all the values are
+    /// evaluated, they wait here until then.
+    pending: Option<Vec<(Target, SExpr)>>,
+}
+
+/// A `parameterize` whose values are being converted.
+#[derive(Debug, Clone)]
+pub struct Parameterize {
+    /// The remaining parameters, with their unconverted values.
+    pending: IntoIter<(ParameterData, SExpr)>,
+    converted: Vec<(ParameterData, SExpr)>,
+    thunk: SExpr,
+    env: EnvRef,
+}
+
+/// A `map`, `for-each`, `fold`, `any` or `every` that calls its procedure
+/// on the rows of its lists. The calls are made by the machine, so the
+/// continuations captured in them can be re-entered.
+#[derive(Debug, Clone)]
+pub struct Traversal {
+    control: Control,
+    procedure: ProcedureData,
+    rows: Rows,
+    /// The results of `map` so far.
+    mapped: SExprs,
+    env: EnvRef,
+}
+
+impl Traversal {
+    fn new(control: Control, procedure: SExpr, lists: SExprs, env: EnvRef) -> SResult<Traversal> {
+        Ok(Traversal {
+            control,
+            procedure: procedure.as_proc()?.clone(),
+            rows: list::rows(lists)?,
+            mapped: vec![],
+            env
+        })
+    }
+}
+
+/// What a binding of a `let` binds: a variable, or parameters that take
+/// all the values of the init, like the ones of `let-values`.
+#[derive(Debug, Clone)]
+pub enum Target {
+    Variable(String),
+    Values(Param),
+}
+
+impl Target {
+    fn define(self, value: SExpr, env: &EnvRef) -> SResult<()> {
+        match self {
+            Target::Variable(name) => env.define(name, value),
+            Target::Values(params) => params.bind(value.into_values(), |name, x| env.define(name, x))?
+        }
+        Ok(())
+    }
+
+    fn declare(&self, env: &EnvRef) {
+        match self {
+            Target::Variable(name) => env.declare(name.clone()),
+            Target::Values(params) => {
+                for name in params.names() {
+                    env.declare(name.clone());
+                }
+            }
+        }
+    }
+}
+
+/// A frame of the control stack. Each one describes what to do with the
+/// value of the expression that is being evaluated.
+#[derive(Debug, Clone)]
+pub enum Frame {
+    If(SExpr, SExpr, EnvRef),
+    Begin(IntoIter<SExpr>, EnvRef),
+    /// The remaining operands of an `and` or an `or`.
+    And(IntoIter<SExpr>, EnvRef),
+    Or(IntoIter<SExpr>, EnvRef),
+    /// Waiting for the test of a `cond` clause, with the body of that
+    /// clause and the remaining ones.
+    Cond(SExprs, IntoIter<SExpr>, EnvRef),
+    /// Waiting for the key of a `case`, with its clauses.
+    Case(IntoIter<SExpr>, EnvRef),
+    /// Waiting for the procedure after `=>` in a `cond` or `case` clause,
+    /// with the value to call it with.
+    Receiver(SExpr, EnvRef),
+    /// `let`, `let*`, `letrec` and `letrec*`: waiting for the value of a
+    /// variable.
+    Bind(Target, Let),
+    Define(String, EnvRef),
+    DefineValues(Param, EnvRef),
+    Set(String, EnvRef),
+    /// Waiting for the operator of an application, with its operands and
+    /// the location of the application.
+    Operator(SExprs, EnvRef, Option<Span>),
+    /// Evaluating operands; holds the remaining and the evaluated ones.
+    Operands(ProcedureData, IntoIter<SExpr>, SExprs, EnvRef, Option<Span>),
+    /// `dynamic-wind`: waiting for the `before` thunk.
+    WindBefore(SExpr, SExpr, SExpr),
+    /// `dynamic-wind`: waiting for the body thunk, then calls `after`.
+    WindBody(SExpr, Winders),
+    /// Waiting for the expression of a promise that is being forced.
+    Force(PromiseData),
+    /// `make-parameter`: waiting for the converter to return the initial
+    /// value, holds the converter.
+    MakeParameter(SExpr),
+    /// `parameterize`: waiting for the converter of this parameter.
+    Convert(ParameterData, Parameterize),
+    /// `call-with-values`: calls the consumer with the returned values.
+    Consumer(SExpr, EnvRef),
+    /// Waiting for the procedure of a `map`, `for-each`, `fold`, `any` or
+    /// `every` to return.
+    Traverse(Traversal),
+    /// Ignores the returned value and returns this one instead.
+    Value(SExpr),
+    /// Calls a thunk with the given winders while a continuation is being
+    /// reinstated.
+    WindStep(SExpr, Winders),
+    SetWinders(Winders),
+    SetHandlers(Handlers),
+    SetParameterization(Parameterization),
+    /// A handler returned from a non-continuable `raise` of this object.
+    Raised(SExpr, Option<Span>),
+    /// Marks the extent of an escape-only continuation.
+    Escape(usize),
+}
+
+impl Frame {
+    /// Whether the value is given to something that takes a single value.
+    /// The other frames pass multiple values along or take all of them.
+    fn takes_one_value(&self) -> bool {
+        matches!(self,
+            Frame::If(_, _, _)
+                | Frame::And(_, _)
+                | Frame::Or(_, _)
+                | Frame::Cond(_, _, _)
+                | Frame::Case(_, _)
+                | Frame::Receiver(_, _)
+                | Frame::Bind(Target::Variable(_), _)
+                | Frame::Define(_, _)
+                | Frame::Set(_, _)
+                | Frame::Operator(_, _, _)
+                | Frame::Operands(_, _, _, _, _)
+                | Frame::MakeParameter(_)
+                | Frame::Convert(_, _))
+            || matches!(self, Frame::Traverse(x) if x.control != Control::ForEach)
+    }
+}
+
+/// Checks that `value` isn't the result of a `values` that returned zero
+/// or several values.
+fn single(value: SExpr) -> SResult<SExpr> {
+    match value {
+        SExpr::Values(xs) if xs.is_empty() => bail!("Expected a single value, found no values"),
+        SExpr::Values(xs) => bail!("Expected a single value, found {} values: {}", xs.len(), SExpr::Values(xs)),
+        x => Ok(x)
+    }
+}
+
+/// Active `dynamic-wind` extents, innermost first.
+pub type Winders = Option<Rc<Winder>>;
+
+#[derive(Debug)]
+pub struct Winder {
+    before: SExpr,
+    after: SExpr,
+    depth: usize,
+    parent: Winders,
+}
+
+fn winders_depth(winders: &Winders) -> usize {
+    winders.as_ref().map_or(0, |w| w.depth)
+}
+
+fn same_winders(x: &Winders, y: &Winders) -> bool {
+    match (x, y) {
+        (Some(x), Some(y)) => Rc::ptr_eq(x, y),
+        (None, None) => true,
+        _ => false
+    }
+}
+
+/// Installed exception handlers, innermost first.
+pub type Handlers = Option<Rc<Handler>>;
+
+#[derive(Debug)]
+pub struct Handler {
+    handler: SExpr,
+    parent: Handlers,
+}
+
+/// A continuation captured by `call/cc` or `call/ec`.
+#[derive(Debug, Clone)]
+pub struct ContinuationData {
+    kind: ContinuationKind,
+    winders: Winders,
+    handlers: Handlers,
+    parameterization: Parameterization,
+    /// The machine run that captured this continuation.
+    run: usize,
+}
+
+#[derive(Debug, Clone)]
+enum ContinuationKind {
+    /// A re-entrant continuation, holding a copy of the control stack.
+    Full(Rc<Vec<Frame>>, /*captured at toplevel:*/ bool),
+    /// An escape-only continuation; only valid while its `Escape` frame is
+    /// on the stack, at the given height.
+    Escape(usize, usize),
+}
+
+impl PartialEq for ContinuationData {
+    fn eq(&self, other: &ContinuationData) -> bool {
+        match (&self.kind, &other.kind) {
+            (ContinuationKind::Full(x, _), ContinuationKind::Full(y, _)) => Rc::ptr_eq(x, y),
+            (ContinuationKind::Escape(x, _), ContinuationKind::Escape(y, _)) => x == y,
+            _ => false
+        }
+    }
+}
+
+/// A continuation invoked from a run other than the one it belongs to. It
+/// travels as an error until it reaches its own run.
+#[derive(Debug)]
+pub struct Jump {
+    target: usize,
+    continuation: ContinuationData,
+    value: SExpr,
+}
+
+thread_local! {
+    static WINDERS: RefCell<Winders> = const { RefCell::new(None) };
+    static HANDLERS: RefCell<Handlers> = const { RefCell::new(None) };
+    /// Ids of the machine runs that are currently on the Rust stack.
+    static RUNS: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+    static NEXT_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+fn next_id() -> usize {
+    NEXT_ID.with(|x| {
+        x.set(x.get() + 1);
+        x.get()
+    })
+}
+
+fn current_winders() -> Winders {
+    WINDERS.with(|w| w.borrow().clone())
+}
+
+fn set_winders(winders: Winders) {
+    WINDERS.with(|w| *w.borrow_mut() = winders);
+}
+
+fn current_handlers() -> Handlers {
+    HANDLERS.with(|h| h.borrow().clone())
+}
+
+fn set_handlers(handlers: Handlers) {
+    HANDLERS.with(|h| *h.borrow_mut() = handlers);
+}
+
+fn is_active(run: usize) -> bool {
+    RUNS.with(|r| r.borrow().contains(&run))
+}
+
+fn toplevel_run() -> Option<usize> {
+    RUNS.with(|r| r.borrow().first().cloned())
+}
+
+enum State {
+    Eval(SExpr, EnvRef),
+    Apply(ProcedureData, SExprs, EnvRef),
+    Return(SExpr),
+}
 
-    loop {
-        match sexpr {
+/// Evaluates expressions with an explicit control stack, so continuations
+/// can be captured by copying it. A primitive that calls back into the
+/// evaluator starts a nested run; continuations captured inside it can
+/// escape from it but can't be re-entered once it has returned. Runs that
+/// are started from toplevel have no such restriction.
+struct Machine {
+    id: usize,
+    stack: Vec<Frame>,
+    /// Environment that the run was started in, `dynamic-wind` thunks get
+    /// called in it.
+    env: EnvRef,
+    /// Where the expression that is being evaluated, or the procedure that
+    /// is being applied, was read from. Errors that aren't handled are
+    /// reported with it.
+    location: Option<Span>,
+}
+
+impl Machine {
+    fn run(state: State) -> SResult<SExpr> {
+        let env = match state {
+            State::Eval(_, ref env) | State::Apply(_, _, ref env) => env.clone_ref(),
+            State::Return(_) => EnvRef::null()
+        };
+        let mut machine = Machine { id: next_id(), stack: vec![], env, location: None };
+        let winders = current_winders();
+        let handlers = current_handlers();
+        let parameterization = parameter::current_parameterization();
+
+        RUNS.with(|r| r.borrow_mut().push(machine.id));
+        let result = machine.execute(state);
+        let toplevel = RUNS.with(|r| {
+            let mut runs = r.borrow_mut();
+            runs.pop();
+            runs.is_empty()
+        });
+
+        match result {
+            Err(SErr::Jump(x)) => Err(SErr::Jump(x)),
+            Err(e) => {
+                // The frames that would restore them are gone.
+                set_winders(winders);
+                set_handlers(handlers);
+                parameter::set_parameterization(parameterization);
+
+                // Handlers of the outer runs have seen this error already.
+                match e {
+                    SErr::Unhandled(e) if toplevel => Err(*e),
+                    e@SErr::Unhandled(_) => Err(e),
+                    e if toplevel => Err(e),
+                    e => Err(SErr::Unhandled(Box::new(e)))
+                }
+            },
+            Ok(x) => Ok(x)
+        }
+    }
+
+    fn execute(&mut self, mut state: State) -> SResult<SExpr> {
+        loop {
+            let result = match state {
+                State::Eval(sexpr, env) => self.eval(sexpr, env),
+                State::Apply(procedure, args, env) => self.apply(procedure, args, env),
+                State::Return(value) => match self.stack.pop() {
+                    Some(frame) => self.resume(frame, value),
+                    None => return Ok(value)
+                }
+            };
+
+            state = match result {
+                Ok(state) => state,
+                Err(SErr::Jump(jump)) => {
+                    if jump.target == self.id {
+                        self.reinstate(jump.continuation, jump.value)?
+                    } else {
+                        return Err(SErr::Jump(jump))
+                    }
+                },
+                Err(e@SErr::Unhandled(_)) => return Err(e.located(self.location.as_ref())),
+                Err(e) => {
+                    if current_handlers().is_none() {
+                        return Err(e.located(self.location.as_ref()))
+                    }
+                    self.raise(ConditionData::from_serr(e), false)?
+                }
+            };
+        }
+    }
+
+    fn eval(&mut self, sexpr: SExpr, env: EnvRef) -> SResult<State> {
+        let xs = match sexpr {
+            SExpr::Located(span, x) => {
+                self.location = Some(span);
+                return self.eval(*x, env)
+            },
             SExpr::Atom(Token::Symbol(x)) => {
-                return env.get(&x)
-            },
-            x@SExpr::Atom(_) | x@SExpr::Procedure(_)
-                | x@SExpr::Port(_) | x@SExpr::Unspecified => {
-                return Ok(x)
-            },
-            list@SExpr::DottedList(_,_) => {
-                fn flatten(list: SExpr) -> SExprs {
-                    match list {
-                        SExpr::DottedList(xs, sexpr) => {
-                            let mut ys = xs;
-                            match *sexpr {
-                                SExpr::List(mut xs) => ys.append(&mut xs),
-                                dl@SExpr::DottedList(_,_) => ys.append(&mut flatten(dl)),
-                                x => ys.push(x)
-                            };
-                            ys
-                        },
-                        SExpr::List(xs) => xs,
-                        x => vec![x]
+                return Ok(State::Return(env.get(&x)?))
+            },
+            list@SExpr::DottedList(_,_) => flatten(list),
+            SExpr::List(xs) => xs,
+            x => return Ok(State::Return(x))
+        };
+
+        let mut iter = xs.into_iter();
+        let op = iter.next()
+            .ok_or_else(|| SErr::new_unexpected_form(&SExpr::List(vec![])))?;
+        let mut args = iter.collect::<SExprs>();
+
+        // Need to handle control structures here to be able to use same
+        // stack for tail recursive functions. Other control structures
+        // should be written in terms of these.
+        match op {
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "if" => {
+                let mut arg_iter = args.into_iter();
+                let test = arg_iter.next()
+                    .ok_or_else(|| SErr::WrongArgCount(2, 0))?;
+                let consequent = arg_iter.next()
+                    .ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+                let alterne = arg_iter.next()
+                    .unwrap_or(SExpr::Unspecified);
+
+                self.stack.push(Frame::If(consequent, alterne, env.clone_ref()));
+                Ok(State::Eval(test, env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "begin" => {
+                if args.is_empty() {
+                    bail!("Bodyless `begin`")
+                }
+                self.body(args.into_iter(), env)
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "and" || sym == "or" => {
+                if args.is_empty() {
+                    return Ok(State::Return(sbool!(sym == "and")))
+                }
+                Ok(self.and_or(sym == "and", args.into_iter(), env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "when" || sym == "unless" => {
+                let mut iter = args.into_iter();
+                let test = iter.next()
+                    .ok_or_else(|| SErr::WrongArgCount(2, 0))?;
+                if iter.len() == 0 {
+                    return Err(SErr::WrongArgCount(2, 1))
+                }
+
+                let mut body = vec![ssymbol!("begin")];
+                body.extend(iter);
+                let (then, otherwise) = if sym == "when" {
+                    (SExpr::List(body), SExpr::Unspecified)
+                } else {
+                    (SExpr::Unspecified, SExpr::List(body))
+                };
+                self.stack.push(Frame::If(then, otherwise, env.clone_ref()));
+                Ok(State::Eval(test, env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "delay" || sym == "delay-force" => {
+                if args.len() != 1 {
+                    return Err(SErr::WrongArgCount(1, args.len()))
+                }
+                let expr = args.pop().unwrap();
+                let promise = if sym == "delay" {
+                    Promise::Delay(expr, env)
+                } else {
+                    Promise::DelayForce(expr, env)
+                };
+                Ok(State::Return(SExpr::Promise(PromiseData::new(promise))))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "parameterize" => {
+                // Becomes (parameterize-control p1 v1 ... (lambda () body...)),
+                // the parameters and the values are evaluated like operands.
+                let mut iter = args.into_iter();
+                let bindings = iter.next()
+                    .ok_or_else(|| SErr::WrongArgCount(2, 0))?;
+                let body = iter.collect::<SExprs>();
+                if body.is_empty() {
+                    return Err(SErr::WrongArgCount(2, 1))
+                }
+
+                let mut application = vec![ProcedureData::new_control(Control::Parameterize)];
+                for binding in bindings.into_list()? {
+                    let binding = binding.into_list()?;
+                    if binding.len() != 2 {
+                        bail!(UnexpectedForm => SExpr::List(binding))
+                    }
+                    application.extend(binding);
+                }
+                application.push(ProcedureData::new_compound(SExpr::List(vec![]), body, &env)?);
+                Ok(State::Eval(SExpr::List(application), env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "cond" => {
+                self.cond(args.into_iter(), env)
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "case" => {
+                let mut iter = args.into_iter();
+                let key = iter.next()
+                    .ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+                self.stack.push(Frame::Case(iter, env.clone_ref()));
+                Ok(State::Eval(key, env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if is_let(sym) => {
+                let values = sym.ends_with("-values");
+                let mut iter = args.into_iter();
+                let bindings = iter.next()
+                    .ok_or_else(|| SErr::WrongArgCount(2, 0))?
+                    .into_list()?
+                    .into_iter()
+                    .map(|x| binding(x, values))
+                    .collect::<SResult<Vec<_>>>()?;
+
+                let inner = Env::new(env.clone_ref()).into_ref();
+                if sym.starts_with("letrec") {
+                    for (target, _) in &bindings {
+                        target.declare(&inner);
                     }
                 }
 
-                sexpr = SExpr::List(flatten(list));
-            },
-            SExpr::List(xs) => {
-                let mut iter = xs.into_iter();
-                let op = iter.next()
-                    .ok_or_else(|| SErr::new_unexpected_form(&SExpr::List(vec![])))?;
-                let mut args = Args::new(iter.collect(), &env);
-
-                match op {
-                    // Need to handle control structres like if and begin
-                    // here to be able to use same stack for tail recursive
-                    // functions.
-                    // Other control structres should be written in forms of
-                    // if or begin (and I hope that's all for basic TCO)
-                    SExpr::Atom(Token::Symbol(ref sym)) if sym == "if" => {
-                        let mut arg_iter = args.into_iter();
-                        let test = arg_iter.next()
-                            .ok_or_else(|| SErr::WrongArgCount(2, 0))?;
-                        let consequent = arg_iter.next()
-                            .ok_or_else(|| SErr::WrongArgCount(2, 1))?;
-                        let alterne = arg_iter.next()
-                            .unwrap_or(SExpr::Unspecified);
-
-                        if test.eval(&env)?.to_bool() {
-                            sexpr = consequent;
-                        } else {
-                            sexpr = alterne;
-                        }
-                    },
-                    SExpr::Atom(Token::Symbol(ref sym)) if sym == "begin" => {
-                        let last = args.pop()
-                            .ok_or_else(|| SErr::new_generic("Bodyless `begin`"))?;
-                        args.eval()?; // eval all except the last one
-                        sexpr = last;
-                    },
-                    SExpr::Atom(Token::Symbol(symbol)) => {
-                        let procedure = args.env
-                            .get(&symbol)?
-                            .clone();
-
-                        match procedure {
-                            SExpr::Procedure(proc) => match proc {
-                                ProcedureData::Primitive(x) => return x.apply(args),
-                                ProcedureData::Compound(x) => {
-                                    env = x.build_env(args)?;
-                                    sexpr = *x.body;
-                                }
-                            },
-                            _ => bail!(NotAProcedure => procedure)
-                        };
+                self.bind(Let {
+                    bindings: bindings.into_iter(),
+                    body: iter.collect(),
+                    init_env: if sym == "let" || sym == "let-values" { env } else { inner.clone_ref() },
+                    env: inner,
+                    pending: if sym == "letrec" { Some(vec![]) } else { None },
+                })
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) if sym == "define-values" => {
+                if args.len() != 2 {
+                    return Err(SErr::WrongArgCount(2, args.len()))
+                }
+                let init = args.pop().unwrap();
+                let params = Param::parse(args.pop().unwrap())?;
+                self.stack.push(Frame::DefineValues(params, env.clone_ref()));
+                Ok(State::Eval(init, env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym))
+                if (sym == "define" || sym == "set!") && args.len() == 2 && args[0].as_symbol().is_ok() => {
+                let value = args.pop().unwrap();
+                let name = args.pop().unwrap().into_symbol()?;
+                if sym == "define" {
+                    self.stack.push(Frame::Define(name, env.clone_ref()));
+                } else {
+                    self.stack.push(Frame::Set(name, env.clone_ref()));
+                }
+                Ok(State::Eval(value, env))
+            },
+            SExpr::Atom(Token::Symbol(ref sym)) => {
+                let procedure = env.get(sym)?;
+                self.operator(procedure, args, env)
+            },
+            // A variable in operator position is looked up right away, its
+            // own location is only needed when it's unbound.
+            SExpr::Located(span, x) => match *x {
+                SExpr::Atom(Token::Symbol(ref sym)) => {
+                    let procedure = env.get(sym)
+                        .map_err(|e| e.located(Some(&span)))?;
+                    self.operator(procedure, args, env)
+                },
+                x => {
+                    self.stack.push(Frame::Operator(args, env.clone_ref(), self.location.clone()));
+                    Ok(State::Eval(SExpr::Located(span, Box::new(x)), env))
+                }
+            },
+            x => {
+                self.stack.push(Frame::Operator(args, env.clone_ref(), self.location.clone()));
+                Ok(State::Eval(x, env))
+            }
+        }
+    }
+
+    /// Evaluates a sequence of expressions, the last one is in tail
+    /// position.
+    fn body(&mut self, mut exprs: IntoIter<SExpr>, env: EnvRef) -> SResult<State> {
+        let first = exprs.next()
+            .ok_or_else(|| SErr::new_generic("Expected at least one expression in body"))?;
+        if exprs.len() > 0 {
+            self.stack.push(Frame::Begin(exprs, env.clone_ref()));
+        }
+        Ok(State::Eval(first, env))
+    }
+
+    /// Evaluates the next operand of an `and` or an `or`, `exprs` can't be
+    /// empty.
+    fn and_or(&mut self, is_and: bool, mut exprs: IntoIter<SExpr>, env: EnvRef) -> State {
+        let next = exprs.next().unwrap();
+        if exprs.len() > 0 {
+            let frame = if is_and { Frame::And(exprs, env.clone_ref()) } else { Frame::Or(exprs, env.clone_ref()) };
+            self.stack.push(frame);
+        }
+        State::Eval(next, env)
+    }
+
+    /// Evaluates the test of the next `cond` clause.
+    fn cond(&mut self, mut clauses: IntoIter<SExpr>, env: EnvRef) -> SResult<State> {
+        let clause = match clauses.next() {
+            Some(x) => x.into_list()?,
+            None => return Ok(State::Return(SExpr::Unspecified))
+        };
+
+        let mut iter = clause.into_iter();
+        let test = iter.next()
+            .ok_or_else(|| SErr::new_unexpected_form(&SExpr::List(vec![])))?;
+        if test.is_symbol("else") {
+            return self.body(iter, env)
+        }
+
+        self.stack.push(Frame::Cond(iter.collect(), clauses, env.clone_ref()));
+        Ok(State::Eval(test, env))
+    }
+
+    /// Evaluates the body of a `cond` or `case` clause that was selected by
+    /// `value`. An empty body returns `value` and `=> receiver` calls
+    /// `receiver` with it.
+    fn clause(&mut self, body: SExprs, value: SExpr, env: EnvRef) -> SResult<State> {
+        match body.first() {
+            None => Ok(State::Return(value)),
+            Some(x) if x.is_symbol("=>") => {
+                if body.len() != 2 {
+                    bail!(UnexpectedForm => SExpr::List(body))
+                }
+                let receiver = body.into_iter().nth(1).unwrap();
+                self.stack.push(Frame::Receiver(value, env.clone_ref()));
+                Ok(State::Eval(receiver, env))
+            },
+            _ => self.body(body.into_iter(), env)
+        }
+    }
+
+    /// Evaluates the value of the next binding of a `let`, or its body
+    /// once all of them are bound.
+    fn bind(&mut self, mut state: Let) -> SResult<State> {
+        let (target, init) = match state.bindings.next() {
+            Some(x) => x,
+            None => {
+                for (target, value) in state.pending.unwrap_or_default() {
+                    target.define(value, &state.env)?;
+                }
+                return self.body(state.body.into_iter(), state.env)
+            }
+        };
+
+        let init_env = state.init_env.clone_ref();
+        self.stack.push(Frame::Bind(target, state));
+        Ok(State::Eval(init, init_env))
+    }
+
+    /// Continues an application once its operator is known.
+    fn operator(&mut self, op: SExpr, args: SExprs, env: EnvRef) -> SResult<State> {
+        let procedure = match op {
+            SExpr::Procedure(procedure) => procedure,
+            x => bail!(NotAProcedure => x)
+        };
+
+        if let ProcedureData::Primitive(ref x) = procedure {
+            if x.is_special_form() {
+                return Ok(State::Return(x.apply(Args::new(args, &env))?))
+            }
+        }
+
+        let mut iter = args.into_iter();
+        match iter.next() {
+            Some(first) => {
+                self.stack.push(Frame::Operands(procedure, iter, vec![], env.clone_ref(), self.location.clone()));
+                Ok(State::Eval(first, env))
+            },
+            None => Ok(State::Apply(procedure, vec![], env))
+        }
+    }
+
+    fn apply(&mut self, procedure: ProcedureData, args: SExprs, env: EnvRef) -> SResult<State> {
+        match procedure {
+            ProcedureData::Primitive(x) => {
+                Ok(State::Return(x.apply(Args::evaluated(args, &env))?))
+            },
+            ProcedureData::Compound(x) => {
+                let (body, inner_env) = x.bind(args)?;
+                Ok(State::Eval(body, inner_env))
+            },
+            ProcedureData::Continuation(k) => {
+                self.throw(k, SExpr::values(args))
+            },
+            ProcedureData::Control(control) => self.control(control, args, env),
+            ProcedureData::Parameter(parameter) => {
+                if !args.is_empty() {
+                    return Err(SErr::WrongArgCount(0, args.len()))
+                }
+                Ok(State::Return(parameter.value()))
+            },
+            ProcedureData::Record(x) => Ok(State::Return(x.apply(args)?))
+        }
+    }
+
+    fn control(&mut self, control: Control, args: SExprs, env: EnvRef) -> SResult<State> {
+        let args = Args::evaluated(args, &env);
+        match control {
+            Control::Any | Control::Every | Control::ForEach | Control::Map => {
+                let (procedure, lists) = args.own_one_rest()?;
+                let initial = sbool!(control == Control::Every);
+                Ok(self.traverse(Traversal::new(control, procedure, lists, env)?, initial))
+            },
+            Control::Apply => {
+                if args.len() < 2 {
+                    return Err(SErr::WrongArgCount(2, args.len()))
+                }
+
+                let (procedure, mut rest) = args.own_one_rest()?;
+                let mut arg_list = rest.split_off(rest.len() - 1);
+                rest.append(&mut arg_list.pop().unwrap().into_list()?);
+                Ok(State::Apply(procedure.as_proc()?.clone(), rest, env))
+            },
+            Control::CallCC => {
+                let procedure = args.own_one()?;
+                let root = toplevel_run() == Some(self.id);
+                let k = ContinuationData {
+                    kind: ContinuationKind::Full(Rc::new(self.stack.clone()), root),
+                    winders: current_winders(),
+                    handlers: current_handlers(),
+                    parameterization: parameter::current_parameterization(),
+                    run: self.id
+                };
+                let k = SExpr::Procedure(ProcedureData::Continuation(k));
+                Ok(State::Apply(procedure.as_proc()?.clone(), vec![k], env))
+            },
+            Control::CallEC => {
+                let procedure = args.own_one()?;
+                let id = next_id();
+                let k = ContinuationData {
+                    kind: ContinuationKind::Escape(id, self.stack.len()),
+                    winders: current_winders(),
+                    handlers: current_handlers(),
+                    parameterization: parameter::current_parameterization(),
+                    run: self.id
+                };
+                self.stack.push(Frame::Escape(id));
+                let k = SExpr::Procedure(ProcedureData::Continuation(k));
+                Ok(State::Apply(procedure.as_proc()?.clone(), vec![k], env))
+            },
+            Control::CallWithValues => {
+                let (producer, consumer) = args.own_two()?;
+                consumer.as_proc()?;
+                self.stack.push(Frame::Consumer(consumer, env.clone_ref()));
+                Ok(State::Apply(producer.as_proc()?.clone(), vec![], env))
+            },
+            Control::DynamicWind => {
+                let (before, thunk, after) = args.own_three()?;
+                before.as_proc()?;
+                self.stack.push(Frame::WindBefore(before.clone(), thunk, after));
+                Ok(State::Apply(before.as_proc()?.clone(), vec![], env))
+            },
+            Control::Fold => {
+                if args.len() < 3 {
+                    return Err(SErr::WrongArgCount(3, args.len()))
+                }
+
+                let (procedure, mut lists) = args.own_one_rest()?;
+                let knil = lists.remove(0);
+                Ok(self.traverse(Traversal::new(control, procedure, lists, env)?, knil))
+            },
+            Control::Force => match args.own_one()? {
+                SExpr::Promise(promise) => Ok(self.force(promise)),
+                // Forcing anything else returns it as it is.
+                x => Ok(State::Return(x))
+            },
+            Control::HashTableRef => match hashtable::hash_table_ref(args)? {
+                Lookup::Found(value, None) => Ok(State::Return(value)),
+                Lookup::Found(value, Some(success)) =>
+                    Ok(State::Apply(success.as_proc()?.clone(), vec![value], env)),
+                Lookup::Missing(failure) =>
+                    Ok(State::Apply(failure.as_proc()?.clone(), vec![], env))
+            },
+            Control::MakeParameter => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err(SErr::WrongArgCount(1, args.len()))
+                }
+                let (value, mut rest) = args.own_one_rest()?;
+                match rest.pop() {
+                    Some(converter) => {
+                        let procedure = converter.as_proc()?.clone();
+                        self.stack.push(Frame::MakeParameter(converter));
+                        Ok(State::Apply(procedure, vec![value], env))
                     },
-                    x => {
-                        // Trying to use something other than a symbol as procedure
-                        // Evaluate and see if it's a procedure.
-                        let evaled = eval(&x, &env)?;
-                        if let SExpr::Procedure(procedure) = evaled {
-                            match procedure {
-                                ProcedureData::Primitive(x) => return x.apply(args),
-                                ProcedureData::Compound(x) => {
-                                    env = x.build_env(args)?;
-                                    sexpr = *x.body;
-                                }
-                            };
-                        } else {
-                            bail!(NotAProcedure => x)
-                        }
+                    None => Ok(State::Return(ProcedureData::new_parameter(ParameterData::new(value, None))))
+                }
+            },
+            Control::Parameterize => {
+                let mut args = args.into_iter().collect::<SExprs>();
+                let thunk = args.pop()
+                    .ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+                if args.len() % 2 != 0 {
+                    bail!("`parameterize` takes a value for each parameter")
+                }
+
+                let mut bindings = vec![];
+                let mut iter = args.into_iter();
+                while let (Some(parameter), Some(value)) = (iter.next(), iter.next()) {
+                    match parameter {
+                        SExpr::Procedure(ProcedureData::Parameter(x)) => bindings.push((x, value)),
+                        x => bail!(TypeMismatch => "parameter", x)
                     }
                 }
+
+                self.convert(Parameterize {
+                    pending: bindings.into_iter(),
+                    converted: vec![],
+                    thunk,
+                    env
+                })
+            },
+            Control::Raise => self.raise(args.own_one()?, false),
+            Control::RaiseContinuable => self.raise(args.own_one()?, true),
+            Control::WithExceptionHandler => {
+                let (handler, thunk) = args.own_two()?;
+                handler.as_proc()?;
+                let parent = current_handlers();
+                self.stack.push(Frame::SetHandlers(parent.clone()));
+                set_handlers(Some(Rc::new(Handler { handler, parent })));
+                Ok(State::Apply(thunk.as_proc()?.clone(), vec![], env))
             }
+        }
+    }
+
+    /// Calls the procedure of a traversal on its next row. `last` is what
+    /// the previous call returned, or the initial value: the accumulator of
+    /// `fold` and the result of `any` and `every` when the lists run out.
+    fn traverse(&mut self, mut traversal: Traversal, last: SExpr) -> State {
+        let mut row = match traversal.rows.next() {
+            Some(row) => row,
+            None => return State::Return(match traversal.control {
+                Control::Map => SExpr::list_from(traversal.mapped),
+                Control::ForEach => SExpr::Unspecified,
+                _ => last
+            })
+        };
+
+        if traversal.control == Control::Fold {
+            row.push(last);
+        }
+        let procedure = traversal.procedure.clone();
+        let env = traversal.env.clone_ref();
+        self.stack.push(Frame::Traverse(traversal));
+        State::Apply(procedure, row, env)
+    }
+
+    /// Returns the value of a promise or evaluates its expression. The
+    /// promises of `delay-force` are forced in a loop, so a long chain of
+    /// them runs in constant space.
+    fn force(&mut self, promise: PromiseData) -> State {
+        match promise.get() {
+            Promise::Done(x) => State::Return(x),
+            Promise::Delay(expr, env) | Promise::DelayForce(expr, env) => {
+                self.stack.push(Frame::Force(promise));
+                State::Eval(expr, env)
+            }
+        }
+    }
+
+    /// Calls the converter of the next parameter of a `parameterize`, or its
+    /// body once all the values are converted.
+    fn convert(&mut self, mut state: Parameterize) -> SResult<State> {
+        loop {
+            let (parameter, value) = match state.pending.next() {
+                Some(x) => x,
+                None => break
+            };
+
+            match parameter.converter() {
+                Some(converter) => {
+                    let converter = converter.as_proc()?.clone();
+                    let env = state.env.clone_ref();
+                    self.stack.push(Frame::Convert(parameter, state));
+                    return Ok(State::Apply(converter, vec![value], env))
+                },
+                None => state.converted.push((parameter, value))
+            }
+        }
+
+        self.stack.push(Frame::SetParameterization(parameter::current_parameterization()));
+        parameter::set_parameterization(parameter::parameterize(state.converted));
+        Ok(State::Apply(state.thunk.as_proc()?.clone(), vec![], state.env))
+    }
+
+    /// Calls the current exception handler with `obj`, while the outer
+    /// handlers are installed.
+    fn raise(&mut self, obj: SExpr, continuable: bool) -> SResult<State> {
+        let handlers = current_handlers();
+        let handler = match handlers {
+            Some(ref x) => x.handler.as_proc()?.clone(),
+            None => return Err(SErr::Raise(obj))
+        };
+
+        self.stack.push(Frame::SetHandlers(handlers.clone()));
+        if !continuable {
+            self.stack.push(Frame::Raised(obj.clone(), self.location.clone()));
+        }
+        set_handlers(handlers.and_then(|x| x.parent.clone()));
+        Ok(State::Apply(handler, vec![obj], self.env.clone_ref()))
+    }
+
+    fn resume(&mut self, frame: Frame, value: SExpr) -> SResult<State> {
+        let value = if frame.takes_one_value() { single(value)? } else { value };
+        match frame {
+            Frame::If(consequent, alterne, env) => {
+                if value.to_bool() {
+                    Ok(State::Eval(consequent, env))
+                } else {
+                    Ok(State::Eval(alterne, env))
+                }
+            },
+            Frame::Begin(iter, env) => self.body(iter, env),
+            Frame::And(_, _) if !value.to_bool() => Ok(State::Return(value)),
+            Frame::And(iter, env) => Ok(self.and_or(true, iter, env)),
+            Frame::Or(_, _) if value.to_bool() => Ok(State::Return(value)),
+            Frame::Or(iter, env) => Ok(self.and_or(false, iter, env)),
+            Frame::Cond(_, clauses, env) if !value.to_bool() => self.cond(clauses, env),
+            Frame::Cond(body, _, env) => self.clause(body, value, env),
+            Frame::Case(clauses, env) => {
+                for clause in clauses {
+                    let mut iter = clause.into_list()?.into_iter();
+                    let data = iter.next()
+                        .ok_or_else(|| SErr::new_unexpected_form(&SExpr::List(vec![])))?;
+                    if data.is_symbol("else") || data.into_list()?.iter().any(|x| eqv(x, &value)) {
+                        return self.clause(iter.collect(), value, env)
+                    }
+                }
+                Ok(State::Return(SExpr::Unspecified))
+            },
+            Frame::Receiver(x, env) => {
+                Ok(State::Apply(value.as_proc()?.clone(), vec![x], env))
+            },
+            Frame::Bind(target, mut state) => {
+                match state.pending {
+                    Some(ref mut pending) => pending.push((target, value)),
+                    None => target.define(value, &state.env)?
+                }
+                self.bind(state)
+            },
+            Frame::Define(name, env) => {
+                env.define(name, value);
+                Ok(State::Return(SExpr::Unspecified))
+            },
+            Frame::DefineValues(params, env) => {
+                params.bind(value.into_values(), |name, x| env.define(name, x))?;
+                Ok(State::Return(SExpr::Unspecified))
+            },
+            Frame::Force(promise) => {
+                match promise.get() {
+                    // It was forced again while its expression was running.
+                    Promise::Done(_) => (),
+                    Promise::Delay(_, _) => promise.set(Promise::Done(value)),
+                    Promise::DelayForce(_, _) => match value {
+                        SExpr::Promise(x) => promise.adopt(&x),
+                        x => bail!(TypeMismatch => "promise", x)
+                    }
+                }
+                Ok(self.force(promise))
+            },
+            Frame::MakeParameter(converter) => {
+                Ok(State::Return(ProcedureData::new_parameter(ParameterData::new(value, Some(converter)))))
+            },
+            Frame::Convert(parameter, mut state) => {
+                state.converted.push((parameter, value));
+                self.convert(state)
+            },
+            Frame::Consumer(consumer, env) => {
+                Ok(State::Apply(consumer.as_proc()?.clone(), value.into_values(), env))
+            },
+            Frame::Traverse(mut traversal) => match traversal.control {
+                Control::Map => {
+                    traversal.mapped.push(value);
+                    Ok(self.traverse(traversal, SExpr::Unspecified))
+                },
+                Control::Any if value.to_bool() => Ok(State::Return(value)),
+                Control::Every if !value.to_bool() => Ok(State::Return(value)),
+                _ => Ok(self.traverse(traversal, value))
+            },
+            Frame::Set(name, env) => {
+                Ok(State::Return(env.set(name, value)?))
+            },
+            Frame::Operator(args, env, location) => {
+                self.location = location;
+                self.operator(value, args, env)
+            },
+            Frame::Operands(procedure, mut iter, mut evaled, env, location) => {
+                evaled.push(value);
+                match iter.next() {
+                    Some(next) => {
+                        self.stack.push(Frame::Operands(procedure, iter, evaled, env.clone_ref(), location));
+                        Ok(State::Eval(next, env))
+                    },
+                    None => {
+                        self.location = location;
+                        Ok(State::Apply(procedure, evaled, env))
+                    }
+                }
+            },
+            Frame::WindBefore(before, thunk, after) => {
+                let parent = current_winders();
+                let depth = winders_depth(&parent) + 1;
+                set_winders(Some(Rc::new(Winder {
+                    before, after: after.clone(), depth, parent: parent.clone()
+                })));
+                self.stack.push(Frame::WindBody(after, parent));
+                Ok(State::Apply(thunk.as_proc()?.clone(), vec![], self.env.clone_ref()))
+            },
+            Frame::WindBody(after, parent) => {
+                set_winders(parent);
+                self.stack.push(Frame::Value(value));
+                Ok(State::Apply(after.as_proc()?.clone(), vec![], self.env.clone_ref()))
+            },
+            Frame::Value(x) => Ok(State::Return(x)),
+            Frame::WindStep(thunk, winders) => {
+                set_winders(winders);
+                Ok(State::Apply(thunk.as_proc()?.clone(), vec![], self.env.clone_ref()))
+            },
+            Frame::SetWinders(winders) => {
+                set_winders(winders);
+                Ok(State::Return(value))
+            },
+            Frame::SetHandlers(handlers) => {
+                set_handlers(handlers);
+                Ok(State::Return(value))
+            },
+            Frame::SetParameterization(parameterization) => {
+                parameter::set_parameterization(parameterization);
+                Ok(State::Return(value))
+            },
+            // The handler returned, which is an error of its own. The outer
+            // handlers get it with the raised object as its irritant.
+            Frame::Raised(obj, location) => {
+                self.location = location;
+                let message = "handler returned from non-continuable raise".to_string();
+                Err(SErr::Raise(SExpr::Condition(ConditionData::new(message, vec![obj]))))
+            },
+            Frame::Escape(_) => Ok(State::Return(value))
+        }
+    }
+
+    /// Passes `value` to the continuation `k`.
+    fn throw(&mut self, k: ContinuationData, value: SExpr) -> SResult<State> {
+        let target = match k.kind {
+            ContinuationKind::Full(_, true) => toplevel_run().unwrap_or(self.id),
+            _ => k.run
         };
+
+        if !is_active(target) {
+            match k.kind {
+                ContinuationKind::Escape(_, _) => bail!("Escape continuation invoked outside of its extent"),
+                _ => bail!("Continuation can't be invoked, the primitive that it was captured in has returned")
+            }
+        }
+
+        if target == self.id {
+            self.reinstate(k, value)
+        } else {
+            Err(SErr::Jump(Box::new(Jump { target, continuation: k, value })))
+        }
+    }
+
+    /// Replaces the control stack with the one of `k`, running the
+    /// `dynamic-wind` thunks needed to get from here to there.
+    fn reinstate(&mut self, k: ContinuationData, value: SExpr) -> SResult<State> {
+        match k.kind {
+            ContinuationKind::Full(stack, _) => {
+                self.stack = (*stack).clone();
+            },
+            ContinuationKind::Escape(id, height) => {
+                match self.stack.get(height) {
+                    Some(Frame::Escape(x)) if *x == id => self.stack.truncate(height),
+                    _ => bail!("Escape continuation invoked outside of its extent")
+                }
+            }
+        }
+
+        let mut exits = vec![];
+        let mut entries = vec![];
+        let mut from = current_winders();
+        let mut to = k.winders.clone();
+        while !same_winders(&from, &to) {
+            if winders_depth(&from) >= winders_depth(&to) {
+                let w = from.unwrap();
+                exits.push(Frame::WindStep(w.after.clone(), w.parent.clone()));
+                from = w.parent.clone();
+            } else {
+                let w = to.unwrap();
+                entries.push(Frame::WindStep(w.before.clone(), w.parent.clone()));
+                to = w.parent.clone();
+            }
+        }
+
+        self.stack.push(Frame::Value(value));
+        self.stack.push(Frame::SetHandlers(k.handlers));
+        self.stack.push(Frame::SetParameterization(k.parameterization));
+        self.stack.push(Frame::SetWinders(k.winders));
+        self.stack.extend(entries);
+        self.stack.extend(exits.into_iter().rev());
+        Ok(State::Return(SExpr::Unspecified))
+    }
+}
+
+fn is_let(sym: &str) -> bool {
+    matches!(sym, "let" | "let*" | "letrec" | "letrec*" | "let-values" | "let*-values")
+}
+
+/// Splits a `(name init)` binding of a `let`. The name can also be a
+/// parameter list, and it always is one for `let-values`.
+fn binding(x: SExpr, values: bool) -> SResult<(Target, SExpr)> {
+    let mut binding = x.into_list()?;
+    if binding.len() != 2 {
+        bail!(UnexpectedForm => SExpr::List(binding))
+    }
+
+    let init = binding.pop().unwrap();
+    let target = match binding.pop().unwrap() {
+        SExpr::Atom(Token::Symbol(x)) if !values => Target::Variable(x),
+        x => Target::Values(Param::parse(x)?)
     };
+    Ok((target, init))
+}
+
+fn flatten(list: SExpr) -> SExprs {
+    match list {
+        SExpr::DottedList(xs, sexpr) => {
+            let mut ys = xs;
+            match *sexpr {
+                SExpr::List(mut xs) => ys.append(&mut xs),
+                dl@SExpr::DottedList(_,_) => ys.append(&mut flatten(dl)),
+                x => ys.push(x)
+            };
+            ys
+        },
+        SExpr::List(xs) => xs,
+        x => vec![x]
+    }
 }
 
 #[derive(Debug)]
@@ -145,7 +1206,10 @@ pub enum Extra {
 pub struct Args {
     pub env: EnvRef,
     pub extra: Extra,
-    vec: SExprs
+    vec: SExprs,
+    /// Arguments of procedures are evaluated by the evaluator, only special
+    /// forms get the expressions as they are.
+    evaluated: bool
 }
 
 impl Deref for Args {
@@ -164,11 +1228,15 @@ impl DerefMut for Args {
 
 impl Args {
     pub fn new_with_extra(vec: SExprs, extra: Extra, env: &EnvRef) -> Args {
-        Args { env: env.clone_ref(), extra, vec }
+        Args { env: env.clone_ref(), extra, vec, evaluated: false }
     }
 
     pub fn new(vec: SExprs, env: &EnvRef) -> Args {
-        Args { env: env.clone_ref(), extra: Extra::Nothing, vec }
+        Args { env: env.clone_ref(), extra: Extra::Nothing, vec, evaluated: false }
+    }
+
+    pub fn evaluated(vec: SExprs, env: &EnvRef) -> Args {
+        Args { env: env.clone_ref(), extra: Extra::Nothing, vec, evaluated: true }
     }
 
     pub fn env(&self) -> EnvRef {
@@ -180,13 +1248,26 @@ impl Args {
     }
 
     pub fn eval(&self) -> SResult<SExprs> {
+        if self.evaluated {
+            return Ok(self.vec.clone())
+        }
+
         self.vec.iter()
-            .map(|x| eval(&x, &self.env))
+            .map(|x| eval(x, &self.env))
             .collect::<SResult<_>>()
     }
 
     pub fn evaled(self) -> SResult<Args> {
-        Ok(Args::new_with_extra(self.eval()?, self.extra, &self.env))
+        if self.evaluated {
+            return Ok(self)
+        }
+
+        Ok(Args {
+            vec: self.eval()?,
+            env: self.env,
+            extra: self.extra,
+            evaluated: true
+        })
     }
 
     pub fn own_one(self) -> SResult<SExpr> {
@@ -246,14 +1327,120 @@ impl Args {
     }
 }
 
-// FIXME: does unneccesarry cloning when called, refactor with into_iter
-pub trait ToArgs {
-    fn to_args(&self, env: &EnvRef) -> Args;
-}
+#[cfg(test)]
+mod tests {
+    use env::{Env, EnvRef};
+    use lexer::TokenIterator;
+    use parser::{parse, SExpr};
+    use expander::expand;
+    use primitives;
+    use serr::SResult;
+
+    /// Evaluates `code` in a fresh environment and returns the value of
+    /// its last expression.
+    fn run(code: &str) -> SResult<SExpr> {
+        let env = Env::with_values(EnvRef::null(), primitives::env()).into_ref();
+        primitives::load_prelude(&env)?;
+        let mut result = SExpr::Unspecified;
+        for sexpr in parse(TokenIterator::new(code.chars()))? {
+            result = expand(sexpr)?.eval(&env)?;
+        }
+        Ok(result)
+    }
+
+    fn error_of(code: &str) -> String {
+        match run(code) {
+            Ok(x) => panic!("Expected an error, got: {}", x),
+            Err(e) => e.to_string()
+        }
+    }
+
+    #[test]
+    fn values_as_a_list_element() {
+        let error = error_of("(list (values 1 2))");
+        assert!(error.ends_with("Expected a single value, found 2 values: 1 2"), "{}", error);
+    }
+
+    #[test]
+    fn values_as_an_operand() {
+        let error = error_of("(+ 1 (values 2 3))");
+        assert!(error.ends_with("Expected a single value, found 2 values: 2 3"), "{}", error);
+    }
+
+    #[test]
+    fn values_reach_their_consumer() {
+        let result = run("(call-with-values (lambda () (values 1 2)) list)").unwrap();
+        assert_eq!(result.to_string(), "(1 2)");
+    }
 
+    #[test]
+    fn map_can_be_reentered() {
+        let result = run("
+            (let ((k #f) (results '()))
+              (set! results (cons (map (lambda (x) (if (= x 2) (call/cc (lambda (c) (set! k c) x)) x))
+                                       '(1 2 3))
+                                  results))
+              (if (= (length results) 1) (k 20))
+              results)").unwrap();
+        assert_eq!(result.to_string(), "((1 20 3) (1 2 3))");
+    }
+
+    #[test]
+    fn for_each_generator() {
+        let result = run("
+            (define (make-generator lst)
+              (define return #f)
+              (define resume #f)
+              (lambda ()
+                (call/cc (lambda (r)
+                  (set! return r)
+                  (if resume
+                      (resume #f)
+                      (begin
+                        (for-each (lambda (x) (call/cc (lambda (next) (set! resume next) (return x))))
+                                  lst)
+                        (set! resume (lambda (_) (return 'done)))
+                        (return 'done)))))))
+            (let ((next (make-generator '(a b c))))
+              (list (next) (next) (next) (next)))").unwrap();
+        assert_eq!(result.to_string(), "(a b c done)");
+    }
+
+    #[test]
+    fn handler_returns_from_raise() {
+        let result = run("
+            (guard (e ((error-object? e) (cons (error-object-message e) (error-object-irritants e))))
+              (with-exception-handler
+                (lambda (c) 42)
+                (lambda () (+ (raise 'c) 1))))").unwrap();
+        assert_eq!(result.to_string(), "(\"handler returned from non-continuable raise\" c)");
+    }
+
+    #[test]
+    fn forms_are_expanded_before_they_run() {
+        let error = error_of("(raise 'first) (let ((x)) x)");
+        assert!(error.contains("first"), "{}", error);
+    }
+
+    #[test]
+    fn macro_defined_by_a_macro() {
+        let result = run("
+            (define-syntax define-getter
+              (syntax-rules ()
+                ((_ name val) (define-syntax name
+                                (syntax-rules () ((_) (let ((tmp val)) tmp)))))))
+            (define-getter get 5)
+            (define tmp 10)
+            (list (get) tmp)").unwrap();
+        assert_eq!(result.to_string(), "(5 10)");
+    }
 
-impl ToArgs for [SExpr] {
-    fn to_args(&self, env: &EnvRef) -> Args {
-        Args::new(self.to_vec(), &env)
+    #[test]
+    fn nan_hash_table_key() {
+        let result = run("
+            (define table (make-hash-table))
+            (hash-table-set! table (list +nan.0) 1)
+            (hash-table-ref/default table (list +nan.0) #f)").unwrap();
+        assert_eq!(result.to_string(), "1");
     }
 }
@@ -1,8 +1,1168 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use lexer::Token;
 use parser::SExpr;
-use serr::{SResult};
+use parser::SExprs;
+use serr::{SErr, SResult};
+
+/// Names that the expander treats as syntactic keywords when they are not
+/// shadowed by a local binding or a macro.
+const KEYWORDS: &[&str] = &[
+    "quote", "quasiquote", "unquote", "unquote-splicing",
+    "lambda", "λ", "case-lambda", "define", "set!", "if", "begin",
+    "let", "let*", "letrec", "letrec*", "cond", "case", "and", "or",
+    "when", "unless", "do", "let-values", "let*-values", "receive",
+    "define-values", "delay", "delay-force", "parameterize",
+    "define-syntax", "let-syntax", "letrec-syntax", "syntax-rules",
+    "else", "=>"
+];
+
+type ScopeRef = Rc<Scope>;
+
+/// What an identifier means at expansion time.
+#[derive(Clone)]
+enum Binding {
+    /// A local variable, renamed to a unique name.
+    Variable(String),
+    Macro(Rc<Macro>),
+}
+
+/// A lexical scope seen by the expander. The root scope holds global
+/// macros, global variables are not recorded at all.
+struct Scope {
+    parent: Option<ScopeRef>,
+    bindings: RefCell<HashMap<String, Binding>>,
+}
+
+impl Scope {
+    fn new(parent: &ScopeRef) -> ScopeRef {
+        Rc::new(Scope {
+            parent: Some(Rc::clone(parent)),
+            bindings: RefCell::new(HashMap::new()),
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        match self.bindings.borrow().get(name) {
+            Some(binding) => Some(binding.clone()),
+            None => self.parent.as_ref().and_then(|p| p.lookup(name))
+        }
+    }
+
+    fn bind(&self, name: &str, binding: Binding) {
+        self.bindings.borrow_mut().insert(name.to_string(), binding);
+    }
+
+    fn is_global(&self) -> bool {
+        self.parent.is_none()
+    }
+}
+
+/// Result of resolving an identifier in a scope.
+#[derive(Clone)]
+enum Resolved {
+    Variable(String),
+    Macro(Rc<Macro>),
+    Keyword(String),
+}
+
+impl PartialEq for Resolved {
+    fn eq(&self, other: &Resolved) -> bool {
+        match (self, other) {
+            (Resolved::Variable(x), Resolved::Variable(y)) => x == y,
+            (Resolved::Keyword(x), Resolved::Keyword(y)) => x == y,
+            (Resolved::Macro(x), Resolved::Macro(y)) => Rc::ptr_eq(x, y),
+            _ => false
+        }
+    }
+}
+
+/// An identifier inserted by a macro template. It remembers the symbol
+/// written in the template and the scope where the macro was defined, so
+/// that it can neither capture nor be captured by user bindings.
+#[derive(Clone)]
+struct Alias {
+    name: String,
+    scope: ScopeRef,
+}
+
+thread_local! {
+    static GLOBAL: ScopeRef = Rc::new(Scope {
+        parent: None,
+        bindings: RefCell::new(HashMap::new()),
+    });
+    static ALIASES: RefCell<HashMap<String, Alias>> = RefCell::new(HashMap::new());
+    static COUNTER: Cell<usize> = const { Cell::new(0) };
+}
 
+fn global_scope() -> ScopeRef {
+    GLOBAL.with(Rc::clone)
+}
+
+/// Generates a name that can't clash with anything the user writes.
+fn fresh_name(base: &str) -> String {
+    COUNTER.with(|c| {
+        c.set(c.get() + 1);
+        format!("{}%{}", base_name(base), c.get())
+    })
+}
+
+fn new_alias(name: &str, scope: &ScopeRef) -> String {
+    let alias = fresh_name(name);
+    ALIASES.with(|a| a.borrow_mut().insert(alias.clone(), Alias {
+        name: name.to_string(),
+        scope: Rc::clone(scope)
+    }));
+    alias
+}
+
+fn unalias(name: &str) -> Option<(String, ScopeRef)> {
+    ALIASES.with(|a| {
+        a.borrow()
+            .get(name)
+            .map(|alias| (alias.name.clone(), Rc::clone(&alias.scope)))
+    })
+}
+
+/// Strips the renaming suffix from a name produced by the expander.
+/// Useful for showing a local variable's name to the user.
+pub fn base_name(name: &str) -> &str {
+    match name.rfind('%') {
+        Some(i) if i > 0 && name[i+1..].chars().all(|c| c.is_ascii_digit())
+            && i + 1 < name.len() => &name[..i],
+        _ => name
+    }
+}
+
+fn resolve(name: &str, scope: &ScopeRef) -> Resolved {
+    if let Some(binding) = scope.lookup(name) {
+        return match binding {
+            Binding::Variable(x) => Resolved::Variable(x),
+            Binding::Macro(m) => Resolved::Macro(m)
+        }
+    }
+
+    if let Some((original, def_scope)) = unalias(name) {
+        return resolve(&original, &def_scope)
+    }
+
+    if KEYWORDS.contains(&name) {
+        Resolved::Keyword(name.to_string())
+    } else {
+        Resolved::Variable(name.to_string())
+    }
+}
+
+/// Resolves the head of a form, if it's an identifier.
+fn resolve_head(sexpr: &SExpr, scope: &ScopeRef) -> Option<Resolved> {
+    match sexpr.unlocated() {
+        SExpr::List(xs) if !xs.is_empty() => match *xs[0].unlocated() {
+            SExpr::Atom(Token::Symbol(ref x)) => Some(resolve(x, scope)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Converts an expression back to plain data by replacing every alias with
+/// the symbol it was created from. Used for quoted data.
+fn strip(sexpr: &SExpr) -> SExpr {
+    match sexpr {
+        SExpr::Atom(Token::Symbol(x)) => {
+            let mut name = x.clone();
+            while let Some((original, _)) = unalias(&name) {
+                name = original;
+            }
+            ssymbol!(name)
+        },
+        SExpr::List(xs) => SExpr::List(xs.iter().map(strip).collect()),
+        SExpr::DottedList(xs, y) => SExpr::dottedlist(xs.iter().map(strip).collect(), strip(y)),
+        SExpr::Vector(xs) => SExpr::vector_from(xs.borrow().iter().map(strip).collect()),
+        SExpr::Located(_, x) => strip(x),
+        x => x.clone()
+    }
+}
+
+/// Finds the aliases that appear in an expression, along with the ones
+/// they were created from.
+fn collect_aliases(sexpr: &SExpr, aliases: &mut HashMap<String, Alias>) {
+    match sexpr {
+        SExpr::Atom(Token::Symbol(x)) => {
+            let mut name = x.clone();
+            while let Some(alias) = ALIASES.with(|a| a.borrow().get(&name).cloned()) {
+                let original = alias.name.clone();
+                aliases.insert(name, alias);
+                name = original;
+            }
+        },
+        SExpr::List(xs) => xs.iter().for_each(|x| collect_aliases(x, aliases)),
+        SExpr::DottedList(xs, y) => {
+            xs.iter().for_each(|x| collect_aliases(x, aliases));
+            collect_aliases(y, aliases);
+        },
+        SExpr::Vector(xs) => xs.borrow().iter().for_each(|x| collect_aliases(x, aliases)),
+        SExpr::Located(_, x) => collect_aliases(x, aliases),
+        _ => ()
+    }
+}
+
+/// Splits a list into its elements and its final cdr, flattening nested
+/// dotted lists along the way.
+fn split_list(sexpr: &SExpr) -> Option<(SExprs, SExpr)> {
+    match sexpr.unlocated() {
+        SExpr::List(xs) => Some((xs.clone(), slist![])),
+        SExpr::DottedList(xs, y) => {
+            let mut elems = xs.clone();
+            match split_list(y) {
+                Some((mut ys, tail)) => {
+                    elems.append(&mut ys);
+                    Some((elems, tail))
+                },
+                None => Some((elems, y.unlocated().clone()))
+            }
+        },
+        _ => None
+    }
+}
+
+fn join_list(mut xs: SExprs, tail: SExpr) -> SExpr {
+    match tail {
+        SExpr::List(mut ys) => {
+            xs.append(&mut ys);
+            SExpr::List(xs)
+        },
+        SExpr::DottedList(mut ys, y) => {
+            xs.append(&mut ys);
+            SExpr::DottedList(xs, y)
+        },
+        y => if xs.is_empty() { y } else { SExpr::dottedlist(xs, y) }
+    }
+}
+
+fn symbol_name(sexpr: &SExpr) -> Option<&String> {
+    match sexpr.unlocated() {
+        SExpr::Atom(Token::Symbol(x)) => Some(x),
+        _ => None
+    }
+}
+
+/// Expands all macros in a toplevel form. Macros defined with
+/// `define-syntax` at toplevel stay visible to the forms that follow.
 pub fn expand(sexpr: SExpr) -> SResult<SExpr> {
-    // TODO: after implementing hygienic macros, expand them here
-    // TODO: (begin) -> #<unspecified>
-    Ok(sexpr)
+    let expanded = expand_expr(&sexpr, &global_scope());
+
+    // The aliases are only needed while the form is expanded, a global
+    // macro keeps the ones that its own rules refer to.
+    ALIASES.with(|a| a.borrow_mut().clear());
+    expanded
+}
+
+fn expand_expr(sexpr: &SExpr, scope: &ScopeRef) -> SResult<SExpr> {
+    match sexpr {
+        SExpr::Located(span, x) => {
+            let expanded = expand_expr(x, scope)
+                .map_err(|e| e.located(Some(span)))?;
+
+            // Only the variables and the forms keep their location, that's
+            // where the evaluator can fail.
+            match expanded {
+                SExpr::Atom(Token::Symbol(ref x)) if KEYWORDS.contains(&x.as_str()) => Ok(expanded),
+                x@SExpr::Atom(Token::Symbol(_))
+                    | x@SExpr::List(_)
+                    | x@SExpr::DottedList(_, _) => Ok(SExpr::Located(span.clone(), Box::new(x))),
+                x => Ok(x)
+            }
+        },
+        SExpr::Atom(Token::Symbol(x)) => match resolve(x, scope) {
+            Resolved::Variable(name) => Ok(ssymbol!(name)),
+            Resolved::Keyword(name) => Ok(ssymbol!(name)),
+            Resolved::Macro(_) => bail!("Syntax keyword used as a variable: {}", base_name(x))
+        },
+        SExpr::List(xs) if !xs.is_empty() => {
+            match resolve_head(sexpr, scope) {
+                Some(Resolved::Macro(m)) => {
+                    let expanded = m.transcribe(sexpr, scope)?;
+                    expand_expr(&expanded, scope)
+                },
+                Some(Resolved::Keyword(k)) => expand_special(&k, xs, scope),
+                _ => Ok(SExpr::List(expand_all(xs, scope)?))
+            }
+        },
+        SExpr::DottedList(xs, y) => {
+            Ok(SExpr::dottedlist(expand_all(xs, scope)?, expand_expr(y, scope)?))
+        },
+        // Vectors evaluate to themselves.
+        x@SExpr::Vector(_) => Ok(strip(x).into_data()),
+        x => Ok(x.clone())
+    }
+}
+
+fn expand_all(xs: &[SExpr], scope: &ScopeRef) -> SResult<SExprs> {
+    xs.iter()
+        .map(|x| expand_expr(x, scope))
+        .collect()
+}
+
+fn expand_special(keyword: &str, xs: &[SExpr], scope: &ScopeRef) -> SResult<SExpr> {
+    let form = SExpr::List(xs.to_vec());
+    let head = ssymbol!(keyword);
+
+    match keyword {
+        "quote" => {
+            if xs.len() != 2 { bail!(UnexpectedForm => form) }
+            Ok(slist![head, strip(&xs[1]).into_data()])
+        },
+        "quasiquote" => {
+            if xs.len() != 2 { bail!(UnexpectedForm => form) }
+            Ok(slist![head, expand_quasi(&xs[1], 1, scope)?])
+        },
+        "lambda" | "λ" => {
+            if xs.len() < 3 { bail!(UnexpectedForm => form) }
+            let mut result = vec![head];
+            result.append(&mut expand_lambda(&xs[1], &xs[2..], scope)?);
+            Ok(SExpr::List(result))
+        },
+        "case-lambda" => {
+            let mut result = vec![head];
+            for clause in &xs[1..] {
+                let clause = clause.clone().into_list()?;
+                if clause.len() < 2 { bail!(UnexpectedForm => SExpr::List(clause)) }
+                result.push(SExpr::List(expand_lambda(&clause[0], &clause[1..], scope)?));
+            }
+            Ok(SExpr::List(result))
+        },
+        "define" => expand_define(xs, scope, scope.is_global()),
+        "set!" => {
+            if xs.len() != 3 { bail!(UnexpectedForm => form) }
+            Ok(slist![head, expand_expr(&xs[1], scope)?, expand_expr(&xs[2], scope)?])
+        },
+        "if" | "and" | "or" | "when" | "unless" | "delay" | "delay-force" => {
+            let mut result = vec![head];
+            result.append(&mut expand_all(&xs[1..], scope)?);
+            Ok(SExpr::List(result))
+        },
+        "begin" => {
+            if xs.len() == 1 {
+                return Ok(SExpr::Unspecified)
+            }
+
+            // Forms inside a toplevel begin are toplevel forms, so expand
+            // them one by one to make definitions visible to later forms.
+            let mut result = vec![head];
+            for x in &xs[1..] {
+                result.push(expand_expr(x, scope)?);
+            }
+            Ok(SExpr::List(result))
+        },
+        "let" if xs.len() > 1 && symbol_name(&xs[1]).is_some() => expand_named_let(xs, scope),
+        "let" | "let*" | "letrec" | "letrec*" | "let-values" | "let*-values" => {
+            expand_let(keyword, xs, scope)
+        },
+        "receive" => {
+            // (receive formals expr body...) is (let-values ((formals expr)) body...)
+            if xs.len() < 4 { bail!(UnexpectedForm => form) }
+            let mut let_values = vec![ssymbol!("let-values"), slist![slist![xs[1].clone(), xs[2].clone()]]];
+            let_values.extend_from_slice(&xs[3..]);
+            expand_let("let-values", &let_values, scope)
+        },
+        "define-values" => {
+            if xs.len() != 3 { bail!(UnexpectedForm => form) }
+            let formals = if scope.is_global() { strip(&xs[1]) } else { expand_expr(&xs[1], scope)?.strip_locations() };
+            Ok(slist![head, formals, expand_expr(&xs[2], scope)?])
+        },
+        "do" => expand_do(xs, scope),
+        "parameterize" => {
+            if xs.len() < 3 { bail!(UnexpectedForm => form) }
+            let bindings = let_bindings(&xs[1])?
+                .iter()
+                .map(|(p, v)| Ok(slist![expand_expr(p, scope)?, expand_expr(v, scope)?]))
+                .collect::<SResult<SExprs>>()?;
+            let mut result = vec![head, SExpr::List(bindings)];
+            result.append(&mut expand_body(&xs[2..], &Scope::new(scope))?);
+            Ok(SExpr::List(result))
+        },
+        "cond" => {
+            let mut result = vec![head];
+            for clause in &xs[1..] {
+                result.push(expand_clause(clause, scope, false)?);
+            }
+            Ok(SExpr::List(result))
+        },
+        "case" => {
+            if xs.len() < 2 { bail!(UnexpectedForm => form) }
+            let mut result = vec![head, expand_expr(&xs[1], scope)?];
+            for clause in &xs[2..] {
+                result.push(expand_clause(clause, scope, true)?);
+            }
+            Ok(SExpr::List(result))
+        },
+        "define-syntax" => {
+            if xs.len() != 3 { bail!(UnexpectedForm => form) }
+            let name = symbol_name(&xs[1])
+                .ok_or_else(|| SErr::new_id_not_found(&xs[1].to_string()))?;
+            let transformer = Macro::new(&xs[2], scope)?;
+            scope.bind(name, Binding::Macro(Rc::new(transformer)));
+            Ok(SExpr::Unspecified)
+        },
+        "let-syntax" | "letrec-syntax" => {
+            if xs.len() < 3 { bail!(UnexpectedForm => form) }
+            let bindings = xs[1].clone().into_list()?;
+            let inner = Scope::new(scope);
+            let def_scope = if keyword == "let-syntax" { scope } else { &inner };
+
+            // Parse all transformers first, so that a let-syntax
+            // transformer can't see its siblings.
+            let mut macros = vec![];
+            for binding in bindings {
+                let binding = binding.into_list()?;
+                if binding.len() != 2 { bail!(UnexpectedForm => SExpr::List(binding)) }
+                let name = symbol_name(&binding[0])
+                    .ok_or_else(|| SErr::new_id_not_found(&binding[0].to_string()))?
+                    .clone();
+                macros.push((name, Macro::new(&binding[1], def_scope)?));
+            }
+
+            for (name, transformer) in macros {
+                inner.bind(&name, Binding::Macro(Rc::new(transformer)));
+            }
+
+            let mut result = vec![ssymbol!("let"), slist![]];
+            result.append(&mut expand_body(&xs[2..], &inner)?);
+            Ok(SExpr::List(result))
+        },
+        "syntax-rules" => bail!("`syntax-rules` used outside of a macro definition"),
+        _ => bail!("Invalid use of syntax keyword: {}", form)
+    }
+}
+
+/// Binds a parameter list in the given scope and returns the renamed list.
+fn bind_params(params: &SExpr, scope: &ScopeRef) -> SResult<SExpr> {
+    let bind = |x: &SExpr| -> SResult<SExpr> {
+        let name = symbol_name(x)
+            .ok_or_else(|| SErr::TypeMismatch("identifier".to_string(), x.clone()))?;
+        let renamed = fresh_name(name);
+        scope.bind(name, Binding::Variable(renamed.clone()));
+        Ok(ssymbol!(renamed))
+    };
+
+    match params.unlocated() {
+        SExpr::Atom(Token::Symbol(_)) => bind(params),
+        _ => {
+            let (xs, tail) = split_list(params)
+                .ok_or_else(|| SErr::TypeMismatch("parameter list".to_string(), params.clone()))?;
+            let names = xs.iter()
+                .map(&bind)
+                .collect::<SResult<SExprs>>()?;
+            let rest = if tail.is_proper_list() { tail } else { bind(&tail)? };
+            Ok(join_list(names, rest))
+        }
+    }
+}
+
+/// Expands a definition. Toplevel definitions keep their names, the rest
+/// are already bound in `scope` by `expand_body`.
+fn expand_define(xs: &[SExpr], scope: &ScopeRef, toplevel: bool) -> SResult<SExpr> {
+    let form = SExpr::List(xs.to_vec());
+    if xs.len() < 2 { bail!(UnexpectedForm => form) }
+
+    let define_name = |x: &SExpr| -> SResult<SExpr> {
+        let name = symbol_name(x)
+            .ok_or_else(|| SErr::new_id_not_found(&x.to_string()))?;
+        if toplevel {
+            // A toplevel definition shadows a global macro of the same name.
+            let name = strip(x).into_symbol()?;
+            scope.bindings.borrow_mut().remove(&name);
+            Ok(ssymbol!(name))
+        } else {
+            expand_expr(&ssymbol!(name.clone()), scope)
+        }
+    };
+
+    match *xs[1].unlocated() {
+        SExpr::Atom(Token::Symbol(_)) => {
+            let name = define_name(&xs[1])?;
+            let mut result = vec![ssymbol!("define"), name];
+            result.append(&mut expand_all(&xs[2..], scope)?);
+            Ok(SExpr::List(result))
+        },
+        SExpr::List(_) | SExpr::DottedList(_, _) => {
+            if xs.len() < 3 { bail!(UnexpectedForm => form) }
+            let (header, tail) = split_list(&xs[1]).unwrap();
+            let name = header.first()
+                .ok_or_else(|| SErr::new_id_not_found("nothing"))?;
+            let name = define_name(name)?;
+
+            let inner = Scope::new(scope);
+            let params = bind_params(&join_list(header[1..].to_vec(), tail), &inner)?;
+            let mut result = vec![ssymbol!("define"), join_list(vec![name], params)];
+            result.append(&mut expand_body(&xs[2..], &inner)?);
+            Ok(SExpr::List(result))
+        },
+        ref x => Err(SErr::new_id_not_found(&x.to_string()))
+    }
+}
+
+/// Expands the parameters and the body of a lambda, they are bound in a
+/// new scope.
+fn expand_lambda(params: &SExpr, body: &[SExpr], scope: &ScopeRef) -> SResult<SExprs> {
+    let inner = Scope::new(scope);
+    let mut result = vec![bind_params(params, &inner)?];
+    result.append(&mut expand_body(body, &inner)?);
+    Ok(result)
+}
+
+/// Splits the `(name init)` bindings of a `let`.
+fn let_bindings(bindings: &SExpr) -> SResult<Vec<(SExpr, SExpr)>> {
+    bindings.clone().into_list()?
+        .into_iter()
+        .map(|x| {
+            let binding = x.into_list()?;
+            if binding.len() != 2 { bail!(UnexpectedForm => SExpr::List(binding)) }
+            Ok((binding[0].clone(), binding[1].clone()))
+        })
+        .collect()
+}
+
+fn expand_let(keyword: &str, xs: &[SExpr], scope: &ScopeRef) -> SResult<SExpr> {
+    let form = SExpr::List(xs.to_vec());
+    if xs.len() < 3 { bail!(UnexpectedForm => form) }
+
+    let bindings = let_bindings(&xs[1])?;
+
+    let mut current = Rc::clone(scope);
+    let mut new_bindings = vec![];
+    match keyword {
+        "let" | "let-values" => {
+            let inner = Scope::new(scope);
+            for (name, init) in bindings {
+                let init = expand_expr(&init, scope)?;
+                new_bindings.push(slist![bind_params(&name, &inner)?, init]);
+            }
+            current = inner;
+        },
+        "let*" | "let*-values" => {
+            for (name, init) in bindings {
+                let init = expand_expr(&init, &current)?;
+                current = Scope::new(&current);
+                new_bindings.push(slist![bind_params(&name, &current)?, init]);
+            }
+        },
+        _ => {
+            current = Scope::new(scope);
+            let names = bindings.iter()
+                .map(|(name, _)| bind_params(name, &current))
+                .collect::<SResult<SExprs>>()?;
+            for (name, (_, init)) in names.into_iter().zip(bindings) {
+                new_bindings.push(slist![name, expand_expr(&init, &current)?]);
+            }
+        }
+    }
+
+    let mut result = vec![ssymbol!(keyword), SExpr::List(new_bindings)];
+    result.append(&mut expand_body(&xs[2..], &current)?);
+    Ok(SExpr::List(result))
+}
+
+/// `(let name ((var init) ...) body...)` calls a local procedure `name`
+/// with the values of the inits, the body can loop by calling `name`.
+fn expand_named_let(xs: &[SExpr], scope: &ScopeRef) -> SResult<SExpr> {
+    if xs.len() < 4 { bail!(UnexpectedForm => SExpr::List(xs.to_vec())) }
+
+    let bindings = let_bindings(&xs[2])?;
+    let inits = bindings.iter()
+        .map(|(_, init)| expand_expr(init, scope))
+        .collect::<SResult<SExprs>>()?;
+
+    let inner = Scope::new(scope);
+    let name = bind_params(&xs[1], &inner)?;
+    let params = SExpr::List(bindings.into_iter().map(|(name, _)| name).collect());
+    let lambda = expand_lambda(&params, &xs[3..], &inner)?;
+    Ok(loop_call(name, lambda, inits))
+}
+
+/// `(do ((var init step) ...) (test result...) command...)` runs the
+/// commands and updates the variables with their steps until `test` is true.
+fn expand_do(xs: &[SExpr], scope: &ScopeRef) -> SResult<SExpr> {
+    let form = SExpr::List(xs.to_vec());
+    if xs.len() < 3 { bail!(UnexpectedForm => form) }
+
+    let mut params = vec![];
+    let mut inits = vec![];
+    let mut steps = vec![];
+    for spec in xs[1].clone().into_list()? {
+        let spec = spec.into_list()?;
+        if spec.len() != 2 && spec.len() != 3 { bail!(UnexpectedForm => SExpr::List(spec)) }
+        inits.push(expand_expr(&spec[1], scope)?);
+        steps.push(spec.get(2).unwrap_or(&spec[0]).clone());
+        params.push(spec[0].clone());
+    }
+
+    let inner = Scope::new(scope);
+    let params = bind_params(&SExpr::List(params), &inner)?;
+    let test = xs[2].clone().into_list()?;
+    if test.is_empty() { bail!(UnexpectedForm => form) }
+
+    let result = if test.len() == 1 {
+        SExpr::Unspecified
+    } else {
+        let mut result = vec![ssymbol!("begin")];
+        result.append(&mut expand_all(&test[1..], &inner)?);
+        SExpr::List(result)
+    };
+
+    // The loop variable is never visible to user code, so it only needs a
+    // fresh name.
+    let name = ssymbol!(fresh_name("do-loop"));
+    let mut next = vec![name.clone()];
+    next.append(&mut expand_all(&steps, &inner)?);
+    let mut commands = vec![ssymbol!("begin")];
+    commands.append(&mut expand_all(&xs[3..], &inner)?);
+    commands.push(SExpr::List(next));
+
+    let body = slist![ssymbol!("if"), expand_expr(&test[0], &inner)?, result, SExpr::List(commands)];
+    Ok(loop_call(name, vec![params, body], inits))
+}
+
+/// Builds `((letrec ((name (lambda . lambda))) name) inits...)`.
+fn loop_call(name: SExpr, lambda: SExprs, inits: SExprs) -> SExpr {
+    let mut procedure = vec![ssymbol!("lambda")];
+    procedure.extend(lambda);
+    let letrec = slist![
+        ssymbol!("letrec"),
+        slist![slist![name.clone(), SExpr::List(procedure)]],
+        name
+    ];
+
+    let mut result = vec![letrec];
+    result.extend(inits);
+    SExpr::List(result)
+}
+
+/// Expands a `cond` or `case` clause.
+fn expand_clause(clause: &SExpr, scope: &ScopeRef, is_case: bool) -> SResult<SExpr> {
+    let xs = clause.clone().into_list()?;
+    if xs.is_empty() { bail!(UnexpectedForm => clause) }
+
+    let is_keyword = |x: &SExpr, k: &str| match x.unlocated() {
+        SExpr::Atom(Token::Symbol(s)) => resolve(s, scope) == Resolved::Keyword(k.to_string()),
+        _ => false
+    };
+
+    let mut result = vec![];
+    let rest = if is_keyword(&xs[0], "else") {
+        result.push(ssymbol!("else"));
+        &xs[1..]
+    } else if is_case {
+        result.push(strip(&xs[0]));
+        &xs[1..]
+    } else {
+        &xs[..]
+    };
+
+    for x in rest {
+        if is_keyword(x, "=>") {
+            result.push(ssymbol!("=>"));
+        } else {
+            result.push(expand_expr(x, scope)?);
+        }
+    }
+
+    Ok(SExpr::List(result))
+}
+
+fn expand_quasi(sexpr: &SExpr, level: usize, scope: &ScopeRef) -> SResult<SExpr> {
+    match sexpr.unlocated() {
+        SExpr::List(xs) if !xs.is_empty() => {
+            let keyword = match *xs[0].unlocated() {
+                SExpr::Atom(Token::Symbol(ref x)) if xs.len() == 2 => match resolve(x, scope) {
+                    Resolved::Keyword(k) => Some(k),
+                    _ => None
+                },
+                _ => None
+            };
+
+            match keyword.as_deref() {
+                Some("unquote") | Some("unquote-splicing") if level == 1 => {
+                    Ok(slist![ssymbol!(keyword.unwrap()), expand_expr(&xs[1], scope)?])
+                },
+                Some("unquote") | Some("unquote-splicing") => {
+                    Ok(slist![ssymbol!(keyword.unwrap()), expand_quasi(&xs[1], level - 1, scope)?])
+                },
+                Some("quasiquote") => {
+                    Ok(slist![ssymbol!("quasiquote"), expand_quasi(&xs[1], level + 1, scope)?])
+                },
+                _ => {
+                    let result = xs.iter()
+                        .map(|x| expand_quasi(x, level, scope))
+                        .collect::<SResult<_>>()?;
+                    Ok(SExpr::List(result))
+                }
+            }
+        },
+        SExpr::DottedList(xs, y) => {
+            let result = xs.iter()
+                .map(|x| expand_quasi(x, level, scope))
+                .collect::<SResult<_>>()?;
+            Ok(SExpr::dottedlist(result, expand_quasi(y, level, scope)?))
+        },
+        x => Ok(strip(x))
+    }
+}
+
+/// A form of a body after the definitions are scanned.
+enum BodyForm {
+    Define(SExpr),
+    /// The renamed formals and the init.
+    DefineValues(SExpr, SExpr),
+    Expr(SExpr)
+}
+
+/// Expands a body. Internal definitions (including the ones produced by
+/// macros or spliced from `begin`s) are bound before anything is expanded,
+/// so that the body can refer to them regardless of their order.
+fn expand_body(body: &[SExpr], scope: &ScopeRef) -> SResult<SExprs> {
+    let mut queue = body.iter().cloned().rev().collect::<SExprs>();
+    let mut forms = vec![];
+
+    while let Some(mut form) = queue.pop() {
+        loop {
+            match resolve_head(&form, scope) {
+                Some(Resolved::Macro(m)) => {
+                    form = m.transcribe(&form, scope)?;
+                    continue;
+                },
+                Some(Resolved::Keyword(ref k)) if k == "begin" => {
+                    let xs = form.clone().into_list()?;
+                    queue.extend(xs.into_iter().skip(1).rev());
+                },
+                Some(Resolved::Keyword(ref k)) if k == "define-syntax" => {
+                    expand_expr(&form, scope)?;
+                },
+                Some(Resolved::Keyword(ref k)) if k == "define" => {
+                    let xs = form.clone().into_list()?;
+                    let target = xs.get(1)
+                        .ok_or_else(|| SErr::new_id_not_found("nothing"))?;
+                    let name = match split_list(target) {
+                        Some((header, _)) => header.first().cloned(),
+                        None => Some(target.clone())
+                    };
+                    let name = name.as_ref()
+                        .and_then(symbol_name)
+                        .ok_or_else(|| SErr::new_id_not_found(&target.to_string()))?;
+                    scope.bind(name, Binding::Variable(fresh_name(name)));
+                    forms.push(BodyForm::Define(form));
+                },
+                Some(Resolved::Keyword(ref k)) if k == "define-values" => {
+                    let mut xs = form.clone().into_list()?;
+                    if xs.len() != 3 { bail!(UnexpectedForm => form) }
+                    let init = xs.pop().unwrap();
+                    forms.push(BodyForm::DefineValues(bind_params(&xs[1], scope)?, init));
+                },
+                _ => forms.push(BodyForm::Expr(form))
+            }
+            break;
+        }
+    }
+
+    if forms.is_empty() {
+        bail!("Expected at least one expression in body")
+    }
+
+    // Internal definitions become a `letrec*` around the rest of the body.
+    // Expressions that come before a definition are bound to dummy
+    // variables so everything is still evaluated in order.
+    let mut bindings = vec![];
+    let mut exprs = vec![];
+    for form in forms {
+        let binding = match form {
+            BodyForm::Expr(x) => {
+                exprs.push(expand_expr(&x, scope)?);
+                continue;
+            },
+            BodyForm::Define(x) => expand_internal_define(&x.into_list()?, scope)?,
+            BodyForm::DefineValues(formals, init) => {
+                let init = expand_expr(&init, scope)?;
+                match formals {
+                    // A single name takes all the values as a list, but a
+                    // symbol binds just one value in a `letrec*`.
+                    SExpr::Atom(_) => {
+                        let tmp = ssymbol!(fresh_name("values"));
+                        let all = slist![ssymbol!("let-values"), slist![slist![tmp.clone(), init]], tmp];
+                        slist![formals, all]
+                    },
+                    _ => slist![formals, init]
+                }
+            }
+        };
+
+        for x in exprs.drain(..) {
+            bindings.push(slist![ssymbol!(fresh_name("_")), x]);
+        }
+        bindings.push(binding);
+    }
+
+    if bindings.is_empty() {
+        return Ok(exprs)
+    }
+    if exprs.is_empty() {
+        exprs.push(SExpr::Unspecified);
+    }
+
+    let mut result = vec![ssymbol!("letrec*"), SExpr::List(bindings)];
+    result.append(&mut exprs);
+    Ok(vec![SExpr::List(result)])
+}
+
+/// Expands an internal definition into a `(name init)` binding of a
+/// `letrec*`, `expand_body` has already bound the name.
+fn expand_internal_define(xs: &[SExpr], scope: &ScopeRef) -> SResult<SExpr> {
+    let mut define = expand_define(xs, scope, false)?
+        .into_list()?
+        .into_iter()
+        .skip(1);
+    let target = define.next().unwrap();
+
+    match split_list(&target) {
+        Some((header, params)) => {
+            let mut lambda = vec![ssymbol!("lambda"), join_list(header[1..].to_vec(), params)];
+            lambda.extend(define);
+            Ok(slist![header[0].clone(), SExpr::List(lambda)])
+        },
+        None => Ok(slist![target, define.next().unwrap_or(SExpr::Unspecified)])
+    }
+}
+
+//
+// syntax-rules
+//
+
+/// A `syntax-rules` transformer.
+struct Macro {
+    ellipsis: Option<String>,
+    literals: Vec<String>,
+    rules: Vec<(SExpr, SExpr)>,
+    scope: ScopeRef,
+    /// Aliases in the rules, when the macro was defined by another macro.
+    aliases: HashMap<String, Alias>,
+}
+
+/// Pattern variable bindings. Variables under an ellipsis are bound to a
+/// sequence of matches, one for each repetition.
+#[derive(Debug, Clone)]
+enum Match {
+    One(SExpr),
+    Many(Vec<Match>),
+}
+
+type Matches = HashMap<String, Match>;
+
+impl Macro {
+    fn new(spec: &SExpr, scope: &ScopeRef) -> SResult<Macro> {
+        let spec = &spec.clone().strip_locations();
+        let xs = spec.clone().into_list()?;
+        let is_syntax_rules = match xs.first() {
+            Some(SExpr::Atom(Token::Symbol(x))) => {
+                resolve(x, scope) == Resolved::Keyword("syntax-rules".to_string())
+            },
+            _ => false
+        };
+
+        if !is_syntax_rules || xs.len() < 2 {
+            bail!("Expected a `syntax-rules` transformer, found: {}", spec)
+        }
+
+        // (syntax-rules <ellipsis> (<literal> ...) <rule> ...)
+        let (ellipsis, rest) = match xs[1] {
+            SExpr::Atom(Token::Symbol(ref x)) => (Some(x.clone()), &xs[2..]),
+            _ => (None, &xs[1..])
+        };
+
+        let literals = rest.first()
+            .ok_or_else(|| SErr::new_unexpected_form(spec))?
+            .clone()
+            .into_list()?
+            .into_iter()
+            .map(|x| x.into_symbol())
+            .collect::<SResult<_>>()?;
+
+        let rules = rest[1..].iter()
+            .map(|rule| {
+                let rule = rule.clone().into_list()?;
+                if rule.len() != 2 { bail!(UnexpectedForm => SExpr::List(rule)) }
+                Ok((rule[0].clone(), rule[1].clone()))
+            })
+            .collect::<SResult<_>>()?;
+
+        let mut aliases = HashMap::new();
+        collect_aliases(spec, &mut aliases);
+
+        Ok(Macro { ellipsis, literals, rules, scope: Rc::clone(scope), aliases })
+    }
+
+    fn is_ellipsis(&self, x: &SExpr) -> bool {
+        match (&self.ellipsis, x) {
+            (Some(e), SExpr::Atom(Token::Symbol(x))) => e == x,
+            (None, SExpr::Atom(Token::Ellipsis)) => true,
+            _ => false
+        }
+    }
+
+    /// Rewrites a macro use according to the first matching rule.
+    fn transcribe(&self, form: &SExpr, use_scope: &ScopeRef) -> SResult<SExpr> {
+        if !self.aliases.is_empty() {
+            ALIASES.with(|a| a.borrow_mut().extend(self.aliases.clone()));
+        }
+
+        let (_, form_tail) = split_list(form).unwrap();
+        let form_args = {
+            let (xs, _) = split_list(form).unwrap();
+            join_list(xs[1..].to_vec(), form_tail)
+        };
+
+        for (pattern, template) in &self.rules {
+            // The keyword position of the pattern is ignored.
+            let pattern_args = match split_list(pattern) {
+                Some((ref xs, ref tail)) if !xs.is_empty() => join_list(xs[1..].to_vec(), tail.clone()),
+                _ => bail!("Invalid syntax-rules pattern: {}", pattern)
+            };
+
+            let mut matches = Matches::new();
+            if self.matches(&pattern_args, &form_args, use_scope, &mut matches)? {
+                let mut renames = HashMap::new();
+                return self.instantiate(template, &matches, &mut renames, false)
+            }
+        }
+
+        bail!("Invalid syntax, no rule matches: {}", strip(form))
+    }
+
+    fn matches(&self, pattern: &SExpr, form: &SExpr, use_scope: &ScopeRef,
+               matches: &mut Matches) -> SResult<bool> {
+        match pattern {
+            SExpr::Atom(Token::Symbol(x)) => {
+                if self.literals.contains(x) {
+                    Ok(match form.unlocated() {
+                        SExpr::Atom(Token::Symbol(y)) => {
+                            resolve(x, &self.scope) == resolve(y, use_scope)
+                        },
+                        _ => false
+                    })
+                } else {
+                    if x != "_" {
+                        matches.insert(x.clone(), Match::One(form.clone()));
+                    }
+                    Ok(true)
+                }
+            },
+            SExpr::List(_) | SExpr::DottedList(_, _) => {
+                let (pats, pat_tail) = split_list(pattern).unwrap();
+                let (elems, tail) = match split_list(form) {
+                    Some(x) => x,
+                    None => (vec![], form.clone())
+                };
+
+                match pats.iter().position(|x| self.is_ellipsis(x)) {
+                    Some(i) => {
+                        if i == 0 { bail!("Ellipsis without a preceding pattern: {}", pattern) }
+                        let before = &pats[..i-1];
+                        let repeated = &pats[i-1];
+                        let after = &pats[i+1..];
+                        if elems.len() < before.len() + after.len() {
+                            return Ok(false)
+                        }
+                        if pat_tail.is_proper_list() && !tail.is_proper_list() {
+                            return Ok(false)
+                        }
+
+                        for (p, x) in before.iter().zip(elems.iter()) {
+                            if !self.matches(p, x, use_scope, matches)? {
+                                return Ok(false)
+                            }
+                        }
+
+                        let count = elems.len() - before.len() - after.len();
+                        let mut repetitions = vec![];
+                        for x in &elems[before.len()..before.len() + count] {
+                            let mut m = Matches::new();
+                            if !self.matches(repeated, x, use_scope, &mut m)? {
+                                return Ok(false)
+                            }
+                            repetitions.push(m);
+                        }
+
+                        for var in self.pattern_vars(repeated) {
+                            let seq = repetitions.iter_mut()
+                                .map(|m| m.remove(&var).unwrap())
+                                .collect();
+                            matches.insert(var, Match::Many(seq));
+                        }
+
+                        for (p, x) in after.iter().zip(elems[before.len() + count..].iter()) {
+                            if !self.matches(p, x, use_scope, matches)? {
+                                return Ok(false)
+                            }
+                        }
+
+                        self.matches(&pat_tail, &tail, use_scope, matches)
+                    },
+                    None => {
+                        if pat_tail.is_proper_list() {
+                            if elems.len() != pats.len() || !tail.is_proper_list() {
+                                return Ok(false)
+                            }
+                        } else if elems.len() < pats.len() {
+                            return Ok(false)
+                        }
+
+                        for (p, x) in pats.iter().zip(elems.iter()) {
+                            if !self.matches(p, x, use_scope, matches)? {
+                                return Ok(false)
+                            }
+                        }
+
+                        if pat_tail.is_proper_list() {
+                            Ok(true)
+                        } else {
+                            let rest = join_list(elems[pats.len()..].to_vec(), tail);
+                            self.matches(&pat_tail, &rest, use_scope, matches)
+                        }
+                    }
+                }
+            },
+            // Vectors match like lists, element by element.
+            SExpr::Vector(pats) => match form.unlocated() {
+                SExpr::Vector(elems) => {
+                    let pats = SExpr::List(pats.borrow().clone());
+                    let elems = SExpr::List(elems.borrow().clone());
+                    self.matches(&pats, &elems, use_scope, matches)
+                },
+                _ => Ok(false)
+            },
+            x => Ok(x == form.unlocated())
+        }
+    }
+
+    fn pattern_vars(&self, pattern: &SExpr) -> Vec<String> {
+        match pattern {
+            SExpr::Atom(Token::Symbol(x)) => {
+                if x == "_" || self.literals.contains(x) || self.is_ellipsis(pattern) {
+                    vec![]
+                } else {
+                    vec![x.clone()]
+                }
+            },
+            SExpr::List(_) | SExpr::DottedList(_, _) => {
+                let (mut xs, tail) = split_list(pattern).unwrap();
+                if !tail.is_proper_list() {
+                    xs.push(tail);
+                }
+                xs.iter()
+                    .flat_map(|x| self.pattern_vars(x))
+                    .collect()
+            },
+            SExpr::Vector(xs) => {
+                xs.borrow()
+                    .iter()
+                    .flat_map(|x| self.pattern_vars(x))
+                    .collect()
+            },
+            _ => vec![]
+        }
+    }
+
+    fn instantiate(&self, template: &SExpr, matches: &Matches,
+                   renames: &mut HashMap<String, String>, escaped: bool) -> SResult<SExpr> {
+        match template {
+            SExpr::Atom(Token::Symbol(x)) => match matches.get(x) {
+                Some(Match::One(y)) => Ok(y.clone()),
+                Some(Match::Many(_)) => bail!("Pattern variable used without an ellipsis: {}", x),
+                None => {
+                    let scope = &self.scope;
+                    let alias = renames.entry(x.clone())
+                        .or_insert_with(|| new_alias(x, scope));
+                    Ok(ssymbol!(alias.clone()))
+                }
+            },
+            SExpr::List(_) | SExpr::DottedList(_, _) => {
+                let (xs, tail) = split_list(template).unwrap();
+
+                // (... <template>) escapes the ellipsis
+                if !escaped && xs.len() == 2 && tail.is_proper_list() && self.is_ellipsis(&xs[0]) {
+                    return self.instantiate(&xs[1], matches, renames, true)
+                }
+
+                let mut result = vec![];
+                let mut i = 0;
+                while i < xs.len() {
+                    let mut depth = 0;
+                    while !escaped && i + depth + 1 < xs.len() && self.is_ellipsis(&xs[i + depth + 1]) {
+                        depth += 1;
+                    }
+
+                    if depth == 0 {
+                        result.push(self.instantiate(&xs[i], matches, renames, escaped)?);
+                    } else {
+                        result.append(&mut self.instantiate_ellipsis(&xs[i], depth, matches, renames)?);
+                    }
+                    i += depth + 1;
+                }
+
+                if tail.is_proper_list() {
+                    Ok(SExpr::List(result))
+                } else {
+                    let tail = self.instantiate(&tail, matches, renames, escaped)?;
+                    Ok(join_list(result, tail))
+                }
+            },
+            SExpr::Vector(xs) => {
+                let xs = SExpr::List(xs.borrow().clone());
+                let result = self.instantiate(&xs, matches, renames, escaped)?;
+                Ok(SExpr::vector_from(result.into_list()?))
+            },
+            x => Ok(x.clone())
+        }
+    }
+
+    fn instantiate_ellipsis(&self, template: &SExpr, depth: usize, matches: &Matches,
+                            renames: &mut HashMap<String, String>) -> SResult<SExprs> {
+        let vars = self.pattern_vars(template)
+            .into_iter()
+            .filter(|x| matches!(matches.get(x), Some(Match::Many(_))))
+            .collect::<Vec<_>>();
+
+        if vars.is_empty() {
+            bail!("No pattern variables before ellipsis in template: {}", template)
+        }
+
+        let len = vars.iter()
+            .map(|x| match matches[x] {
+                Match::Many(ref xs) => xs.len(),
+                _ => 0
+            })
+            .max()
+            .unwrap_or(0);
+
+        let mut result = vec![];
+        for i in 0..len {
+            let mut current = matches.clone();
+            for var in &vars {
+                if let Match::Many(ref xs) = matches[var] {
+                    match xs.get(i) {
+                        Some(x) => current.insert(var.clone(), x.clone()),
+                        None => bail!("Mismatched ellipsis depths for: {}", var)
+                    };
+                }
+            }
+
+            if depth > 1 {
+                result.append(&mut self.instantiate_ellipsis(template, depth - 1, &current, renames)?);
+            } else {
+                result.push(self.instantiate(template, &current, renames, false)?);
+            }
+        }
+
+        Ok(result)
+    }
 }
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use env::EnvRef;
+use evaluator;
+use lexer::Token;
+use parser::{SExpr, SExprs};
+use primitives::equivalence::{eqv, equal};
+use serr::SResult;
+use utils::{new_rc_ref_cell, RcRefCell};
+
+/// How the keys of a hash table are compared.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Equivalence {
+    /// `eq?` and `eqv?`, which are the same thing here.
+    Eqv,
+    /// `equal?`, and `string=?` which is `equal?` for strings.
+    Equal,
+    /// An equality procedure and a hash procedure for it. Without a hash
+    /// procedure all the keys end up in the same bucket.
+    Custom(SExpr, Option<SExpr>),
+}
+
+/// A mutable hash table. The keys are hashed by `Equivalence` and the ones
+/// with the same hash share a bucket.
+#[derive(Clone)]
+pub struct HashTableData(RcRefCell<HashTable>);
+
+#[derive(Clone)]
+struct HashTable {
+    equivalence: Equivalence,
+    buckets: HashMap<u64, Vec<(SExpr, SExpr)>>,
+    count: usize,
+}
+
+/// Objects that are `eqv?` are hashed the same. Objects that are compared by
+/// identity are hashed by their address.
+pub fn hash_eqv(x: &SExpr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_atom_or_identity(x, &mut hasher);
+    hasher.finish()
+}
+
+/// Objects that are `equal?` are hashed the same. Only the first few
+/// elements of long or circular structures are looked at.
+pub fn hash_equal(x: &SExpr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let mut budget = 64;
+    hash_structure(x, &mut hasher, &mut budget);
+    hasher.finish()
+}
+
+fn hash_atom_or_identity(x: &SExpr, hasher: &mut DefaultHasher) {
+    match x {
+        SExpr::Atom(Token::Integer(i)) => i.hash(hasher),
+        SExpr::Atom(Token::Str(s)) => s.borrow().hash(hasher),
+        SExpr::Atom(Token::Symbol(s)) => s.hash(hasher),
+        SExpr::Atom(t) => t.to_string().hash(hasher),
+        SExpr::Pair(x) => x.id().hash(hasher),
+        SExpr::Vector(x) => (&**x as *const _ as usize).hash(hasher),
+        SExpr::Bytevector(x) => (&**x as *const _ as usize).hash(hasher),
+        // The rest goes into one bucket per kind.
+        x => std::mem::discriminant(x).hash(hasher)
+    }
+}
+
+fn hash_structure(x: &SExpr, hasher: &mut DefaultHasher, budget: &mut usize) {
+    if *budget == 0 {
+        return
+    }
+    *budget -= 1;
+
+    match x {
+        SExpr::Pair(pair) => {
+            let mut current = pair.clone();
+            loop {
+                hash_structure(&current.car(), hasher, budget);
+                match current.cdr() {
+                    SExpr::Pair(next) if *budget > 0 => {
+                        *budget -= 1;
+                        current = next;
+                    },
+                    SExpr::Pair(_) => break,
+                    tail => {
+                        hash_structure(&tail, hasher, budget);
+                        break
+                    }
+                }
+            }
+        },
+        SExpr::Vector(xs) => {
+            for x in xs.borrow().iter() {
+                hash_structure(x, hasher, budget);
+            }
+        },
+        SExpr::Bytevector(xs) => xs.borrow().hash(hasher),
+        x => hash_atom_or_identity(x, hasher)
+    }
+}
+
+impl HashTableData {
+    pub fn new(equivalence: Equivalence) -> HashTableData {
+        HashTableData(new_rc_ref_cell(HashTable {
+            equivalence,
+            buckets: HashMap::new(),
+            count: 0
+        }))
+    }
+
+    pub fn equivalence(&self) -> Equivalence {
+        self.0.borrow().equivalence.clone()
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.borrow().count
+    }
+
+    fn hash(&self, key: &SExpr, env: &EnvRef) -> SResult<u64> {
+        match self.equivalence() {
+            Equivalence::Eqv => Ok(hash_eqv(key)),
+            Equivalence::Equal => Ok(hash_equal(key)),
+            Equivalence::Custom(_, None) => Ok(0),
+            Equivalence::Custom(_, Some(hash)) => {
+                let x = evaluator::apply(&hash, vec![key.clone()], env)?;
+                Ok(x.as_int()? as u64)
+            }
+        }
+    }
+
+    /// Finds the bucket of `key` and its position in it. The table isn't
+    /// borrowed while a custom equality procedure runs, so it can look at
+    /// the table too.
+    fn find(&self, key: &SExpr, env: &EnvRef) -> SResult<(u64, Option<usize>)> {
+        let hash = self.hash(key, env)?;
+        let same = {
+            let table = self.0.borrow();
+            let bucket = match table.buckets.get(&hash) {
+                Some(bucket) => bucket,
+                None => return Ok((hash, None))
+            };
+
+            match table.equivalence {
+                Equivalence::Eqv => return Ok((hash, bucket.iter().position(|(k, _)| eqv(k, key)))),
+                Equivalence::Equal => return Ok((hash, bucket.iter().position(|(k, _)| equal(k, key)))),
+                Equivalence::Custom(ref same, _) => same.clone()
+            }
+        };
+
+        let keys: SExprs = self.0.borrow().buckets[&hash].iter().map(|(k, _)| k.clone()).collect();
+        for (i, k) in keys.into_iter().enumerate() {
+            if evaluator::apply(&same, vec![k, key.clone()], env)?.to_bool() {
+                return Ok((hash, Some(i)))
+            }
+        }
+        Ok((hash, None))
+    }
+
+    pub fn get(&self, key: &SExpr, env: &EnvRef) -> SResult<Option<SExpr>> {
+        let (hash, position) = self.find(key, env)?;
+        Ok(position.map(|i| self.0.borrow().buckets[&hash][i].1.clone()))
+    }
+
+    pub fn set(&self, key: SExpr, value: SExpr, env: &EnvRef) -> SResult<()> {
+        let (hash, position) = self.find(&key, env)?;
+        let table = &mut *self.0.borrow_mut();
+        let bucket = table.buckets.entry(hash).or_default();
+        match position {
+            Some(i) => bucket[i].1 = value,
+            None => {
+                bucket.push((key, value));
+                table.count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the key was there.
+    pub fn delete(&self, key: &SExpr, env: &EnvRef) -> SResult<bool> {
+        let (hash, position) = self.find(key, env)?;
+        let i = match position {
+            Some(i) => i,
+            None => return Ok(false)
+        };
+
+        let table = &mut *self.0.borrow_mut();
+        let bucket = table.buckets.get_mut(&hash).unwrap();
+        bucket.swap_remove(i);
+        if bucket.is_empty() {
+            table.buckets.remove(&hash);
+        }
+        table.count -= 1;
+        Ok(true)
+    }
+
+    pub fn clear(&self) {
+        let table = &mut *self.0.borrow_mut();
+        table.buckets.clear();
+        table.count = 0;
+    }
+
+    /// The associations, in no particular order.
+    pub fn entries(&self) -> Vec<(SExpr, SExpr)> {
+        self.0.borrow().buckets
+            .values()
+            .flat_map(|bucket| bucket.iter().cloned())
+            .collect()
+    }
+
+    /// A new table with the same associations.
+    pub fn copy(&self) -> HashTableData {
+        HashTableData(new_rc_ref_cell(self.0.borrow().clone()))
+    }
+
+    pub fn ptr_eq(&self, other: &HashTableData) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for HashTableData {
+    fn eq(&self, other: &HashTableData) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl fmt::Debug for HashTableData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HashTableData({:#x})", &*self.0 as *const _ as usize)
+    }
+}
@@ -1,10 +1,16 @@
-use std::iter::Peekable;
 use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+use std::vec::IntoIter;
 use utils::{new_rc_ref_cell, RcRefCell};
+use serr::{SErr, SResult};
 
 use utils::GentleIterator;
 use utils::AndOr;
+use utils::bigint::BigInt;
 use utils::fraction::Fraction;
+use utils::complex::Complex;
 
 // TODO: string.parse::<Token>();
 
@@ -14,13 +20,19 @@ pub enum Token {
     RParen,
     Symbol(String),
     Integer(i64),
+    /// Only holds integers that don't fit in an `Integer`.
+    BigInt(BigInt),
     Fraction(Fraction),
     Float(f64),
+    /// Never holds a real number, see `Complex::make`.
+    Complex(Box<Complex>),
     Boolean(bool),
     Chr(char),
     Str(RcRefCell<String>),
     Dot,
     Ellipsis,
+    VectorOpener,
+    BytevectorOpener,
     Quote,
     QuasiQuote,
     UnQuote,
@@ -33,13 +45,22 @@ impl PartialOrd for Token {
         match (self, other) {
             (Integer(x), Integer(y)) => x.partial_cmp(y),
             (Float(x), Float(y)) => x.partial_cmp(y),
-            (Fraction(x), Fraction(y)) => x.partial_cmp(y),
-            (Integer(x), Float(y)) => (*x as f64).partial_cmp(y),
-            (Float(x), Integer(y)) => x.partial_cmp(&(*y as f64)),
-            (Integer(x), Fraction(y)) => (*x as f64).partial_cmp(&(*y).into()),
-            (Fraction(x), Integer(y)) => f64::from(*x).partial_cmp(&(*y as f64)),
-            (Float(x), Fraction(y)) => x.partial_cmp(&(*y).into()),
-            (Fraction(x), Float(y)) => f64::from(*x).partial_cmp(y),
+            // Complex numbers can only be equal, they have no order
+            (x, y) if x.is_number() && y.is_number() && !(x.is_real() && y.is_real()) => {
+                let re = x.real_part().partial_cmp(&y.real_part());
+                let im = x.imag_part().partial_cmp(&y.imag_part());
+                if re == Some(Ordering::Equal) && im == Some(Ordering::Equal) {
+                    Some(Ordering::Equal)
+                } else {
+                    None
+                }
+            },
+            (x, y) if x.is_number() && y.is_number() => {
+                match (x.to_fraction(), y.to_fraction()) {
+                    (Some(x), Some(y)) => x.partial_cmp(&y),
+                    _ => x.to_float()?.partial_cmp(&y.to_float()?)
+                }
+            },
 
             (Str(x), Str(y)) => x.partial_cmp(y),
             (Chr(x), Chr(y)) => x.partial_cmp(y),
@@ -51,7 +72,85 @@ impl PartialOrd for Token {
     }
 }
 
+impl From<BigInt> for Token {
+    fn from(x: BigInt) -> Token {
+        match x.to_i64() {
+            Some(x) => Token::Integer(x),
+            None => Token::BigInt(x)
+        }
+    }
+}
+
+impl From<Fraction> for Token {
+    fn from(x: Fraction) -> Token {
+        if x.is_int() {
+            Token::from(x.n)
+        } else {
+            Token::Fraction(x)
+        }
+    }
+}
+
 impl Token {
+    pub fn is_number(&self) -> bool {
+        self.is_real() || matches!(self, Token::Complex(_))
+    }
+
+    pub fn is_real(&self) -> bool {
+        matches!(self, Token::Integer(_) | Token::BigInt(_) | Token::Fraction(_) | Token::Float(_))
+    }
+
+    pub fn is_exact(&self) -> bool {
+        match self {
+            Token::Integer(_) | Token::BigInt(_) | Token::Fraction(_) => true,
+            Token::Complex(x) => x.re.is_exact(),
+            _ => false
+        }
+    }
+
+    /// Only meaningful for numbers, real numbers are their own real part.
+    pub fn real_part(&self) -> Token {
+        match self {
+            Token::Complex(x) => x.re.clone(),
+            x => x.clone()
+        }
+    }
+
+    /// Only meaningful for numbers, real numbers have an exact zero
+    /// imaginary part.
+    pub fn imag_part(&self) -> Token {
+        match self {
+            Token::Complex(x) => x.im.clone(),
+            _ => Token::Integer(0)
+        }
+    }
+
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Token::Integer(x) => Some(BigInt::from(*x)),
+            Token::BigInt(x) => Some(x.clone()),
+            _ => None
+        }
+    }
+
+    /// Converts any exact number to a fraction.
+    pub fn to_fraction(&self) -> Option<Fraction> {
+        match self {
+            Token::Fraction(x) => Some(x.clone()),
+            x => x.to_bigint().map(Fraction::from)
+        }
+    }
+
+    pub fn to_float(&self) -> Option<f64> {
+        match self {
+            Token::Float(x) => Some(*x),
+            Token::Integer(x) => Some(*x as f64),
+            Token::BigInt(x) => Some(x.to_f64()),
+            Token::Fraction(x) => Some(x.into()),
+            _ => None
+        }
+    }
+
     fn get(chr: char) -> Token {
         match chr {
             '(' | '['  => Token::LParen,
@@ -66,59 +165,233 @@ impl Token {
     }
 }
 
+/// Where a token starts. Lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: Option<Rc<String>>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{}:{}:{}", file, self.line, self.column),
+            None => write!(f, "line {}, column {}", self.line, self.column)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// Malformed syntax. The line it's on is kept to point at the problem.
+#[derive(Debug, Clone)]
+pub struct ReadErrorData {
+    pub message: String,
+    pub span: Span,
+    pub line: String,
+}
+
+impl fmt::Display for ReadErrorData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Tabs are kept so that the caret lines up with the text above it.
+        let padding: String = self.line.chars()
+            .take(self.span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{}: {}\n{}\n{}^", self.span, self.message, self.line, padding)
+    }
+}
+
+/// The characters that are being tokenized. Keeps track of the position
+/// and of the lines read so far, for error messages.
+pub struct Source<I: Iterator<Item=char>> {
+    inner: I,
+    peeked: VecDeque<char>,
+    file: Option<Rc<String>>,
+    line: usize,
+    column: usize,
+    lines: Vec<String>,
+}
+
+impl<I: Iterator<Item=char>> Source<I> {
+    fn new(inner: I, file: Option<Rc<String>>) -> Self {
+        Source { inner, peeked: VecDeque::new(), file, line: 1, column: 1, lines: vec![String::new()] }
+    }
+
+    /// Looks `n` characters ahead without consuming anything.
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        while self.peeked.len() <= n {
+            let c = self.inner.next()?;
+            self.peeked.push_back(c);
+        }
+        Some(self.peeked[n])
+    }
+
+    pub fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn span(&self) -> Span {
+        Span { file: self.file.clone(), line: self.line, column: self.column }
+    }
+
+    /// The text of `line`. The current line is read up to its end, without
+    /// consuming it.
+    fn line_text(&mut self, line: usize) -> String {
+        let mut text = self.lines.get(line - 1).cloned().unwrap_or_default();
+        if line == self.line {
+            let mut i = 0;
+            while let Some(c) = self.peek_nth(i) {
+                if c == '\n' {
+                    break
+                }
+                text.push(c);
+                i += 1;
+            }
+        }
+        text.trim_end_matches('\r').to_string()
+    }
+
+    fn error<T>(&mut self, span: &Span, message: String) -> SResult<T> {
+        let line = self.line_text(span.line);
+        Err(SErr::ReadError(Box::new(ReadErrorData { message, span: span.clone(), line })))
+    }
+}
+
+impl<I: Iterator<Item=char>> Iterator for Source<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = match self.peeked.pop_front() {
+            Some(c) => c,
+            None => self.inner.next()?
+        };
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+            self.lines.push(String::new());
+        } else {
+            self.column += 1;
+            self.lines.last_mut().unwrap().push(c);
+        }
+        Some(c)
+    }
+}
+
+impl<I: Iterator<Item=char>> GentleIterator<I> for Source<I> {
+    fn take_until<F>(&mut self, predicate: F) -> IntoIter<char>
+        where F: Fn(&char) -> bool {
+
+        let mut v = vec![];
+        while self.peek().is_some_and(|c| predicate(&c)) {
+            v.push(self.next().unwrap());
+        }
+
+        v.into_iter()
+    }
+}
+
 pub struct TokenIterator<I: Iterator<Item=char>> {
-    inner: Peekable<I>
+    source: Source<I>,
+    fold_case: bool,
+    peeked: Option<SpannedToken>
 }
 
 impl<I: Iterator<Item=char>> TokenIterator<I> {
     pub fn new(inner: I) -> Self {
         TokenIterator {
-            inner: inner.peekable()
+            source: Source::new(inner, None),
+            fold_case: false,
+            peeked: None
+        }
+    }
+
+    /// The spans of the tokens name `file`.
+    pub fn with_file(inner: I, file: &str) -> Self {
+        TokenIterator {
+            source: Source::new(inner, Some(Rc::new(file.to_string()))),
+            fold_case: false,
+            peeked: None
         }
     }
+
+    /// The characters that were looked at but not consumed, like the
+    /// delimiter after a symbol or the rest of a line with an error.
+    pub fn lookahead(&self) -> String {
+        self.source.peeked.iter().collect()
+    }
+
+    /// The next token, without consuming it.
+    pub fn peek(&mut self) -> SResult<Option<&SpannedToken>> {
+        if self.peeked.is_none() {
+            self.peeked = tokenize_single(&mut self.source, &mut self.fold_case)?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    /// Whether the next token is `token`.
+    pub fn peek_is(&mut self, token: &Token) -> SResult<bool> {
+        Ok(self.peek()?.is_some_and(|x| x.token == *token))
+    }
+
+    /// A read error about the token at `span`, which should be on the
+    /// current line or on one before it.
+    pub fn error<T>(&mut self, span: &Span, message: String) -> SResult<T> {
+        self.source.error(span, message)
+    }
 }
 
 impl<I: Iterator<Item=char>> Iterator for TokenIterator<I> {
-    type Item = Token;
+    type Item = SResult<SpannedToken>;
 
-    fn next(&mut self) -> Option<Token> {
-        tokenize_single(&mut self.inner)
+    fn next(&mut self) -> Option<SResult<SpannedToken>> {
+        match self.peeked.take() {
+            Some(x) => Some(Ok(x)),
+            None => tokenize_single(&mut self.source, &mut self.fold_case).transpose()
+        }
     }
 }
 
-pub fn tokenize_single<I>(iter: &mut Peekable<I>) -> Option<Token>
+/// Reads the next token. `fold_case` is set by the `#!fold-case` and
+/// `#!no-fold-case` directives, symbols and character names are read in
+/// lower case while it's set.
+fn tokenize_single<I>(iter: &mut Source<I>, fold_case: &mut bool) -> SResult<Option<SpannedToken>>
 where I: Iterator<Item = char> {
-    while parse_whitespace(iter) || parse_comment(iter) {
+    while parse_whitespace(iter)
+        || parse_comment(iter)
+        || parse_block_comment(iter)?
+        || parse_datum_comment(iter, fold_case)?
+        || parse_directive(iter, fold_case)? {
         continue
     }
 
-    parse_lparen(iter)
-        .or_else(|| parse_quote(iter))
-        .or_else(|| parse_unquote(iter))
-        .or_else(|| parse_quasiquote(iter))
-        .or_else(|| parse_rparen(iter))
+    let span = iter.span();
+    let token = parse_lparen(iter).map(Ok)
+        .or_else(|| parse_quote(iter).map(Ok))
+        .or_else(|| parse_unquote(iter).map(Ok))
+        .or_else(|| parse_quasiquote(iter).map(Ok))
+        .or_else(|| parse_rparen(iter).map(Ok))
         .or_else(|| parse_string(iter))
-        .or_else(|| parse_hash(iter))
-        .or_else(|| parse_symbol(iter))
-}
+        .or_else(|| parse_hash(iter, *fold_case))
+        .or_else(|| parse_bar_symbol(iter))
+        .or_else(|| parse_symbol(iter, *fold_case).map(Ok))
+        .transpose()?;
 
-pub fn tokenize<I>(iter: &mut Peekable<I>) -> Vec<Token>
-where I: Iterator<Item = char> {
-    let mut tokens: Vec<Token> = vec![];
-
-    while let Some(x) = tokenize_single(iter) {
-        tokens.push(x)
-    }
-
-    tokens
+    Ok(token.map(|token| SpannedToken { token, span }))
 }
 
 //
 // Parsers
 //
-fn parse_whitespace<I>(iter: &mut Peekable<I>) -> bool
+fn parse_whitespace<I>(iter: &mut Source<I>) -> bool
 where I: Iterator<Item = char> {
-    if check_chr(iter, ' ') || check_chr(iter, '\n') {
+    if check(iter, char::is_whitespace) {
         iter.next();
         true
     } else {
@@ -126,7 +399,7 @@ where I: Iterator<Item = char> {
     }
 }
 
-fn parse_comment<I>(iter: &mut Peekable<I>) -> bool
+fn parse_comment<I>(iter: &mut Source<I>) -> bool
 where I: Iterator<Item = char> {
     if check_chr(iter, ';') {
         iter.take_until(|c| *c != '\n');
@@ -136,81 +409,307 @@ where I: Iterator<Item = char> {
     }
 }
 
-fn parse_quote<I>(iter: &mut Peekable<I>) -> Option<Token>
+/// Skips a `#| ... |#` comment. Block comments can be nested.
+fn parse_block_comment<I>(iter: &mut Source<I>) -> SResult<bool>
+where I: Iterator<Item = char> {
+    if !check_two(iter, '#', '|') {
+        return Ok(false)
+    }
+
+    let span = iter.span();
+    iter.next();
+    iter.next();
+    let mut depth = 1;
+    while depth > 0 {
+        match iter.next() {
+            Some('|') if check_chr(iter, '#') => {
+                iter.next();
+                depth -= 1;
+            },
+            Some('#') if check_chr(iter, '|') => {
+                iter.next();
+                depth += 1;
+            },
+            Some(_) => (),
+            None => return iter.error(&span, "Expected the end of this block comment, got nothing.".to_string())
+        }
+    }
+    Ok(true)
+}
+
+/// Skips a `#;` comment and the datum that follows it.
+fn parse_datum_comment<I>(iter: &mut Source<I>, fold_case: &mut bool) -> SResult<bool>
+where I: Iterator<Item = char> {
+    if !check_two(iter, '#', ';') {
+        return Ok(false)
+    }
+
+    let span = iter.span();
+    iter.next();
+    iter.next();
+    let mut depth = 0;
+    loop {
+        let token = match tokenize_single(iter, fold_case)? {
+            Some(x) => x.token,
+            None => return iter.error(&span, "Expected a datum after #;, got nothing.".to_string())
+        };
+        match token {
+            Token::LParen | Token::VectorOpener | Token::BytevectorOpener => depth += 1,
+            Token::RParen if depth == 0 => return iter.error(&span, "Expected a datum after #;, got: )".to_string()),
+            Token::RParen => depth -= 1,
+            // These are followed by the rest of the datum
+            Token::Quote | Token::QuasiQuote | Token::UnQuote | Token::UnQuoteSplicing => continue,
+            _ => ()
+        }
+
+        if depth == 0 {
+            return Ok(true)
+        }
+    }
+}
+
+/// Reads the `#!fold-case` and `#!no-fold-case` directives.
+fn parse_directive<I>(iter: &mut Source<I>, fold_case: &mut bool) -> SResult<bool>
+where I: Iterator<Item = char> {
+    if !check_two(iter, '#', '!') {
+        return Ok(false)
+    }
+
+    let span = iter.span();
+    iter.next();
+    iter.next();
+    let directive: String = iter.take_until(|c| !is_delimiter(*c)).collect();
+    match directive.as_ref() {
+        "fold-case" => *fold_case = true,
+        "no-fold-case" => *fold_case = false,
+        x => return iter.error(&span, format!("Unknown directive: #!{}", x))
+    }
+    Ok(true)
+}
+
+fn parse_quote<I>(iter: &mut Source<I>) -> Option<Token>
 where I: Iterator<Item = char> {
     parse_single(iter, '\'')
 }
 
-fn parse_unquote<I>(iter: &mut Peekable<I>) -> Option<Token>
+fn parse_unquote<I>(iter: &mut Source<I>) -> Option<Token>
 where I: Iterator<Item = char> {
     parse_single(iter, ',')
         .and_or(parse_single(iter, '@'))
 }
 
-fn parse_quasiquote<I>(iter: &mut Peekable<I>) -> Option<Token>
+fn parse_quasiquote<I>(iter: &mut Source<I>) -> Option<Token>
 where I: Iterator<Item = char> {
     parse_single(iter, '`')
 }
 
-fn parse_lparen<I>(iter: &mut Peekable<I>) -> Option<Token>
+fn parse_lparen<I>(iter: &mut Source<I>) -> Option<Token>
 where I: Iterator<Item = char> {
     parse_single(iter, '(')
         .or_else(|| parse_single(iter, '['))
 }
 
-fn parse_rparen<I>(iter: &mut Peekable<I>) -> Option<Token>
+fn parse_rparen<I>(iter: &mut Source<I>) -> Option<Token>
 where I: Iterator<Item = char> {
     parse_single(iter, ')')
         .or_else(|| parse_single(iter, ']'))
 }
 
-fn parse_string<I>(iter: &mut Peekable<I>) -> Option<Token>
+fn parse_string<I>(iter: &mut Source<I>) -> Option<SResult<Token>>
 where I: Iterator<Item = char> {
-    // FIXME: check escape chars
     if !check_chr(iter, '"') {
         return None
     }
 
+    let span = iter.span();
     iter.next(); // Consume the opening "
-    let value = iter
-        .take_until(|c| *c != '"')
-        .collect();
-    iter.next(); // Consume the closing "
-    Some(Token::Str(new_rc_ref_cell(value)))
+    Some(parse_delimited(iter, '"', &span).map(|x| Token::Str(new_rc_ref_cell(x))))
+}
+
+/// Symbols written between bars, like `|hello world|`, can contain any
+/// character.
+fn parse_bar_symbol<I>(iter: &mut Source<I>) -> Option<SResult<Token>>
+where I: Iterator<Item = char> {
+    if !check_chr(iter, '|') {
+        return None
+    }
+
+    let span = iter.span();
+    iter.next(); // Consume the opening |
+    Some(parse_delimited(iter, '|', &span).map(Token::Symbol))
+}
+
+/// Reads the characters of a string or a `|symbol|` that starts at `span`
+/// up to the closing `terminator`, replacing the escapes with what they
+/// stand for.
+fn parse_delimited<I>(iter: &mut Source<I>, terminator: char, span: &Span) -> SResult<String>
+where I: Iterator<Item = char> {
+    let mut value = String::new();
+    loop {
+        let escape = iter.span();
+        match iter.next() {
+            Some(c) if c == terminator => return Ok(value),
+            Some('\\') => {
+                if let Some(c) = parse_escape(iter, &escape)? {
+                    value.push(c);
+                }
+            },
+            Some(c) => value.push(c),
+            None => return iter.error(span, format!("Expected a closing {}, got nothing.", terminator))
+        }
+    }
+}
+
+/// Reads what follows a backslash. A backslash at the end of a line joins
+/// it with the next one, the whitespace around the line break is skipped
+/// and nothing is returned.
+fn parse_escape<I>(iter: &mut Source<I>, span: &Span) -> SResult<Option<char>>
+where I: Iterator<Item = char> {
+    let is_intraline = |c: &char| *c == ' ' || *c == '\t' || *c == '\r';
+    match iter.next() {
+        Some('a') => Ok(Some('\x07')),
+        Some('b') => Ok(Some('\x08')),
+        Some('t') => Ok(Some('\t')),
+        Some('n') => Ok(Some('\n')),
+        Some('r') => Ok(Some('\r')),
+        Some('x') | Some('X') => {
+            let hex: String = iter.take_until(|c| c.is_ascii_hexdigit()).collect();
+            if !check_chr(iter, ';') {
+                return iter.error(span, format!("Expected a ; after \\x{}", hex))
+            }
+            iter.next();
+            match parse_hex_char(&hex) {
+                Some(c) => Ok(Some(c)),
+                None => iter.error(span, format!("Not a valid character code: \\x{};", hex))
+            }
+        },
+        Some(c) if c == '\n' || is_intraline(&c) => {
+            if c != '\n' {
+                iter.take_until(is_intraline);
+                if !check_chr(iter, '\n') {
+                    return iter.error(span, "Expected a line ending after \\".to_string())
+                }
+                iter.next();
+            }
+            iter.take_until(is_intraline);
+            Ok(None)
+        },
+        // \", \\ and \| stand for themselves
+        Some(c@'"') | Some(c@'\\') | Some(c@'|') => Ok(Some(c)),
+        Some(c) => iter.error(span, format!("Unknown escape sequence: \\{}", c)),
+        None => iter.error(span, "Expected an escaped character, got nothing.".to_string())
+    }
+}
+
+fn parse_hex_char(hex: &str) -> Option<char> {
+    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
 }
 
-fn parse_hash<I>(iter: &mut Peekable<I>) -> Option<Token>
+/// The characters that have a name, like `#\space`.
+pub const CHAR_NAMES: [(&str, char); 9] = [
+    ("alarm", '\x07'),
+    ("backspace", '\x08'),
+    ("delete", '\x7f'),
+    ("escape", '\x1b'),
+    ("newline", '\n'),
+    ("null", '\0'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+/// Reads the rest of a `#\` character that starts at `span`, which is a
+/// single character, the name of one or its code in hex like `#\x41`.
+fn parse_char<I>(iter: &mut Source<I>, fold_case: bool, span: &Span) -> SResult<char>
+where I: Iterator<Item = char> {
+    let first = match iter.next() {
+        Some(c) => c,
+        None => return iter.error(span, "Expected a char after #\\, got nothing.".to_string())
+    };
+    let rest: String = iter.take_until(|c| !is_delimiter(*c)).collect();
+    if rest.is_empty() {
+        return Ok(first)
+    }
+
+    let mut name = format!("{}{}", first, rest);
+    if fold_case {
+        name = name.to_lowercase();
+    }
+    let value = CHAR_NAMES.iter()
+        .find(|(x, _)| *x == name)
+        .map(|(_, c)| *c)
+        .or_else(|| if first == 'x' { parse_hex_char(&rest) } else { None });
+
+    match value {
+        Some(c) => Ok(c),
+        None => iter.error(span, format!("Unknown character: #\\{}", name))
+    }
+}
+
+fn parse_hash<I>(iter: &mut Source<I>, fold_case: bool) -> Option<SResult<Token>>
 where I: Iterator<Item = char> {
     if !check_chr(iter, '#') {
         return None
     }
 
+    let span = iter.span();
     iter.next(); // Consume #
-    match iter.next() {
-        Some('t') => Some(Token::Boolean(true)),  // #t means true
-        Some('f') => Some(Token::Boolean(false)), // #f means false
+    let token = match iter.next() {
+        Some(c@'t') | Some(c@'f') => {
+            // #t and #true mean true, #f and #false mean false
+            let name: String = iter
+                .take_until(|c| !is_delimiter(*c))
+                .collect();
+            match (c, name.as_str()) {
+                ('t', "") | ('t', "rue") => Ok(Token::Boolean(true)),
+                ('f', "") | ('f', "alse") => Ok(Token::Boolean(false)),
+                _ => iter.error(&span, format!("Expected #t, #true, #f or #false, got: #{}{}", c, name))
+            }
+        },
         Some('\\') => {
-            // #\a represents char 'a'
-            // #\b represents char 'b'
-            // ...
-            let value = iter.next()
-                .expect("Expected a char, got nothing.");
-            Some(Token::Chr(value))
+            // #\a represents char 'a', #\space represents ' ' and #\x41
+            // represents 'A'
+            parse_char(iter, fold_case, &span).map(Token::Chr)
         },
-        Some('(') => {
-            // Return Token::VectorOpener ?
-            panic!("Not yet implemented.")
-        }
-        Some(c) => {
-            panic!("Expected #t, #f, #(...) or #\\<char> got: #{}", c)
+        Some('(') => Ok(Token::VectorOpener),
+        Some('u') => {
+            // #u8( starts a bytevector
+            if iter.next() != Some('8') || iter.next() != Some('(') {
+                iter.error(&span, "Expected #u8(...)".to_string())
+            } else {
+                Ok(Token::BytevectorOpener)
+            }
+        },
+        Some(c@'e') | Some(c@'i') => {
+            // #e and #i set the exactness of the number that follows
+            let value: String = iter
+                .take_until(|c| !is_delimiter(*c))
+                .collect();
+            let number = if c == 'e' {
+                parse_number_with(&value, parse_exact_decimal)
+            } else {
+                parse_number_with(&value, parse_inexact)
+            };
+
+            match number {
+                Some(x) => Ok(x),
+                None => iter.error(&span, format!("Expected a number after #{}, got: {}", c, value))
+            }
+        },
+        Some(c) if !c.is_whitespace() => {
+            let rest: String = iter
+                .take_until(|c| !is_delimiter(*c))
+                .collect();
+            iter.error(&span, format!("Expected #t, #f, #e, #i, #(...), #u8(...) or #\\<char>, got: #{}{}", c, rest))
         },
-        None => {
-            panic!("Expected something , got nothing: ....")
+        _ => {
+            iter.error(&span, "Expected something after #, got nothing.".to_string())
         }
-    }
+    };
+    Some(token)
 }
 
-fn parse_symbol<I>(iter: &mut Peekable<I>) -> Option<Token>
+fn parse_symbol<I>(iter: &mut Source<I>, fold_case: bool) -> Option<Token>
 where I: Iterator<Item = char> {
     // Check if iter is empty or not
     if !check(iter, |_| true) {
@@ -218,17 +717,18 @@ where I: Iterator<Item = char> {
     }
 
     let value: String = iter
-        .take_until(|c| *c != ' ' && *c != ')' && *c != ']' && *c != '\n')
+        .take_until(|c| !is_delimiter(*c))
         .collect();
 
     parse_number(&value)
         .or_else(|| if value == "..." { Some(Token::Ellipsis) } else { None })
         .or_else(|| if value == "." { Some(Token::Dot) } else { None })
+        .or_else(|| if fold_case { Some(Token::Symbol(value.to_lowercase())) } else { None })
         .or_else(|| Some(Token::Symbol(value)))
 }
 
 /// Parse a single char and return the corresponding Token
-fn parse_single<I>(iter: &mut Peekable<I>, chr: char) -> Option<Token>
+fn parse_single<I>(iter: &mut Source<I>, chr: char) -> Option<Token>
 where I: Iterator<Item = char> {
     if !check_chr(iter, chr) {
         return None
@@ -239,29 +739,134 @@ where I: Iterator<Item = char> {
 }
 
 pub fn parse_number(value: &str) -> Option<Token> {
-    value.parse::<i64>().map(Token::Integer)
-        .or_else(|_| value.parse::<f64>().map(Token::Float))
-        .or_else(|_| value.parse::<Fraction>().map(|f| {
-            if f.is_int() { Token::Integer(f.n)}
-            else { Token::Fraction(f) }
-        }))
-        .ok()
+    parse_number_with(value, parse_real)
+}
+
+/// Parses a real number with `parse_real`, or a complex number whose parts
+/// are read with it.
+fn parse_number_with(value: &str, parse_real: fn(&str) -> Option<Token>) -> Option<Token> {
+    parse_real(value)
+        .or_else(|| parse_complex(value, parse_real))
+}
+
+fn parse_real(value: &str) -> Option<Token> {
+    match value {
+        "+inf.0" => return Some(Token::Float(f64::INFINITY)),
+        "-inf.0" => return Some(Token::Float(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => return Some(Token::Float(f64::NAN)),
+        _ => ()
+    }
+
+    value.parse::<i64>().map(Token::Integer).ok()
+        .or_else(|| parse_exact_number(value, 10))
+        .or_else(|| parse_float(value))
+}
+
+/// Rust also reads things like `inf` or `NaN` as floats, those should stay
+/// symbols.
+fn parse_float(value: &str) -> Option<Token> {
+    if value.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+        value.parse::<f64>().map(Token::Float).ok()
+    } else {
+        None
+    }
+}
+
+fn parse_inexact(value: &str) -> Option<Token> {
+    parse_real(value)?.to_float().map(Token::Float)
+}
+
+/// Parses complex numbers written as `a+bi`, `+bi` or `a@b`, where `a` and
+/// `b` are real numbers. `b` can be left out in the first two, `a+i` and
+/// `-i` are fine too.
+fn parse_complex(value: &str, parse_real: fn(&str) -> Option<Token>) -> Option<Token> {
+    if let Some(i) = value.find('@') {
+        let magnitude = parse_real(&value[..i])?;
+        let angle = parse_real(&value[i + 1..])?;
+        return Some(Complex::polar(magnitude, angle))
+    }
+
+    // The imaginary part starts at the last sign that isn't the sign of an
+    // exponent, like the one in `1e-3`.
+    let value = value.strip_suffix('i')?;
+    let (split, _) = value.char_indices()
+        .rev()
+        .find(|&(i, c)| (c == '+' || c == '-') && !value[..i].ends_with(['e', 'E']))?;
+
+    let (re, im) = value.split_at(split);
+    let re = if re.is_empty() { Token::Integer(0) } else { parse_real(re)? };
+    let im = match im {
+        "+" => Token::Integer(1),
+        "-" => Token::Integer(-1),
+        im => parse_real(im)?
+    };
+
+    Some(Complex::make(re, im))
+}
+
+/// Reads decimals like `1.25` or `1e-3` as the fraction they represent,
+/// without going through a float.
+fn parse_exact_decimal(value: &str) -> Option<Token> {
+    if let Some(x) = parse_exact_number(value, 10) {
+        return Some(x)
+    }
+
+    let (mantissa, exponent) = match value.find(['e', 'E']) {
+        Some(i) => (&value[..i], value[i + 1..].parse::<i32>().ok()?),
+        None => (value, 0)
+    };
+    let (int_part, frac_part) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, "")
+    };
+    if !frac_part.chars().all(|c| c.is_ascii_digit()) {
+        return None
+    }
+
+    let n = BigInt::from_str_radix(&format!("{}{}", int_part, frac_part), 10)?;
+    let exponent = exponent - frac_part.len() as i32;
+    let scale = BigInt::from(10).pow(exponent.unsigned_abs());
+    let result = if exponent >= 0 {
+        Fraction::from(n * scale)
+    } else {
+        Fraction::new(n, scale).ok()?
+    };
+
+    Some(Token::from(result))
+}
+
+/// Parses an integer or a fraction in the given radix.
+pub fn parse_exact_number(value: &str, radix: u32) -> Option<Token> {
+    BigInt::from_str_radix(value, radix).map(Token::from)
+        .or_else(|| Fraction::from_str_radix(value, radix).map(Token::from))
 }
 
 //
 // Helper functions
 //
-fn check<F,I>(iter: &mut Peekable<I>, fun: F) -> bool
+
+/// Characters that end a symbol or a character literal.
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()[]\";".contains(c)
+}
+
+fn check<F,I>(iter: &mut Source<I>, fun: F) -> bool
 where F: Fn(char) -> bool,
       I: Iterator<Item = char> {
-    if let Some(&x) = iter.peek() {
+    if let Some(x) = iter.peek() {
         fun(x)
     } else {
         false
     }
 }
 
-fn check_chr<I>(iter: &mut Peekable<I>, chr: char) -> bool
+fn check_chr<I>(iter: &mut Source<I>, chr: char) -> bool
 where I: Iterator<Item = char> {
     check(iter, |x| x == chr)
 }
+
+/// Whether the next two characters are `first` and `second`.
+fn check_two<I>(iter: &mut Source<I>, first: char, second: char) -> bool
+where I: Iterator<Item = char> {
+    check_chr(iter, first) && iter.peek_nth(1) == Some(second)
+}
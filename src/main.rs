@@ -9,6 +9,12 @@ mod parser;
 mod expander;
 mod port;
 mod procedure;
+mod condition;
+mod pair;
+mod promise;
+mod parameter;
+mod record;
+mod hashtable;
 mod evaluator;
 mod primitives;
 mod pretty_print;
@@ -18,8 +24,9 @@ use std::env::args;
 use std::fs::read_to_string;
 
 use env::{Env, EnvRef};
-use lexer::tokenize;
-use parser::parse;
+use lexer::TokenIterator;
+use parser::parse_single;
+use expander::expand;
 
 fn main() {
     let args = args().collect::<Vec<_>>();
@@ -36,16 +43,26 @@ fn main() {
         let scm = read_to_string(path).expect("Can't read file.");
 
         // TODO: run main function? (define (main args) ...)
-        match parse(tokenize(&mut scm.chars().peekable())) {
-            Ok(sexprs) => {
-                for sexpr in sexprs {
-                    match sexpr.eval(&env) {
-                        Ok(_) => (),
-                        Err(e) => eprintln!("{}", e)
-                    }
+        // Forms are read, expanded and run one by one, so that a bad form
+        // doesn't stop the ones before it.
+        let mut tokens = TokenIterator::with_file(scm.chars(), path);
+        loop {
+            let sexpr = match tokens.peek() {
+                Ok(Some(_)) => parse_single(&mut tokens),
+                Ok(None) => break,
+                Err(e) => Err(e)
+            };
+
+            match sexpr {
+                Ok(sexpr) => match expand(sexpr).and_then(|x| x.eval(&env)) {
+                    Ok(_) => (),
+                    Err(e) => eprintln!("{}", e)
+                },
+                Err(e) => {
+                    eprintln!("{}", e);
+                    break
                 }
-            },
-            Err(e) => eprintln!("{}", e)
+            }
         }
     }
 }
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use parser::SExpr;
+use parser::SExprs;
+use utils::{new_rc_ref_cell, RcRefCell};
+
+/// A mutable cons cell. Clones share the same cell, so `set-car!` on one
+/// of them is visible through all of them.
+#[derive(Clone)]
+pub struct PairData(RcRefCell<(SExpr, SExpr)>);
+
+impl PairData {
+    pub fn new(car: SExpr, cdr: SExpr) -> PairData {
+        PairData(new_rc_ref_cell((car, cdr)))
+    }
+
+    pub fn car(&self) -> SExpr {
+        self.0.borrow().0.clone()
+    }
+
+    pub fn cdr(&self) -> SExpr {
+        self.0.borrow().1.clone()
+    }
+
+    pub fn set_car(&self, x: SExpr) {
+        self.0.borrow_mut().0 = x;
+    }
+
+    pub fn set_cdr(&self, x: SExpr) {
+        self.0.borrow_mut().1 = x;
+    }
+
+    pub fn ptr_eq(&self, other: &PairData) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Takes the cdr out of a pair that isn't shared, so that it can be
+    /// dropped without recursion.
+    pub fn take_unique_cdr(&mut self) -> Option<SExpr> {
+        Rc::get_mut(&mut self.0).map(|cell| mem::replace(&mut cell.get_mut().1, SExpr::Unspecified))
+    }
+
+    /// Identity of the cell, used for detecting cycles.
+    pub fn id(&self) -> usize {
+        &*self.0 as *const _ as usize
+    }
+
+    /// Collects the elements of the list that starts with this pair.
+    /// Returns the elements and the tail, which is `()` for proper lists,
+    /// or `None` if the list is circular.
+    pub fn elements(&self) -> Option<(SExprs, SExpr)> {
+        let mut xs = vec![];
+        let mut current = self.clone();
+        // Moves at half speed; if `current` catches up with it, we are
+        // going around in circles.
+        let mut slow = self.clone();
+        loop {
+            xs.push(current.car());
+            match current.cdr() {
+                SExpr::Pair(next) => current = next,
+                tail => return Some((xs, tail))
+            }
+
+            if xs.len() % 2 == 0 {
+                slow = match slow.cdr() {
+                    SExpr::Pair(x) => x,
+                    _ => unreachable!()
+                };
+            }
+
+            if current.ptr_eq(&slow) {
+                return None
+            }
+        }
+    }
+}
+
+/// Dropping a long list recursively would overflow the stack, so the cdrs
+/// that aren't shared are unlinked in a loop.
+impl Drop for PairData {
+    fn drop(&mut self) {
+        let mut next = match self.take_unique_cdr() {
+            Some(x) => x,
+            None => return
+        };
+
+        while let SExpr::Pair(mut pair) = next {
+            next = match pair.take_unique_cdr() {
+                Some(x) => x,
+                None => break
+            };
+        }
+    }
+}
+
+/// Pairs are equal only to themselves, `equivalence::equal` compares the
+/// contents.
+impl PartialEq for PairData {
+    fn eq(&self, other: &PairData) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl fmt::Debug for PairData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PairData({:#x})", self.id())
+    }
+}
@@ -0,0 +1,93 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use parser::SExpr;
+
+/// A parameter object of `make-parameter`. Calling it returns its value,
+/// which `parameterize` can change for the dynamic extent of its body.
+#[derive(Clone)]
+pub struct ParameterData(Rc<Parameter>);
+
+struct Parameter {
+    value: SExpr,
+    /// Called with the values that the parameter is given, including the
+    /// initial one.
+    converter: Option<SExpr>,
+}
+
+/// The values given by the active `parameterize`s, innermost first.
+pub type Parameterization = Option<Rc<Binding>>;
+
+pub struct Binding {
+    parameter: ParameterData,
+    value: SExpr,
+    parent: Parameterization,
+}
+
+thread_local! {
+    static PARAMETERIZATION: RefCell<Parameterization> = const { RefCell::new(None) };
+}
+
+pub fn current_parameterization() -> Parameterization {
+    PARAMETERIZATION.with(|p| p.borrow().clone())
+}
+
+pub fn set_parameterization(parameterization: Parameterization) {
+    PARAMETERIZATION.with(|p| *p.borrow_mut() = parameterization);
+}
+
+/// Extends the current parameterization with the given values, which are
+/// already converted.
+pub fn parameterize(bindings: Vec<(ParameterData, SExpr)>) -> Parameterization {
+    bindings.into_iter()
+        .fold(current_parameterization(), |parent, (parameter, value)| {
+            Some(Rc::new(Binding { parameter, value, parent }))
+        })
+}
+
+impl ParameterData {
+    /// `value` should already be converted.
+    pub fn new(value: SExpr, converter: Option<SExpr>) -> ParameterData {
+        ParameterData(Rc::new(Parameter { value, converter }))
+    }
+
+    /// The value in the current dynamic extent.
+    pub fn value(&self) -> SExpr {
+        let mut current = current_parameterization();
+        while let Some(binding) = current {
+            if binding.parameter.ptr_eq(self) {
+                return binding.value.clone()
+            }
+            current = binding.parent.clone();
+        }
+
+        self.0.value.clone()
+    }
+
+    pub fn converter(&self) -> Option<&SExpr> {
+        self.0.converter.as_ref()
+    }
+
+    pub fn ptr_eq(&self, other: &ParameterData) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for ParameterData {
+    fn eq(&self, other: &ParameterData) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl fmt::Debug for ParameterData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ParameterData({:#x})", &*self.0 as *const _ as usize)
+    }
+}
+
+impl fmt::Debug for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Binding({:?})", self.parameter)
+    }
+}
@@ -1,28 +1,52 @@
-use std::iter::Peekable;
 use std::ops::Not;
 use std::cmp::Ordering;
 use std::rc::Rc;
 
+use utils::bigint::BigInt;
 use utils::fraction::Fraction;
-use utils::RcRefCell;
-use lexer::Token;
+use utils::{new_rc_ref_cell, RcRefCell};
+use lexer::{Token, TokenIterator, SpannedToken, Span};
 use procedure::ProcedureData;
+use condition::ConditionData;
+use pair::PairData;
+use promise::PromiseData;
+use record::{RecordData, RecordTypeData};
+use hashtable::HashTableData;
 use evaluator;
 use env::EnvRef;
 use port::PortData;
-use expander::expand;
 use serr::{SErr, SResult};
 
 pub type SExprs = Vec<SExpr>;
 
+/// `List` and `DottedList` are what the parser produces for code. Lists
+/// that are created at runtime, including quoted ones, are made of `Pair`s
+/// and end with an empty `List`.
 #[derive(Debug, Clone, PartialEq)]
 pub enum SExpr {
     Atom(Token),
     List(SExprs),
     DottedList(Vec<SExpr>, Box<SExpr>),
+    Pair(PairData),
+    Vector(RcRefCell<SExprs>),
+    Bytevector(RcRefCell<Vec<u8>>),
     Procedure(ProcedureData),
     Port(PortData),
+    Condition(ConditionData),
+    /// Zero or several values returned by `values`. A single value is never
+    /// wrapped in this.
+    Values(SExprs),
+    Promise(PromiseData),
+    Record(RecordData),
+    RecordType(RecordTypeData),
+    HashTable(HashTableData),
+    /// What the read procedures return once a port runs out.
+    Eof,
     Unspecified,
+    /// A list or a symbol of the code, with where it was read from. The
+    /// evaluator uses it to tell where an error happened, it never shows
+    /// up in data.
+    Located(Span, Box<SExpr>),
 }
 
 impl PartialOrd for SExpr {
@@ -74,9 +98,16 @@ impl From<f64> for SExpr {
     }
 }
 
+impl From<BigInt> for SExpr {
+    fn from(i: BigInt) -> Self {
+        SExpr::Atom(Token::from(i))
+    }
+}
+
+/// Whole fractions become integers.
 impl From<Fraction> for SExpr {
     fn from(i: Fraction) -> Self {
-        sfrac!(i)
+        SExpr::Atom(Token::from(i))
     }
 }
 
@@ -110,6 +141,97 @@ impl SExpr {
         SExpr::DottedList(x, Box::new(y))
     }
 
+    pub fn cons(x: SExpr, y: SExpr) -> SExpr {
+        SExpr::Pair(PairData::new(x, y))
+    }
+
+    /// Builds a list of pairs from `xs`.
+    pub fn list_from(xs: SExprs) -> SExpr {
+        SExpr::dotted_list_from(xs, SExpr::List(vec![]))
+    }
+
+    /// Builds a list of pairs from `xs`, ending with `tail`.
+    pub fn dotted_list_from(xs: SExprs, tail: SExpr) -> SExpr {
+        xs.into_iter()
+            .rev()
+            .fold(tail, |acc, x| SExpr::cons(x, acc))
+    }
+
+    pub fn vector_from(xs: SExprs) -> SExpr {
+        SExpr::Vector(new_rc_ref_cell(xs))
+    }
+
+    /// The result of `(values xs...)`.
+    pub fn values(mut xs: SExprs) -> SExpr {
+        if xs.len() == 1 {
+            xs.pop().unwrap()
+        } else {
+            SExpr::Values(xs)
+        }
+    }
+
+    /// Spreads the values that `values` returned, any other expression is
+    /// a single value.
+    pub fn into_values(self) -> SExprs {
+        match self {
+            SExpr::Values(xs) => xs,
+            x => vec![x]
+        }
+    }
+
+    pub fn bytevector_from(xs: Vec<u8>) -> SExpr {
+        SExpr::Bytevector(new_rc_ref_cell(xs))
+    }
+
+    /// Turns the lists of the code into pairs, so it can be used as data.
+    pub fn into_data(self) -> SExpr {
+        match self {
+            SExpr::Vector(xs) => {
+                let xs = xs.borrow().iter().cloned().map(SExpr::into_data).collect();
+                SExpr::vector_from(xs)
+            },
+            SExpr::List(xs) => {
+                SExpr::list_from(xs.into_iter().map(SExpr::into_data).collect())
+            },
+            SExpr::DottedList(xs, y) => {
+                let xs = xs.into_iter().map(SExpr::into_data).collect();
+                SExpr::dotted_list_from(xs, y.into_data())
+            },
+            SExpr::Located(_, x) => x.into_data(),
+            x => x
+        }
+    }
+
+    /// The expression without the location it was read from.
+    pub fn unlocated(&self) -> &SExpr {
+        match self {
+            SExpr::Located(_, x) => x.unlocated(),
+            x => x
+        }
+    }
+
+    pub fn into_unlocated(self) -> SExpr {
+        match self {
+            SExpr::Located(_, x) => x.into_unlocated(),
+            x => x
+        }
+    }
+
+    /// Removes the locations of the expression and of everything in it.
+    pub fn strip_locations(self) -> SExpr {
+        match self {
+            SExpr::List(xs) => SExpr::List(xs.into_iter().map(SExpr::strip_locations).collect()),
+            SExpr::DottedList(xs, y) => {
+                SExpr::dottedlist(xs.into_iter().map(SExpr::strip_locations).collect(), y.strip_locations())
+            },
+            SExpr::Vector(xs) => {
+                SExpr::vector_from(xs.borrow().iter().cloned().map(SExpr::strip_locations).collect())
+            },
+            SExpr::Located(_, x) => x.strip_locations(),
+            x => x
+        }
+    }
+
     pub fn to_bool(&self) -> bool {
         // Anything other than #f is treated as true.
         match self {
@@ -149,7 +271,7 @@ impl SExpr {
 
     pub fn is_integer(&self) -> bool {
         match self {
-            SExpr::Atom(Token::Integer(_)) => true,
+            SExpr::Atom(Token::Integer(_)) | SExpr::Atom(Token::BigInt(_)) => true,
             _ => false
         }
     }
@@ -158,6 +280,14 @@ impl SExpr {
         match self {
             SExpr::List(xs) if !xs.is_empty() => true,
             SExpr::DottedList(_, _) => true,
+            SExpr::Pair(_) => true,
+            _ => false
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            SExpr::List(xs) => xs.is_empty(),
             _ => false
         }
     }
@@ -169,6 +299,10 @@ impl SExpr {
                 SExpr::List(xs) if xs.is_empty() => true,
                 _ => false
             }
+            SExpr::Pair(x) => match x.elements() {
+                Some((_, tail)) => tail.is_null(),
+                None => false
+            },
             _ => false
         }
     }
@@ -196,8 +330,7 @@ impl SExpr {
 
     pub fn is_numeric(&self) -> bool {
         match self {
-            SExpr::Atom(Token::Integer(_)) | SExpr::Atom(Token::Fraction(_))
-                | SExpr::Atom(Token::Float(_)) => true,
+            SExpr::Atom(x) => x.is_number(),
             _ => false
         }
     }
@@ -220,6 +353,7 @@ impl SExpr {
     pub fn as_symbol(&self) -> SResult<&String> {
         match self {
             SExpr::Atom(Token::Symbol(x)) => Ok(x),
+            SExpr::Located(_, x) => x.as_symbol(),
             x => bail!(TypeMismatch => "symbol", x)
         }
     }
@@ -249,6 +383,7 @@ impl SExpr {
     pub fn into_symbol(self) -> SResult<String> {
         match self {
             SExpr::Atom(Token::Symbol(x)) => Ok(x),
+            SExpr::Located(_, x) => x.into_symbol(),
             x => bail!(TypeMismatch => "symbol", x)
         }
     }
@@ -256,10 +391,43 @@ impl SExpr {
     pub fn into_list(self) -> SResult<SExprs> {
         match self {
             SExpr::List(xs) => Ok(xs),
+            SExpr::Located(_, x) => x.into_list(),
+            SExpr::Pair(ref x) => match x.elements() {
+                Some((xs, ref tail)) if tail.is_null() => Ok(xs),
+                _ => bail!(TypeMismatch => "list", self)
+            },
             x => bail!(TypeMismatch => "list", x)
         }
     }
 
+    pub fn as_vector(&self) -> SResult<&RcRefCell<SExprs>> {
+        match self {
+            SExpr::Vector(xs) => Ok(xs),
+            x => bail!(TypeMismatch => "vector", x)
+        }
+    }
+
+    pub fn as_bytevector(&self) -> SResult<&RcRefCell<Vec<u8>>> {
+        match self {
+            SExpr::Bytevector(xs) => Ok(xs),
+            x => bail!(TypeMismatch => "bytevector", x)
+        }
+    }
+
+    pub fn as_byte(&self) -> SResult<u8> {
+        match self {
+            SExpr::Atom(Token::Integer(x)) if *x >= 0 && *x <= 255 => Ok(*x as u8),
+            x => bail!(TypeMismatch => "byte", x)
+        }
+    }
+
+    pub fn as_pair(&self) -> SResult<&PairData> {
+        match self {
+            SExpr::Pair(x) => Ok(x),
+            x => bail!(TypeMismatch => "pair", x)
+        }
+    }
+
     pub fn into_str(self) -> SResult<String> {
         match self {
             SExpr::Atom(Token::Str(x)) => {
@@ -293,13 +461,32 @@ impl SExpr {
     }
 
     pub fn into_float(self) -> SResult<f64> {
+        if let SExpr::Atom(ref x) = self {
+            if let Some(x) = x.to_float() {
+                return Ok(x)
+            }
+        }
+
+        bail!(TypeMismatch => "float", self)
+    }
+
+    pub fn into_bigint(self) -> SResult<BigInt> {
         match self {
-            SExpr::Atom(Token::Float(x)) => Ok(x),
-            SExpr::Atom(Token::Integer(x)) => Ok(x as f64),
-            SExpr::Atom(Token::Fraction(x)) => Ok(x.into()),
-            x => bail!(TypeMismatch => "float", x)
+            SExpr::Atom(Token::Integer(x)) => Ok(BigInt::from(x)),
+            SExpr::Atom(Token::BigInt(x)) => Ok(x),
+            x => bail!(TypeMismatch => "integer", x)
         }
     }
+
+    pub fn into_fraction(self) -> SResult<Fraction> {
+        if let SExpr::Atom(ref x) = self {
+            if let Some(x) = x.to_fraction() {
+                return Ok(x)
+            }
+        }
+
+        bail!(TypeMismatch => "exact number", self)
+    }
     // Transform operations
     pub fn list_own_one_rest(self) -> SResult<(SExpr, SExprs)> {
         match self {
@@ -330,81 +517,115 @@ impl SExpr {
     }
 }
 
-pub fn parse(tokens: Vec<Token>) -> SResult<SExprs> {
-    let mut iter = tokens.into_iter().peekable();
+/// Reads every datum of the input. Macros aren't expanded yet, each form
+/// should be expanded right before it's evaluated.
+pub fn parse<I>(mut tokens: TokenIterator<I>) -> SResult<SExprs>
+where I: Iterator<Item=char> {
     let mut exprs: SExprs = vec![];
 
-    while let Some(_) = iter.peek() {
-        exprs.push(expand(parse_single(&mut iter)?)?);
+    while tokens.peek()?.is_some() {
+        exprs.push(parse_single(&mut tokens)?);
     }
 
     Ok(exprs)
 }
 
-pub fn parse_single<I>(iter: &mut Peekable<I>) -> SResult<SExpr>
-where I: Iterator<Item=Token> {
-    match iter.peek() {
-        Some(&Token::RParen) => bail!(UnexpectedToken => Token::RParen),
-        Some(&Token::LParen) => {
-            iter.next(); // Consume LParen
-
-            // Check if empty list
-            if iter.peek() == Some(&Token::RParen) {
-                iter.next(); // Consume RParen
-                return Ok(slist![]);
-            }
+/// Reads the next datum, `tokens` shouldn't be empty.
+pub fn parse_single<I>(tokens: &mut TokenIterator<I>) -> SResult<SExpr>
+where I: Iterator<Item=char> {
+    let SpannedToken { token, span } = match tokens.next() {
+        Some(x) => x?,
+        None => serr!(FoundNothing)
+    };
 
+    match token {
+        Token::RParen => tokens.error(&span, "Unexpected )".to_string()),
+        Token::Dot => tokens.error(&span, "Unexpected .".to_string()),
+        Token::LParen => {
             let mut head: SExprs = vec![];
-            while iter.peek() != Some(&Token::RParen) &&
-                    iter.peek() != Some(&Token::Dot) {
-                head.push(parse_single(iter)?);
+            while !tokens.peek_is(&Token::RParen)? && !tokens.peek_is(&Token::Dot)? {
+                head.push(parse_until_closed(tokens, &span)?);
             }
 
-            match iter.next() {
-                Some(Token::Dot) => {
-                    let tail = parse_single(iter)?;
-                    if iter.peek() != Some(&Token::RParen) {
-                        let unexpected = iter.peek().unwrap().clone();
-                        bail!(NotExpectedToken => unexpected, Token::RParen)
-                    } else {
-                        iter.next(); // Consume RParen
-
-                        // If the tail is a proper list, then the result should
-                        // also be a proper list.
-                        if let SExpr::List(mut xs) = tail {
-                            head.append(&mut xs);
-                            Ok(SExpr::List(head))
-                        } else {
-                            Ok(SExpr::DottedList(head, Box::new(tail)))
-                        }
-                    }
-                },
-                Some(Token::RParen) => {
-                    Ok(SExpr::List(head))
+            if tokens.next().transpose()?.is_some_and(|x| x.token == Token::RParen) {
+                // The empty list is a constant, there is nothing to locate
+                if head.is_empty() {
+                    return Ok(SExpr::List(head))
+                }
+                return Ok(located(span, SExpr::List(head)))
+            }
+
+            // Consumed a Dot, a single datum and the closing paren follow
+            if head.is_empty() || tokens.peek_is(&Token::RParen)? {
+                return tokens.error(&span, "Expected a datum on both sides of the . in this list".to_string())
+            }
+            let tail = parse_until_closed(tokens, &span)?;
+            match tokens.next().transpose()? {
+                Some(SpannedToken { token: Token::RParen, .. }) => (),
+                Some(x) => return tokens.error(&x.span, format!("Expected a ) after the tail of a dotted list, got: {}", x.token)),
+                None => return tokens.error(&span, "Expected a closing ) for this list, got nothing.".to_string())
+            }
+
+            // If the tail is a proper list, then the result should
+            // also be a proper list.
+            match tail.into_unlocated() {
+                SExpr::List(mut xs) => {
+                    head.append(&mut xs);
+                    Ok(located(span, SExpr::List(head)))
                 },
-                x => bail!(UnexpectedToken => x.unwrap()),
+                tail => Ok(located(span, SExpr::DottedList(head, Box::new(tail))))
             }
         },
-        Some(&Token::Quote) => {
-            iter.next();
-            Ok(quote!(parse_single(iter)?))
-        },
-        Some(&Token::UnQuote) => {
-            iter.next();
-            Ok(unquote!(parse_single(iter)?))
-        },
-        Some(&Token::QuasiQuote) => {
-            iter.next();
-            Ok(quasiquote!(parse_single(iter)?))
-        },
-        Some(&Token::UnQuoteSplicing) => {
-            iter.next();
-            Ok(unquote_splicing!(parse_single(iter)?))
+        Token::VectorOpener => {
+            let mut xs: SExprs = vec![];
+            while !tokens.peek_is(&Token::RParen)? {
+                xs.push(parse_until_closed(tokens, &span)?);
+            }
+
+            tokens.next(); // Consume RParen
+            Ok(SExpr::vector_from(xs))
         },
-        Some(_) => {
-            let y = iter.next().unwrap();
-            Ok(SExpr::Atom(y))
+        Token::BytevectorOpener => {
+            let mut xs = vec![];
+            while !tokens.peek_is(&Token::RParen)? {
+                let byte_span = tokens.peek()?.map(|x| x.span.clone());
+                match parse_until_closed(tokens, &span)?.as_byte() {
+                    Ok(x) => xs.push(x),
+                    Err(e) => return tokens.error(&byte_span.unwrap(), e.to_string())
+                }
+            }
+
+            tokens.next(); // Consume RParen
+            Ok(SExpr::bytevector_from(xs))
         },
-        None => serr!(FoundNothing)
+        Token::Quote => Ok(located(span.clone(), quote!(parse_after(tokens, &span, "'")?))),
+        Token::UnQuote => Ok(located(span.clone(), unquote!(parse_after(tokens, &span, ",")?))),
+        Token::QuasiQuote => Ok(located(span.clone(), quasiquote!(parse_after(tokens, &span, "`")?))),
+        Token::UnQuoteSplicing => Ok(located(span.clone(), unquote_splicing!(parse_after(tokens, &span, ",@")?))),
+        x@Token::Symbol(_) => Ok(located(span, SExpr::Atom(x))),
+        x => Ok(SExpr::Atom(x))
+    }
+}
+
+fn located(span: Span, sexpr: SExpr) -> SExpr {
+    SExpr::Located(span, Box::new(sexpr))
+}
+
+/// Reads the next element of the list, vector or bytevector that starts at
+/// `span`.
+fn parse_until_closed<I>(tokens: &mut TokenIterator<I>, span: &Span) -> SResult<SExpr>
+where I: Iterator<Item=char> {
+    if tokens.peek()?.is_none() {
+        return tokens.error(span, "Expected a closing ) for this list, got nothing.".to_string())
+    }
+    parse_single(tokens)
+}
+
+/// Reads the datum that follows the `prefix` at `span`, like a quote.
+fn parse_after<I>(tokens: &mut TokenIterator<I>, span: &Span, prefix: &str) -> SResult<SExpr>
+where I: Iterator<Item=char> {
+    if tokens.peek()?.is_none() || tokens.peek_is(&Token::RParen)? {
+        return tokens.error(span, format!("Expected a datum after {}", prefix))
     }
+    parse_single(tokens)
 }
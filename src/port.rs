@@ -2,8 +2,13 @@ use std::io::prelude::*;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io;
-use std::io::{BufReader, BufWriter, Stdin, Stdout};
+use std::io::{BufReader, BufWriter, Cursor, Stdin, Stdout, Stderr};
+use std::rc::Rc;
 
+use parser::SExpr;
+use evaluator::Args;
+use parameter::ParameterData;
+use procedure::ProcedureData;
 use serr::{SErr, SResult};
 use utils::chars::Chars;
 use utils::{new_rc_ref_cell, RcRefCell};
@@ -14,8 +19,15 @@ pub enum PortData {
     TextualFileOutput(String, RcRefCell<BufWriter<File>>),
     BinaryFileInput(String, RcRefCell<BufReader<File>>),
     BinaryFileOutput(String, RcRefCell<BufWriter<File>>),
-    StdInput(RcRefCell<Stdin>),
+    StdInput(RcRefCell<StdinReader>),
     StdOutput(RcRefCell<Stdout>),
+    StdError(RcRefCell<Stderr>),
+    /// `open-input-string` and `open-output-string`, the strings are kept
+    /// as UTF-8.
+    StringInput(RcRefCell<Cursor<Vec<u8>>>),
+    StringOutput(RcRefCell<Vec<u8>>),
+    BytevectorInput(RcRefCell<Cursor<Vec<u8>>>),
+    BytevectorOutput(RcRefCell<Vec<u8>>),
     Closed
 }
 
@@ -24,17 +36,28 @@ impl PartialEq for PortData {
         match (self, rhs) {
             (PortData::TextualFileInput(s,r), PortData::TextualFileInput(rs,rr))
                 | (PortData::BinaryFileInput(s,r), PortData::BinaryFileInput(rs, rr)) => {
-                    s == rs && &*r as *const _ == &*rr as *const _
+                    s == rs && Rc::ptr_eq(r, rr)
             }
             (PortData::TextualFileOutput(s,r), PortData::TextualFileOutput(rs,rr))
                 | (PortData::BinaryFileOutput(s,r), PortData::BinaryFileOutput(rs, rr)) => {
-                    s == rs && &*r as *const _ == &*rr as *const _
+                    s == rs && Rc::ptr_eq(r, rr)
             },
             (PortData::StdInput(r), PortData::StdInput(rr)) => {
-                    &*r as *const _ == &*rr as *const _
+                    Rc::ptr_eq(r, rr)
             },
             (PortData::StdOutput(r), PortData::StdOutput(rr)) => {
-                    &*r as *const _ == &*rr as *const _
+                    Rc::ptr_eq(r, rr)
+            },
+            (PortData::StdError(r), PortData::StdError(rr)) => {
+                    Rc::ptr_eq(r, rr)
+            },
+            (PortData::StringInput(r), PortData::StringInput(rr))
+                | (PortData::BytevectorInput(r), PortData::BytevectorInput(rr)) => {
+                    Rc::ptr_eq(r, rr)
+            },
+            (PortData::StringOutput(r), PortData::StringOutput(rr))
+                | (PortData::BytevectorOutput(r), PortData::BytevectorOutput(rr)) => {
+                    Rc::ptr_eq(r, rr)
             },
             _ => false
         }
@@ -87,6 +110,38 @@ impl PortData {
         Ok(PortData::BinaryFileOutput(path.to_string(), new_rc_ref_cell(BufWriter::new(file))))
     }
 
+    pub fn new_string_input(string: String) -> PortData {
+        PortData::StringInput(new_rc_ref_cell(Cursor::new(string.into_bytes())))
+    }
+
+    pub fn new_string_output() -> PortData {
+        PortData::StringOutput(new_rc_ref_cell(vec![]))
+    }
+
+    pub fn new_bytevector_input(bytes: Vec<u8>) -> PortData {
+        PortData::BytevectorInput(new_rc_ref_cell(Cursor::new(bytes)))
+    }
+
+    pub fn new_bytevector_output() -> PortData {
+        PortData::BytevectorOutput(new_rc_ref_cell(vec![]))
+    }
+
+    /// Everything written to a string port so far.
+    pub fn output_string(&self) -> SResult<String> {
+        match self {
+            PortData::StringOutput(buf) => Ok(String::from_utf8_lossy(&buf.borrow()).into_owned()),
+            _ => bail!(WrongPort => "get-output-string", self.kind_name())
+        }
+    }
+
+    /// Everything written to a bytevector port so far.
+    pub fn output_bytes(&self) -> SResult<Vec<u8>> {
+        match self {
+            PortData::BytevectorOutput(buf) => Ok(buf.borrow().clone()),
+            _ => bail!(WrongPort => "get-output-bytevector", self.kind_name())
+        }
+    }
+
     //
     // Read functions
     //
@@ -94,8 +149,9 @@ impl PortData {
         match self {
             PortData::TextualFileInput(_, br) => port_read_str_fn!(br, read_line),
             PortData::StdInput(br) => port_read_str_fn!(br, read_line),
+            PortData::StringInput(br) => port_read_str_fn!(br, read_line),
             // FIXME: fix this and the functions below
-            _x => bail!(WrongPort => "read-line", "TODO:PORT_NAME_HERE")
+            _ => bail!(WrongPort => "read-line", self.kind_name())
         }
     }
 
@@ -103,52 +159,107 @@ impl PortData {
         match self {
             PortData::TextualFileInput(_, br) => port_read_str_fn!(br, read_to_string),
             PortData::StdInput(br) => port_read_str_fn!(br, read_to_string),
-            _x => bail!(WrongPort => "read-all-str", "TODO:PORT_NAME_HERE")
+            PortData::StringInput(br) => port_read_str_fn!(br, read_to_string),
+            _ => bail!(WrongPort => "read-all-str", self.kind_name())
         }
     }
 
     pub fn read_char(&mut self) -> SResult<(usize, char)> {
-        // FIXME: this only reads 1 u8 and casts it to char
         macro_rules! port_read_chr(
             ($br: ident) => {{
                 let br = &mut *$br.borrow_mut();
-                let mut chr = [0; 1];
-                br.read_exact(&mut chr)?;
-                Ok((1, chr[0] as char))
+                match Chars::new(br).next() {
+                    Some(chr) => Ok((chr.len_utf8(), chr)),
+                    None => Ok((0, '\0'))
+                }
             }};
         );
 
         match self {
             PortData::TextualFileInput(_, br) => port_read_chr!(br),
             PortData::StdInput(br) => port_read_chr!(br),
-            _x => bail!(WrongPort => "read-char", "TODO:PORT_NAME_HERE")
+            PortData::StringInput(br) => port_read_chr!(br),
+            _ => bail!(WrongPort => "read-char", self.kind_name())
         }
     }
 
     pub fn read_u8(&mut self) -> SResult<(usize, u8)> {
-        match self {
-            PortData::BinaryFileInput(_, br) => {
-                let br = &mut *br.borrow_mut();
+        macro_rules! port_read_u8(
+            ($br: ident) => {{
+                let br = &mut *$br.borrow_mut();
                 let mut u8s = [0; 1];
-                br.read_exact(&mut u8s)?;
+                let size = br.read(&mut u8s)?;
 
-                Ok((1, u8s[0]))
-            },
-            _x => bail!(WrongPort => "read-u8", "TODO:PORT_NAME_HERE")
+                Ok((size, u8s[0]))
+            }};
+        );
+
+        match self {
+            PortData::BinaryFileInput(_, br) => port_read_u8!(br),
+            PortData::BytevectorInput(br) => port_read_u8!(br),
+            _ => bail!(WrongPort => "read-u8", self.kind_name())
         }
     }
 
     pub fn read_all_u8(&mut self) -> SResult<(usize, Vec<u8>)> {
-        match self {
-            PortData::BinaryFileInput(_, br) => {
-                let br = &mut *br.borrow_mut();
+        macro_rules! port_read_all_u8(
+            ($br: ident) => {{
+                let br = &mut *$br.borrow_mut();
                 let mut u8s = vec![];
                 let size = br.read_to_end(&mut u8s)?;
 
                 Ok((size, u8s))
+            }};
+        );
+
+        match self {
+            PortData::BinaryFileInput(_, br) => port_read_all_u8!(br),
+            PortData::BytevectorInput(br) => port_read_all_u8!(br),
+            _ => bail!(WrongPort => "read-all-u8", self.kind_name())
+        }
+    }
+
+    /// Reads bytes into `buf` until it is full or the port runs out of
+    /// bytes. Returns how many bytes were read.
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> SResult<usize> {
+        macro_rules! port_read_bytes(
+            ($br: ident) => {{
+                let br = &mut *$br.borrow_mut();
+                let mut size = 0;
+                while size < buf.len() {
+                    match br.read(&mut buf[size..])? {
+                        0 => break,
+                        n => size += n
+                    }
+                }
+
+                Ok(size)
+            }};
+        );
+
+        match self {
+            PortData::BinaryFileInput(_, br) => port_read_bytes!(br),
+            PortData::BytevectorInput(br) => port_read_bytes!(br),
+            _ => bail!(WrongPort => "read-bytevector", self.kind_name())
+        }
+    }
+
+    /// Puts back characters that were just read from a textual input port,
+    /// so that the next read starts with them.
+    pub fn unread(&mut self, text: &str) -> SResult<()> {
+        match self {
+            PortData::TextualFileInput(_, br) => br.borrow_mut().seek_relative(-(text.len() as i64))?,
+            PortData::StdInput(br) => {
+                br.borrow_mut().unread.splice(0..0, text.bytes());
+            },
+            PortData::StringInput(br) => {
+                let br = &mut *br.borrow_mut();
+                let position = br.position();
+                br.set_position(position - text.len() as u64);
             },
-            _x => bail!(WrongPort => "read-all-u8", "TODO:PORT_NAME_HERE")
+            _ => bail!(WrongPort => "read", self.kind_name())
         }
+        Ok(())
     }
 
     pub fn with_chars<F, T>(&mut self, f: F) -> SResult<T>
@@ -164,7 +275,8 @@ impl PortData {
         match self {
             PortData::TextualFileInput(_, br) => with_chars!(br),
             PortData::StdInput(br) => with_chars!(br),
-            _x => bail!(WrongPort => "chars", "TODO:PORT_NAME_HERE")
+            PortData::StringInput(br) => with_chars!(br),
+            _ => bail!(WrongPort => "read", self.kind_name())
         }
     }
     //
@@ -182,7 +294,23 @@ impl PortData {
         match self {
             PortData::TextualFileOutput(_,br) => write_string!(br),
             PortData::StdOutput(br) => write_string!(br),
-            _x => bail!(WrongPort => "write-string", "TODO:PORT_NAME_HERE")
+            PortData::StdError(br) => write_string!(br),
+            PortData::StringOutput(br) => write_string!(br),
+            _ => bail!(WrongPort => "write-string", self.kind_name())
+        };
+
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> SResult<()> {
+        match self {
+            PortData::BinaryFileOutput(_, bw) => {
+                let bw = &mut *bw.borrow_mut();
+                bw.write_all(bytes)?;
+                bw.flush()?;
+            },
+            PortData::BytevectorOutput(bw) => bw.borrow_mut().extend_from_slice(bytes),
+            _ => bail!(WrongPort => "write-bytevector", self.kind_name())
         };
 
         Ok(())
@@ -196,10 +324,31 @@ impl PortData {
             PortData::TextualFileInput(_, _) => true,
             PortData::BinaryFileInput(_, _) => true,
             PortData::StdInput(_) => true,
+            PortData::StringInput(_) => true,
+            PortData::BytevectorInput(_) => true,
             _ => false
         }
     }
 
+    /// What kind of port this is, for error messages like "Can't apply
+    /// function `read-char` to the closed port".
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            PortData::TextualFileInput(_, _) => "textual input file port",
+            PortData::TextualFileOutput(_, _) => "textual output file port",
+            PortData::BinaryFileInput(_, _) => "binary input file port",
+            PortData::BinaryFileOutput(_, _) => "binary output file port",
+            PortData::StdInput(_) => "standard input port",
+            PortData::StdOutput(_) => "standard output port",
+            PortData::StdError(_) => "standard error port",
+            PortData::StringInput(_) => "input string port",
+            PortData::StringOutput(_) => "output string port",
+            PortData::BytevectorInput(_) => "input bytevector port",
+            PortData::BytevectorOutput(_) => "output bytevector port",
+            PortData::Closed => "closed port"
+        }
+    }
+
     // pub fn is_output(&self) -> bool {
     //     match self {
     //         PortData::TextualFileOutput(_, _) => true,
@@ -214,7 +363,10 @@ impl PortData {
             PortData::TextualFileInput(_, _) => true,
             PortData::TextualFileOutput(_, _) => true,
             PortData::StdOutput(_) => true,
+            PortData::StdError(_) => true,
             PortData::StdInput(_) => true,
+            PortData::StringInput(_) => true,
+            PortData::StringOutput(_) => true,
             _ => false
         }
     }
@@ -223,19 +375,102 @@ impl PortData {
         match self {
             PortData::BinaryFileInput(_, _) => true,
             PortData::BinaryFileOutput(_, _) => true,
+            PortData::BytevectorInput(_) => true,
+            PortData::BytevectorOutput(_) => true,
             _ => false
         }
     }
 }
 
+/// The standard input, after the characters that `read` put back. Stdin
+/// can't be seeked like files and strings.
+#[derive(Debug)]
+pub struct StdinReader {
+    unread: Vec<u8>,
+    stdin: Stdin,
+}
+
+impl StdinReader {
+    fn new() -> StdinReader {
+        StdinReader { unread: vec![], stdin: io::stdin() }
+    }
+
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        if self.unread.is_empty() {
+            return self.stdin.read_line(line)
+        }
+
+        let end = match self.unread.iter().position(|&b| b == b'\n') {
+            Some(i) => i + 1,
+            None => self.unread.len()
+        };
+        let bytes: Vec<u8> = self.unread.drain(..end).collect();
+        line.push_str(&String::from_utf8_lossy(&bytes));
+        if bytes.ends_with(b"\n") {
+            Ok(bytes.len())
+        } else {
+            Ok(bytes.len() + self.stdin.read_line(line)?)
+        }
+    }
+}
+
+impl Read for StdinReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.unread.is_empty() {
+            return self.stdin.read(buf)
+        }
+
+        let n = buf.len().min(self.unread.len());
+        buf[..n].copy_from_slice(&self.unread[..n]);
+        self.unread.drain(..n);
+        Ok(n)
+    }
+}
+
+thread_local! {
+    static CURRENT_INPUT_PORT: ParameterData =
+        port_parameter(PortData::StdInput(new_rc_ref_cell(StdinReader::new())));
+    static CURRENT_OUTPUT_PORT: ParameterData =
+        port_parameter(PortData::StdOutput(new_rc_ref_cell(io::stdout())));
+    static CURRENT_ERROR_PORT: ParameterData =
+        port_parameter(PortData::StdError(new_rc_ref_cell(io::stderr())));
+}
+
+fn port_parameter(port: PortData) -> ParameterData {
+    let converter = ProcedureData::new_primitive(check_port);
+    ParameterData::new(SExpr::Port(port), Some(converter))
+}
+
+/// The converter of the current port parameters, they only take ports.
+fn check_port(args: Args) -> SResult<SExpr> {
+    let port = args.evaled()?.own_one()?;
+    port.as_port()?;
+    Ok(port)
+}
+
+/// The parameter objects of `current-input-port`, `current-output-port`
+/// and `current-error-port`.
+pub fn port_parameters() -> [(&'static str, ParameterData); 3] {
+    [
+        ("current-input-port", CURRENT_INPUT_PORT.with(|p| p.clone())),
+        ("current-output-port", CURRENT_OUTPUT_PORT.with(|p| p.clone())),
+        ("current-error-port", CURRENT_ERROR_PORT.with(|p| p.clone())),
+    ]
+}
+
+fn current_port(parameter: &ParameterData) -> PortData {
+    match parameter.value() {
+        SExpr::Port(port) => port,
+        // The converter doesn't let anything else in.
+        _ => unreachable!()
+    }
+}
 
 pub fn current_input_port() -> PortData {
-    // TODO: current_input should be changable
-    PortData::StdInput(new_rc_ref_cell(io::stdin()))
+    CURRENT_INPUT_PORT.with(current_port)
 }
 
 pub fn current_output_port() -> PortData {
-    // TODO: current_output should be changable
-    PortData::StdOutput(new_rc_ref_cell(io::stdout()))
+    CURRENT_OUTPUT_PORT.with(current_port)
 }
 
@@ -1,11 +1,14 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 // use env::EnvRef;
-use lexer::Token;
+use lexer::{Token, CHAR_NAMES, parse_number};
 use parser::SExpr;
 use procedure::ProcedureData;
 use procedure::CompoundData;
 use procedure::PrimitiveData;
+use condition::ConditionData;
+use pair::PairData;
 
 #[allow(unused_must_use)]
 impl fmt::Display for Token {
@@ -18,17 +21,21 @@ impl fmt::Display for Token {
             Token::RParen          => ")".to_string(),
             Token::Dot             => ".".to_string(),
             Token::Ellipsis        => "...".to_string(),
+            Token::VectorOpener    => "#(".to_string(),
+            Token::BytevectorOpener => "#u8(".to_string(),
             Token::Quote           => "'".to_string(),
             Token::UnQuote         => ",".to_string(),
             Token::QuasiQuote      => "`".to_string(),
             Token::UnQuoteSplicing => ",@".to_string(),
-            Token::Symbol(x)  => x.to_string(),
+            Token::Symbol(x)  => str_symbol(x),
             Token::Integer(x) => format!("{}", x),
-            Token::Float(x)   => format!("{}", x),
+            Token::Float(x)   => str_float(*x),
+            Token::BigInt(x)  => x.to_string(),
             Token::Fraction(x) => format!("{}/{}", x.n, x.d),
+            Token::Complex(x) => str_complex(&x.re, &x.im),
             Token::Boolean(x) => format_bool(x).to_string(),
-            Token::Chr(x)     => format!("#\\{}", x),
-            Token::Str(x)     => format!("\"{}\"", x.borrow()),
+            Token::Chr(x)     => str_char(*x),
+            Token::Str(x)     => format!("\"{}\"", escape(&x.borrow(), '"')),
         };
 
         fmt.write_str(&s);
@@ -37,6 +44,87 @@ impl fmt::Display for Token {
     }
 }
 
+/// Escapes the characters that can't be written as they are between
+/// `delimiter`s, so that the reader reads back the same thing.
+fn escape(x: &str, delimiter: char) -> String {
+    let mut result = String::new();
+    for c in x.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '\x07' => result.push_str("\\a"),
+            '\x08' => result.push_str("\\b"),
+            c if c == delimiter => {
+                result.push('\\');
+                result.push(c);
+            },
+            c if c.is_control() => result.push_str(&format!("\\x{:x};", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result
+}
+
+fn str_char(x: char) -> String {
+    match CHAR_NAMES.iter().find(|(_, c)| *c == x) {
+        Some((name, _)) => format!("#\\{}", name),
+        None if x.is_control() => format!("#\\x{:x}", x as u32),
+        None => format!("#\\{}", x)
+    }
+}
+
+/// Symbols that would be read as something else are written between bars,
+/// like `|hello world|`.
+fn str_symbol(x: &str) -> String {
+    let plain = !x.is_empty()
+        && !x.starts_with('#')
+        && !x.chars().any(|c| c.is_whitespace() || c.is_control() || "()[]\"';`,|\\".contains(c))
+        && parse_number(x).is_none()
+        && x != ".";
+
+    if plain {
+        x.to_string()
+    } else {
+        format!("|{}|", escape(x, '|'))
+    }
+}
+
+/// Floats are always written in a way that shows they are inexact, like
+/// `2.0` instead of `2`.
+fn str_float(x: f64) -> String {
+    if x.is_nan() {
+        "+nan.0".to_string()
+    } else if x.is_infinite() {
+        if x > 0.0 { "+inf.0" } else { "-inf.0" }.to_string()
+    } else if x != 0.0 && (x.abs() >= 1e21 || x.abs() < 1e-7) {
+        format!("{:e}", x)
+    } else if x.fract() == 0.0 {
+        format!("{:.1}", x)
+    } else {
+        format!("{}", x)
+    }
+}
+
+/// Writes `a+bi`, leaving out `a` if it's an exact zero and `b` if it's an
+/// exact one, like `+i`.
+fn str_complex(re: &Token, im: &Token) -> String {
+    let im = match im {
+        Token::Integer(1) => "+".to_string(),
+        Token::Integer(-1) => "-".to_string(),
+        x => {
+            let x = x.to_string();
+            if x.starts_with(['+', '-']) { x } else { format!("+{}", x) }
+        }
+    };
+
+    match re {
+        Token::Integer(0) => format!("{}i", im),
+        re => format!("{}{}i", re, im)
+    }
+}
+
 #[allow(unused_must_use)]
 impl fmt::Display for SExpr {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -44,9 +132,21 @@ impl fmt::Display for SExpr {
             SExpr::Atom(x) => fmt.write_str(&format!("{}", x)),
             SExpr::Procedure(x) => fmt.write_str(&format!("{}", x)),
             SExpr::Unspecified => fmt.write_str("<unspecified>"),
+            SExpr::Values(xs) => fmt.write_str(&str_list(xs)),
+            SExpr::Promise(_) => fmt.write_str("#<promise>"),
+            SExpr::Eof => fmt.write_str("#<eof>"),
+            SExpr::RecordType(x) => fmt.write_str(&format!("#<record-type {}>", x.name())),
+            SExpr::HashTable(x) => fmt.write_str(&format!("#<hash-table {}>", x.count())),
             SExpr::Port(_port) => fmt.write_str("#<a port>"),
+            SExpr::Condition(x) => fmt.write_str(&format!("#<{}: {}>", x.kind_name(), x)),
             SExpr::DottedList(xs, sexpr) => fmt.write_str(&format!("({} . {})", str_list(xs), sexpr)),
             SExpr::List(xs) => fmt.write_str(&format!("({})", str_list(xs))),
+            SExpr::Located(_, x) => x.fmt(fmt),
+            SExpr::Bytevector(xs) => {
+                let bytes: Vec<String> = xs.borrow().iter().map(|x| x.to_string()).collect();
+                fmt.write_str(&format!("#u8({})", bytes.join(" ")))
+            },
+            x@SExpr::Pair(_) | x@SExpr::Vector(_) | x@SExpr::Record(_) => fmt.write_str(&str_shared(x)),
         };
         Ok(())
     }
@@ -58,6 +158,10 @@ impl fmt::Display for ProcedureData {
         match self {
             ProcedureData::Compound(x)  => fmt.write_str(&format!("{}", x)),
             ProcedureData::Primitive(x) => fmt.write_str(&format!("{}", x)),
+            ProcedureData::Continuation(_) => fmt.write_str("#<continuation>"),
+            ProcedureData::Control(x) => fmt.write_str(&format!("#<primitive-procedure {:?}>", x)),
+            ProcedureData::Parameter(_) => fmt.write_str("#<parameter>"),
+            ProcedureData::Record(x) => fmt.write_str(&format!("{}", x)),
         };
         Ok(())
     }
@@ -80,6 +184,18 @@ impl fmt::Display for PrimitiveData {
     }
 }
 
+/// Shows the message followed by the irritants, the way an uncaught error is
+/// reported.
+impl fmt::Display for ConditionData {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.message)?;
+        for x in &self.irritants {
+            write!(fmt, " {}", x)?;
+        }
+        Ok(())
+    }
+}
+
 #[allow(unused_must_use)]
 fn str_list(xs: &[SExpr]) -> String {
 
@@ -93,3 +209,165 @@ fn str_list(xs: &[SExpr]) -> String {
 
     lstr
 }
+
+/// Prints lists of pairs, vectors and records. The ones that are part of a
+/// cycle get a datum label, like `#0=(1 2 . #0#)`, so that printing terminates.
+fn str_shared(x: &SExpr) -> String {
+    let mut cycles = HashSet::new();
+    find_cycles(x, &mut HashSet::new(), &mut HashSet::new(), &mut cycles);
+
+    let mut printer = SharedPrinter { cycles, labels: HashMap::new(), out: String::new() };
+    printer.write(x);
+    printer.out
+}
+
+fn shared_id(x: &SExpr) -> Option<usize> {
+    match x {
+        SExpr::Pair(x) => Some(x.id()),
+        SExpr::Vector(xs) => Some(&**xs as *const _ as usize),
+        SExpr::Record(x) => Some(x.id()),
+        _ => None
+    }
+}
+
+fn find_cycles(x: &SExpr, path: &mut HashSet<usize>, done: &mut HashSet<usize>, cycles: &mut HashSet<usize>) {
+    // Follow the cdrs in a loop instead of recursing, long lists would
+    // blow up the stack otherwise.
+    let mut chain = vec![];
+    let mut current = x.clone();
+    while let Some(id) = shared_id(&current) {
+        if path.contains(&id) {
+            cycles.insert(id);
+            break;
+        }
+        if done.contains(&id) {
+            break;
+        }
+
+        path.insert(id);
+        chain.push(id);
+        current = match current {
+            SExpr::Pair(ref pair) => {
+                find_cycles(&pair.car(), path, done, cycles);
+                pair.cdr()
+            },
+            SExpr::Vector(ref xs) => {
+                for x in xs.borrow().iter() {
+                    find_cycles(x, path, done, cycles);
+                }
+                break;
+            },
+            SExpr::Record(ref x) => {
+                for (_, value) in x.fields() {
+                    find_cycles(&value, path, done, cycles);
+                }
+                break;
+            },
+            _ => break
+        };
+    }
+
+    for id in chain {
+        path.remove(&id);
+        done.insert(id);
+    }
+}
+
+struct SharedPrinter {
+    cycles: HashSet<usize>,
+    labels: HashMap<usize, usize>,
+    out: String
+}
+
+impl SharedPrinter {
+    fn write(&mut self, x: &SExpr) {
+        match x {
+            SExpr::Pair(pair) => self.write_pair(pair),
+            SExpr::Vector(xs) => {
+                if self.write_label(x) {
+                    return;
+                }
+
+                self.out.push_str("#(");
+                for (i, x) in xs.borrow().iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(' ');
+                    }
+                    self.write(x);
+                }
+                self.out.push(')');
+            },
+            SExpr::Record(record) => {
+                if self.write_label(x) {
+                    return;
+                }
+
+                self.out.push_str(&format!("#<record {}", record.record_type().name()));
+                for (name, value) in record.fields() {
+                    self.out.push_str(&format!(" {}: ", name));
+                    self.write(&value);
+                }
+                self.out.push('>');
+            },
+            x => self.out.push_str(&x.to_string())
+        }
+    }
+
+    fn write_pair(&mut self, pair: &PairData) {
+        if self.write_label(&SExpr::Pair(pair.clone())) {
+            return;
+        }
+
+        self.out.push('(');
+        let mut current = pair.clone();
+        loop {
+            self.write(&current.car());
+            match current.cdr() {
+                SExpr::Pair(ref x) if !self.cycles.contains(&x.id()) => {
+                    self.out.push(' ');
+                    current = x.clone();
+                },
+                SExpr::List(ref xs) if xs.is_empty() => break,
+                x => {
+                    self.out.push_str(" . ");
+                    self.write(&x);
+                    break;
+                }
+            }
+        }
+        self.out.push(')');
+    }
+
+    /// Writes the label of `x` if it has one. Returns true if `x` is already
+    /// printed, so only a reference to it is needed.
+    fn write_label(&mut self, x: &SExpr) -> bool {
+        let id = match shared_id(x) {
+            Some(id) if self.cycles.contains(&id) => id,
+            _ => return false
+        };
+
+        if let Some(label) = self.labels.get(&id) {
+            self.out.push_str(&format!("#{}#", label));
+            return true;
+        }
+
+        let label = self.labels.len();
+        self.labels.insert(id, label);
+        self.out.push_str(&format!("#{}=", label));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::SExpr;
+    use record::{RecordData, RecordTypeData};
+
+    #[test]
+    fn cyclic_record() {
+        let rtype = RecordTypeData::new("<node>", vec!["val".to_string(), "next".to_string()]);
+        let node = RecordData::new(rtype, vec![sint!(1), SExpr::Unspecified]);
+        node.set(1, SExpr::Record(node.clone()));
+        assert_eq!(SExpr::Record(node).to_string(), "#0=#<record node val: 1 next: #0#>");
+    }
+}
@@ -0,0 +1,126 @@
+use parser::SExpr;
+use evaluator::Args;
+use serr::{SErr, SResult};
+use primitives::vector::{index, range};
+
+//
+// Functions
+//
+pub fn bytevector(args: Args) -> SResult<SExpr> {
+    let bytes = args.evaled()?
+        .iter()
+        .map(SExpr::as_byte)
+        .collect::<SResult<Vec<u8>>>()?;
+
+    Ok(SExpr::bytevector_from(bytes))
+}
+
+pub fn bytevector_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.as_bytevector().is_ok()))
+}
+
+pub fn make_bytevector(args: Args) -> SResult<SExpr> {
+    let evaled = args.evaled()?;
+    let (len, fill) = match evaled.len() {
+        1 => (evaled.own_one()?, 0),
+        _ => {
+            let (len, fill) = evaled.own_two()?;
+            (len, fill.as_byte()?)
+        }
+    };
+
+    Ok(SExpr::bytevector_from(vec![fill; index(len)?]))
+}
+
+pub fn bytevector_length(args: Args) -> SResult<SExpr> {
+    let bv = args.evaled()?.own_one()?;
+    let len = bv.as_bytevector()?.borrow().len();
+    Ok(sint!(len as i64))
+}
+
+pub fn bytevector_u8_ref(args: Args) -> SResult<SExpr> {
+    let (bv, i) = args.evaled()?.own_two()?;
+    let bytes = bv.as_bytevector()?.borrow();
+    let i = index(i)?;
+
+    bytes.get(i)
+        .map(|x| sint!(i64::from(*x)))
+        .ok_or_else(|| SErr::IndexOutOfBounds(bytes.len(), i))
+}
+
+pub fn bytevector_u8_set_em(args: Args) -> SResult<SExpr> {
+    let (bv, i, x) = args.evaled()?.own_three()?;
+    let mut bytes = bv.as_bytevector()?.borrow_mut();
+    let i = index(i)?;
+    let len = bytes.len();
+
+    let byte = bytes.get_mut(i)
+        .ok_or_else(|| SErr::IndexOutOfBounds(len, i))?;
+    *byte = x.as_byte()?;
+    Ok(SExpr::Unspecified)
+}
+
+pub fn bytevector_copy(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let bv = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    let bytes = bv.as_bytevector()?.borrow();
+    let (start, end) = range(bytes.len(), iter.next(), iter.next())?;
+
+    Ok(SExpr::bytevector_from(bytes[start..end].to_vec()))
+}
+
+/// (bytevector-copy! to at from [start [end]])
+pub fn bytevector_copy_em(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let to = iter.next().ok_or_else(|| SErr::WrongArgCount(3, 0))?;
+    let at = index(iter.next().ok_or_else(|| SErr::WrongArgCount(3, 1))?)?;
+    let from = iter.next().ok_or_else(|| SErr::WrongArgCount(3, 2))?;
+
+    // Copy first, `to` and `from` may be the same bytevector.
+    let bytes = {
+        let xs = from.as_bytevector()?.borrow();
+        let (start, end) = range(xs.len(), iter.next(), iter.next())?;
+        xs[start..end].to_vec()
+    };
+
+    let mut ys = to.as_bytevector()?.borrow_mut();
+    if at + bytes.len() > ys.len() {
+        bail!(IndexOutOfBounds => ys.len(), at + bytes.len())
+    }
+
+    ys[at..at + bytes.len()].copy_from_slice(&bytes);
+    Ok(SExpr::Unspecified)
+}
+
+pub fn bytevector_append(args: Args) -> SResult<SExpr> {
+    let mut result = vec![];
+    for bv in args.evaled()?.iter() {
+        result.extend_from_slice(&bv.as_bytevector()?.borrow());
+    }
+
+    Ok(SExpr::bytevector_from(result))
+}
+
+/// (utf8->string bytevector [start [end]])
+pub fn utf8_string(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let bv = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    let bytes = bv.as_bytevector()?.borrow();
+    let (start, end) = range(bytes.len(), iter.next(), iter.next())?;
+
+    match String::from_utf8(bytes[start..end].to_vec()) {
+        Ok(string) => Ok(sstr!(string)),
+        Err(_) => bail!(TypeMismatch => "UTF-8 encoded bytevector", bv.clone())
+    }
+}
+
+/// (string->utf8 string [start [end]])
+pub fn string_utf8(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let string = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?.into_str()?;
+    let chars: Vec<char> = string.chars().collect();
+    let (start, end) = range(chars.len(), iter.next(), iter.next())?;
+
+    let substring: String = chars[start..end].iter().collect();
+    Ok(SExpr::bytevector_from(substring.into_bytes()))
+}
@@ -0,0 +1,36 @@
+use env::EnvValues;
+use evaluator::Control;
+use procedure::ProcedureData;
+
+/// Procedures that are implemented by the evaluator, see `evaluator::Control`.
+pub fn env() -> EnvValues {
+    let mut m = EnvValues::new();
+    let controls = [
+        ("any", Control::Any),
+        ("apply", Control::Apply),
+        ("call/cc", Control::CallCC),
+        ("call-with-current-continuation", Control::CallCC),
+        ("call/ec", Control::CallEC),
+        ("call-with-escape-continuation", Control::CallEC),
+        ("call-with-values", Control::CallWithValues),
+        ("dynamic-wind", Control::DynamicWind),
+        ("every", Control::Every),
+        ("fold", Control::Fold),
+        ("for-each", Control::ForEach),
+        ("force", Control::Force),
+        ("hash-table-ref", Control::HashTableRef),
+        ("make-parameter", Control::MakeParameter),
+        ("map", Control::Map),
+        ("map!", Control::Map),
+        ("map-in-order", Control::Map),
+        ("raise", Control::Raise),
+        ("raise-continuable", Control::RaiseContinuable),
+        ("with-exception-handler", Control::WithExceptionHandler),
+    ];
+
+    for (name, control) in controls.iter() {
+        m.insert(name.to_string(), ProcedureData::new_control(*control));
+    }
+
+    m
+}
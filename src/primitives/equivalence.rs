@@ -1,6 +1,10 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
 use lexer::Token;
 use parser::SExpr;
 use evaluator::Args;
+use env::EnvRef;
 use serr::SResult;
 
 pub fn eq_qm(args: Args) -> SResult<SExpr> {
@@ -10,26 +14,90 @@ pub fn eq_qm(args: Args) -> SResult<SExpr> {
 pub fn eqv_qm(args: Args) -> SResult<SExpr> {
     equality(args, |args| {
         let evaled = args.eval()?;
-        let result = match (&evaled[0], &evaled[1]) {
-            (SExpr::Atom(x), SExpr::Atom(y)) => x == y,
-            (SExpr::List(x), SExpr::List(y)) => x.is_empty() && y.is_empty(),
-            (_,_) => false
-        };
-
-        Ok(result)
+        Ok(eqv(&evaled[0], &evaled[1]))
     })
 }
 
+/// Compares atoms by value and everything else by identity. `+nan.0` is
+/// `eqv?` to itself, like it's hashed the same in hash tables.
+pub fn eqv(x: &SExpr, y: &SExpr) -> bool {
+    match (x, y) {
+        (SExpr::Atom(Token::Float(x)), SExpr::Atom(Token::Float(y))) => x == y || x.is_nan() && y.is_nan(),
+        (SExpr::Atom(x), SExpr::Atom(y)) => x == y,
+        (SExpr::Port(x), SExpr::Port(y)) => x == y,
+        (SExpr::Procedure(x), SExpr::Procedure(y)) => x == y,
+        (SExpr::List(x), SExpr::List(y)) => x.is_empty() && y.is_empty(),
+        (SExpr::Pair(x), SExpr::Pair(y)) => x.ptr_eq(y),
+        (SExpr::Vector(x), SExpr::Vector(y)) => Rc::ptr_eq(x, y),
+        (SExpr::Bytevector(x), SExpr::Bytevector(y)) => Rc::ptr_eq(x, y),
+        (SExpr::Promise(x), SExpr::Promise(y)) => x == y,
+        (SExpr::Eof, SExpr::Eof) => true,
+        (SExpr::Record(x), SExpr::Record(y)) => x == y,
+        (SExpr::RecordType(x), SExpr::RecordType(y)) => x == y,
+        (SExpr::HashTable(x), SExpr::HashTable(y)) => x == y,
+        (_,_) => false
+    }
+}
+
+/// Which of `eq?`, `eqv?`, `equal?` and `string=?`, as bound in `env`,
+/// the procedure is. Procedures that take an equality use it to compare
+/// natively instead of calling the predicate.
+pub fn builtin_predicate(procedure: &SExpr, env: &EnvRef) -> Option<&'static str> {
+    ["eq?", "eqv?", "equal?", "string=?"].iter()
+        .find(|name| env.get(name).ok().as_ref() == Some(procedure))
+        .cloned()
+}
+
 pub fn equal_qm(args: Args) -> SResult<SExpr> {
     equality(args, |args| {
         let evaled = args.eval()?;
-        let obj1 = &evaled[0];
-        let obj2 = &evaled[1];
-
-        Ok(obj1 == obj2)
+        Ok(equal(&evaled[0], &evaled[1]))
     })
 }
 
+/// Compares pairs and vectors by their contents. Pairs that are being
+/// compared already are assumed to be equal, so that circular lists can be
+/// compared too.
+pub fn equal(x: &SExpr, y: &SExpr) -> bool {
+    equal_seen(x, y, &mut HashSet::new())
+}
+
+fn equal_seen(x: &SExpr, y: &SExpr, seen: &mut HashSet<(usize, usize)>) -> bool {
+    let (mut x, mut y) = match (x, y) {
+        (SExpr::Pair(x), SExpr::Pair(y)) => (x.clone(), y.clone()),
+        (SExpr::Vector(x), SExpr::Vector(y)) => {
+            let id = (&**x as *const _ as usize, &**y as *const _ as usize);
+            if Rc::ptr_eq(x, y) || !seen.insert(id) {
+                return true
+            }
+
+            let (xs, ys) = (x.borrow(), y.borrow());
+            return xs.len() == ys.len()
+                && xs.iter().zip(ys.iter()).all(|(x, y)| equal_seen(x, y, seen))
+        },
+        (x@SExpr::Atom(_), y@SExpr::Atom(_)) => return eqv(x, y),
+        (x, y) => return x == y
+    };
+
+    loop {
+        if x.ptr_eq(&y) || !seen.insert((x.id(), y.id())) {
+            return true
+        }
+
+        if !equal_seen(&x.car(), &y.car(), seen) {
+            return false
+        }
+
+        match (x.cdr(), y.cdr()) {
+            (SExpr::Pair(x_), SExpr::Pair(y_)) => {
+                x = x_;
+                y = y_;
+            },
+            (x_, y_) => return equal_seen(&x_, &y_, seen)
+        }
+    }
+}
+
 fn equality<F>(args: Args, mut non_atom: F) -> SResult<SExpr>
 where F: (FnMut(&Args) -> SResult<bool>) {
     if args.len() < 2 {
@@ -37,12 +105,9 @@ where F: (FnMut(&Args) -> SResult<bool>) {
     }
 
     let result = match (&args[0], &args[1]) {
-        (x@SExpr::Atom(Token::Symbol(_)), y@SExpr::Atom(Token::Symbol(_))) => {
-            x.eval_ref(&args.env, |x| {
-                y.eval_ref(&args.env, |y| Ok(x == y))
-            })?
-        },
-        (SExpr::Atom(x), SExpr::Atom(y)) => x == y,
+        (x@SExpr::Atom(_), y@SExpr::Atom(_)) => eqv(x, y),
+        (SExpr::Port(x), SExpr::Port(y)) => x == y,
+        (SExpr::Procedure(x), SExpr::Procedure(y)) => x == y,
         _ => {
             non_atom(&args)?
         }
@@ -0,0 +1,54 @@
+use parser::SExpr;
+use evaluator::Args;
+use condition::{ConditionData, ConditionKind};
+use serr::{SErr, SResult};
+
+pub fn error(args: Args) -> SResult<SExpr> {
+    let (message, irritants) = args.evaled()?.own_one_rest()?;
+    let message = if message.is_str() {
+        message.into_str()?
+    } else {
+        message.to_string()
+    };
+
+    Err(SErr::Raise(SExpr::Condition(ConditionData::new(message, irritants))))
+}
+
+pub fn error_object_qm(args: Args) -> SResult<SExpr> {
+    let obj = args.evaled()?.own_one()?;
+    Ok(sbool!(is_condition(&obj, None)))
+}
+
+pub fn file_error_qm(args: Args) -> SResult<SExpr> {
+    let obj = args.evaled()?.own_one()?;
+    Ok(sbool!(is_condition(&obj, Some(ConditionKind::FileError))))
+}
+
+pub fn read_error_qm(args: Args) -> SResult<SExpr> {
+    let obj = args.evaled()?.own_one()?;
+    Ok(sbool!(is_condition(&obj, Some(ConditionKind::ReadError))))
+}
+
+pub fn error_object_message(args: Args) -> SResult<SExpr> {
+    match args.evaled()?.own_one()? {
+        SExpr::Condition(x) => Ok(sstr!(x.message)),
+        x => bail!(TypeMismatch => "error object", x)
+    }
+}
+
+pub fn error_object_irritants(args: Args) -> SResult<SExpr> {
+    match args.evaled()?.own_one()? {
+        SExpr::Condition(x) => Ok(SExpr::list_from(x.irritants)),
+        x => bail!(TypeMismatch => "error object", x)
+    }
+}
+
+//
+// Helpers
+//
+fn is_condition(obj: &SExpr, kind: Option<ConditionKind>) -> bool {
+    match obj {
+        SExpr::Condition(x) => kind.is_none_or(|k| x.kind == k),
+        _ => false
+    }
+}
@@ -0,0 +1,204 @@
+use parser::SExpr;
+use evaluator::Args;
+use env::EnvRef;
+use hashtable::{HashTableData, Equivalence, hash_eqv, hash_equal};
+use primitives::equivalence::builtin_predicate;
+use serr::{SErr, SResult};
+
+//
+// Helpers
+//
+fn as_hash_table(x: &SExpr) -> SResult<&HashTableData> {
+    match x {
+        SExpr::HashTable(x) => Ok(x),
+        x => bail!(TypeMismatch => "hash table", x)
+    }
+}
+
+/// Tables made with `eq?`, `eqv?`, `equal?` or `string=?` are hashed
+/// natively, other equality procedures need a hash procedure to be fast.
+fn equivalence(same: SExpr, hash: Option<SExpr>, env: &EnvRef) -> SResult<Equivalence> {
+    same.as_proc()?;
+    match builtin_predicate(&same, env) {
+        Some("eq?") | Some("eqv?") => return Ok(Equivalence::Eqv),
+        Some("equal?") | Some("string=?") => return Ok(Equivalence::Equal),
+        _ => ()
+    }
+
+    if let Some(ref hash) = hash {
+        hash.as_proc()?;
+    }
+    Ok(Equivalence::Custom(same, hash))
+}
+
+/// Makes a hash an exact non-negative integer, below `bound` if it's given.
+fn hash_result(hash: u64, bound: Option<SExpr>) -> SResult<SExpr> {
+    let hash = hash >> 1;
+    match bound {
+        Some(x) => {
+            let bound = x.as_int()?;
+            if bound <= 0 {
+                bail!(TypeMismatch => "positive integer", x)
+            }
+            Ok(sint!((hash % bound as u64) as i64))
+        },
+        None => Ok(sint!(hash as i64))
+    }
+}
+
+//
+// Functions
+//
+
+/// (make-hash-table [equality [hash]]), the equality defaults to `equal?`.
+pub fn make_hash_table(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let mut iter = args.evaled()?.into_iter();
+    let equivalence = match iter.next() {
+        Some(same) => equivalence(same, iter.next(), &env)?,
+        None => Equivalence::Equal
+    };
+
+    Ok(SExpr::HashTable(HashTableData::new(equivalence)))
+}
+
+pub fn hash_table_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(matches!(args.evaled()?.own_one()?, SExpr::HashTable(_))))
+}
+
+pub fn hash_table_ref_default(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (table, key, default) = args.evaled()?.own_three()?;
+    Ok(as_hash_table(&table)?.get(&key, &env)?.unwrap_or(default))
+}
+
+/// What `hash-table-ref` does after the lookup, the evaluator makes the
+/// calls, see `evaluator::Control::HashTableRef`.
+pub enum Lookup {
+    /// The value of the key, with the `success` procedure to call with it.
+    Found(SExpr, Option<SExpr>),
+    /// The key is missing, calls the `failure` thunk.
+    Missing(SExpr),
+}
+
+/// (hash-table-ref table key [failure [success]]), a missing key is an
+/// error when there's no `failure` thunk.
+pub fn hash_table_ref(args: Args) -> SResult<Lookup> {
+    let env = args.env();
+    let (table, rest) = args.evaled()?.own_one_rest()?;
+    if rest.is_empty() || rest.len() > 3 {
+        return Err(SErr::WrongArgCount(2, rest.len() + 1))
+    }
+
+    let mut rest = rest.into_iter();
+    let key = rest.next().unwrap();
+    let failure = rest.next().filter(|x| x.to_bool());
+    let success = rest.next();
+    match (as_hash_table(&table)?.get(&key, &env)?, failure) {
+        (Some(value), _) => Ok(Lookup::Found(value, success)),
+        (None, Some(failure)) => Ok(Lookup::Missing(failure)),
+        (None, None) => bail!("hash-table-ref: key not found: {}", key)
+    }
+}
+
+pub fn hash_table_contains_qm(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (table, key) = args.evaled()?.own_two()?;
+    Ok(sbool!(as_hash_table(&table)?.get(&key, &env)?.is_some()))
+}
+
+/// (hash-table-set! table key value ...) takes any number of keys and
+/// values.
+pub fn hash_table_set_em(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (table, rest) = args.evaled()?.own_one_rest()?;
+    let table = as_hash_table(&table)?;
+    if rest.len() % 2 != 0 {
+        bail!("hash-table-set!: a key is missing its value")
+    }
+
+    let mut iter = rest.into_iter();
+    while let (Some(key), Some(value)) = (iter.next(), iter.next()) {
+        table.set(key, value, &env)?;
+    }
+    Ok(SExpr::Unspecified)
+}
+
+/// (hash-table-delete! table key ...) returns how many keys were deleted.
+pub fn hash_table_delete_em(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (table, keys) = args.evaled()?.own_one_rest()?;
+    let table = as_hash_table(&table)?;
+
+    let mut deleted = 0;
+    for key in keys {
+        if table.delete(&key, &env)? {
+            deleted += 1;
+        }
+    }
+    Ok(sint!(deleted))
+}
+
+pub fn hash_table_size(args: Args) -> SResult<SExpr> {
+    let table = args.evaled()?.own_one()?;
+    Ok(sint!(as_hash_table(&table)?.count() as i64))
+}
+
+pub fn hash_table_keys(args: Args) -> SResult<SExpr> {
+    let table = args.evaled()?.own_one()?;
+    let keys = as_hash_table(&table)?.entries()
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    Ok(SExpr::list_from(keys))
+}
+
+pub fn hash_table_values(args: Args) -> SResult<SExpr> {
+    let table = args.evaled()?.own_one()?;
+    let values = as_hash_table(&table)?.entries()
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect();
+    Ok(SExpr::list_from(values))
+}
+
+pub fn hash_table_to_alist(args: Args) -> SResult<SExpr> {
+    let table = args.evaled()?.own_one()?;
+    let entries = as_hash_table(&table)?.entries()
+        .into_iter()
+        .map(|(k, v)| SExpr::cons(k, v))
+        .collect();
+    Ok(SExpr::list_from(entries))
+}
+
+pub fn hash_table_copy(args: Args) -> SResult<SExpr> {
+    // The optional mutable? argument doesn't matter, tables are always
+    // mutable.
+    let (table, _) = args.evaled()?.own_one_rest()?;
+    Ok(SExpr::HashTable(as_hash_table(&table)?.copy()))
+}
+
+pub fn hash_table_clear_em(args: Args) -> SResult<SExpr> {
+    let table = args.evaled()?.own_one()?;
+    as_hash_table(&table)?.clear();
+    Ok(SExpr::Unspecified)
+}
+
+/// (hash obj [bound]) hashes like `equal?` tables do.
+pub fn hash(args: Args) -> SResult<SExpr> {
+    let (obj, mut rest) = args.evaled()?.own_one_rest()?;
+    hash_result(hash_equal(&obj), rest.pop())
+}
+
+pub fn string_hash(args: Args) -> SResult<SExpr> {
+    let (string, mut rest) = args.evaled()?.own_one_rest()?;
+    if !string.is_str() {
+        bail!(TypeMismatch => "string", string)
+    }
+    hash_result(hash_equal(&string), rest.pop())
+}
+
+pub fn hash_by_identity(args: Args) -> SResult<SExpr> {
+    let (obj, mut rest) = args.evaled()?.own_one_rest()?;
+    hash_result(hash_eqv(&obj), rest.pop())
+}
@@ -3,6 +3,7 @@ use evaluator::Args;
 use parser::{SExpr, parse_single};
 use port::{PortData, current_input_port, current_output_port};
 use serr::{SErr, SResult};
+use primitives::vector::{index, range};
 
 //
 // Helpers
@@ -21,30 +22,26 @@ fn get_path_from_args(args: Args) -> SResult<String> {
 macro_rules! call_read_fn(
     ($args: ident, $fn: ident) => {{
         if $args.len() == 0 {
-            let (_size, result) = current_input_port().$fn()?;
-            Ok(result)
+            current_input_port().$fn()
         } else {
-            let (_size, result) = $args.evaled()?
+            $args.evaled()?
                 .own_one()?
                 .as_port_mut()?
-                .$fn()?;
-
-            Ok(result)
+                .$fn()
         }
     }};
 );
 
 macro_rules! call_write_fn(
-    ($args: ident, $fn: ident, $thing: expr) => {{
-        if $args.len() <= 1 {
+    ($args: ident, $port_index: expr, $fn: ident, $thing: expr) => {{
+        if $args.len() <= $port_index {
             current_output_port().$fn(&$thing)?;
-        } else if $args.len() == 1 {
-            $args.evaled()?
-                .own_one()?
+        } else if $args.len() == $port_index + 1 {
+            $args.evaled()?[$port_index]
                 .as_port_mut()?
                 .$fn(&$thing)?;
         } else {
-            bail!(WrongArgCount => 1 as usize, $args.len())
+            bail!(WrongArgCount => $port_index + 1 as usize, $args.len())
         }
 
         Ok(SExpr::Unspecified)
@@ -70,41 +67,126 @@ pub fn open_binary_output_file(args: Args) -> SResult<SExpr> {
     Ok(SExpr::Port(PortData::new_binary_file_output(&get_path_from_args(args)?)?))
 }
 
-pub fn read(args: Args) -> SResult<SExpr> {
-    // I just couldn't define this closure as a simple variable
-    macro_rules! parse_chars(() => {
-        |chars| {
-            let mut iter = TokenIterator::new(chars).peekable();
-            Ok(parse_single(&mut iter)?)
-        }
-    };);
-
-    if args.len() == 0 {
-        current_input_port().with_chars(parse_chars!())
-    } else if args.len() == 0 {
-        args.evaled()?
-            .own_one()?
-            .as_port_mut()?
-            .with_chars(parse_chars!())
-    } else {
-        bail!(WrongArgCount => 1 as usize, args.len())
+pub fn open_input_string(args: Args) -> SResult<SExpr> {
+    let string = args.evaled()?.own_one()?.into_str()?;
+    Ok(SExpr::Port(PortData::new_string_input(string)))
+}
+
+pub fn open_output_string(args: Args) -> SResult<SExpr> {
+    if !args.is_empty() {
+        return Err(SErr::WrongArgCount(0, args.len()))
+    }
+    Ok(SExpr::Port(PortData::new_string_output()))
+}
+
+pub fn get_output_string(args: Args) -> SResult<SExpr> {
+    let port = args.evaled()?.own_one()?;
+    Ok(sstr!(port.as_port()?.output_string()?))
+}
+
+pub fn open_input_bytevector(args: Args) -> SResult<SExpr> {
+    let bytes = args.evaled()?.own_one()?.as_bytevector()?.borrow().clone();
+    Ok(SExpr::Port(PortData::new_bytevector_input(bytes)))
+}
+
+pub fn open_output_bytevector(args: Args) -> SResult<SExpr> {
+    if !args.is_empty() {
+        return Err(SErr::WrongArgCount(0, args.len()))
     }
+    Ok(SExpr::Port(PortData::new_bytevector_output()))
+}
+
+pub fn get_output_bytevector(args: Args) -> SResult<SExpr> {
+    let port = args.evaled()?.own_one()?;
+    Ok(SExpr::bytevector_from(port.as_port()?.output_bytes()?))
 }
 
+/// Reads a datum. What the reader looks at past it, like the delimiter
+/// after a symbol, is put back into the port for the next read, also when
+/// the datum is malformed.
+pub fn read(args: Args) -> SResult<SExpr> {
+    let mut port = match args.len() {
+        0 => current_input_port(),
+        1 => args.evaled()?.own_one()?.as_port()?.clone(),
+        n => bail!(WrongArgCount => 1 as usize, n)
+    };
+
+    let (datum, lookahead) = port.with_chars(|chars| {
+        let mut tokens = TokenIterator::new(chars);
+        let datum = match tokens.peek() {
+            Ok(None) => Ok(SExpr::Eof),
+            Ok(Some(_)) => parse_single(&mut tokens).map(SExpr::into_data),
+            Err(e) => Err(e)
+        };
+        Ok((datum, tokens.lookahead()))
+    })?;
+    port.unread(&lookahead)?;
+    datum
+}
+
+// The read functions return the eof object when nothing could be read.
 pub fn read_line(args: Args) -> SResult<SExpr> {
     // I couldn't understand why it can't infer the type of x.
-    let x: SResult<String> = call_read_fn!(args, read_line);
-    Ok(sstr!(x?.trim_end_matches(|c| c == '\n')))
+    let x: SResult<(usize, String)> = call_read_fn!(args, read_line);
+    match x? {
+        (0, _) => Ok(SExpr::Eof),
+        (_, line) => Ok(sstr!(line.trim_end_matches(|c| c == '\n')))
+    }
 }
 
 pub fn read_char(args: Args) -> SResult<SExpr> {
-    let x: SResult<char> = call_read_fn!(args, read_char);
-    Ok(schr!(x?))
+    let x: SResult<(usize, char)> = call_read_fn!(args, read_char);
+    match x? {
+        (0, _) => Ok(SExpr::Eof),
+        (_, chr) => Ok(schr!(chr))
+    }
 }
 
 pub fn read_u8(args: Args) -> SResult<SExpr> {
-    let x: SResult<u8> = call_read_fn!(args, read_u8);
-    Ok(sint!(i64::from(x?)))
+    let x: SResult<(usize, u8)> = call_read_fn!(args, read_u8);
+    match x? {
+        (0, _) => Ok(SExpr::Eof),
+        (_, byte) => Ok(sint!(i64::from(byte)))
+    }
+}
+
+pub fn eof_object(args: Args) -> SResult<SExpr> {
+    if !args.is_empty() {
+        return Err(SErr::WrongArgCount(0, args.len()))
+    }
+    Ok(SExpr::Eof)
+}
+
+pub fn eof_object_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()? == SExpr::Eof))
+}
+
+/// (read-bytevector k [port]) reads at most `k` bytes, the result is
+/// shorter if the port runs out of bytes.
+pub fn read_bytevector(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let k = index(iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?)?;
+    let mut port = iter.next().unwrap_or_else(|| SExpr::Port(current_input_port()));
+
+    let mut bytes = vec![0; k];
+    let size = port.as_port_mut()?.read_bytes(&mut bytes)?;
+    bytes.truncate(size);
+
+    Ok(SExpr::bytevector_from(bytes))
+}
+
+/// (read-bytevector! bytevector [port [start [end]]]) returns the number
+/// of bytes read.
+pub fn read_bytevector_em(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let bv = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    let mut port = iter.next().unwrap_or_else(|| SExpr::Port(current_input_port()));
+
+    let mut bytes = bv.as_bytevector()?.borrow_mut();
+    let (start, end) = range(bytes.len(), iter.next(), iter.next())?;
+    let size = port.as_port_mut()?.read_bytes(&mut bytes[start..end])?;
+
+    Ok(sint!(size as i64))
 }
 
 pub fn read_all(args: Args) -> SResult<SExpr> {
@@ -116,7 +198,7 @@ pub fn read_all(args: Args) -> SResult<SExpr> {
         Ok(sstr!(string))
     } else if port.is_binary() && port.is_input() {
         let (_size, u8s) = port.read_all_u8()?;
-        Ok(SExpr::List(u8s.into_iter().map(|u| sint!(i64::from(u))).collect()))
+        Ok(SExpr::bytevector_from(u8s))
     } else {
         bail!(TypeMismatch => "a textual or binary input port", SExpr::Port(port.clone()))
     }
@@ -125,9 +207,8 @@ pub fn read_all(args: Args) -> SResult<SExpr> {
 pub fn write(args: Args) -> SResult<SExpr> {
     let string = args.get(0)
         .ok_or_else(|| SErr::WrongArgCount(1, 0))?
-        .eval(&args.env)?
         .to_string();
-    call_write_fn!(args, write_string, string)
+    call_write_fn!(args, 1, write_string, string)
 }
 
 pub fn write_string(args: Args) -> SResult<SExpr> {
@@ -135,30 +216,53 @@ pub fn write_string(args: Args) -> SResult<SExpr> {
     // TODO: (write-string string port START END)
     let string = args.get(0)
         .ok_or_else(|| SErr::WrongArgCount(1, 0))?
-        .eval(&args.env)?
+        .clone()
         .into_str()?;
 
-    call_write_fn!(args, write_string, string)
+    call_write_fn!(args, 1, write_string, string)
+}
+
+pub fn write_u8(args: Args) -> SResult<SExpr> {
+    let byte = args.first()
+        .ok_or_else(|| SErr::WrongArgCount(1, 0))?
+        .as_byte()?;
+
+    call_write_fn!(args, 1, write_bytes, [byte])
+}
+
+/// (write-bytevector bytevector [port [start [end]]])
+pub fn write_bytevector(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let bv = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    let mut port = iter.next().unwrap_or_else(|| SExpr::Port(current_output_port()));
+
+    let bytes = bv.as_bytevector()?.borrow();
+    let (start, end) = range(bytes.len(), iter.next(), iter.next())?;
+    port.as_port_mut()?.write_bytes(&bytes[start..end])?;
+
+    Ok(SExpr::Unspecified)
 }
 
 pub fn newline(args: Args) -> SResult<SExpr> {
-    call_write_fn!(args, write_string, "\n")
+    call_write_fn!(args, 0, write_string, "\n")
 }
 
 pub fn display(args: Args) -> SResult<SExpr> {
     let obj = args.get(0)
         .ok_or_else(|| SErr::WrongArgCount(1, 0))?
-        .eval(&args.env)?;
+        .clone();
 
     let string = if obj.is_str() {
         obj.into_str().unwrap()
     } else if obj.is_chr() {
         obj.into_chr().unwrap().to_string()
+    } else if let Ok(symbol) = obj.as_symbol() {
+        symbol.clone()
     } else {
         obj.to_string()
     };
 
-    call_write_fn!(args, write_string, string)
+    call_write_fn!(args, 1, write_string, string)
 }
 
 pub fn close_port(args: Args) -> SResult<SExpr> {
@@ -4,8 +4,6 @@ use parser::SExprs;
 use evaluator::Args;
 use evaluator::Extra;
 use procedure::ProcedureData;
-use env::EnvRef;
-use env::Env;
 use serr::{SErr, SResult};
 
 pub fn define(args: Args) -> SResult<SExpr> {
@@ -22,49 +20,13 @@ pub fn lambda(args: Args) -> SResult<SExpr> {
     ProcedureData::new_compound(params, body, &env)
 }
 
-pub fn apply(args: Args) -> SResult<SExpr> {
+pub fn case_lambda(args: Args) -> SResult<SExpr> {
     let env = args.env();
-    let evaled = args.evaled()?;
-    let (proc, arg_list) = if evaled.len() == 2 {
-         let (proc, arg_list_) = evaled.own_two()?;
-         let arg_list = arg_list_.into_list()?;
-         (proc, arg_list)
-    } else if evaled.len() > 2 {
-        let (proc, list) = evaled.own_one_rest()?;
-        let mut iter = list.into_iter().peekable();
-        let mut arg_list = vec![];
-        while let Some(x) = iter.next() {
-            if iter.peek().is_some() {
-                arg_list.push(x);
-            } else { // last elem
-                arg_list.append(&mut x.into_list()?);
-            }
-        };
-        (proc, arg_list)
-    } else {
-        bail!(WrongArgCount => 2 as usize, evaled.len())
-    };
-
-    // Because the proc will try to reevaluate the arguments,
-    // need to quote them before calling proc.
-    let args_quoted = arg_list.into_iter()
-        .map(|x| quote!(x))
-        .collect();
-
-    proc.as_proc()?.apply(Args::new(args_quoted, &env))
+    ProcedureData::new_case_lambda(args.into_iter().collect(), &env)
 }
 
-pub fn let_(args: Args) -> SResult<SExpr> {
-    let_generic(args, |expr, _, parent_env| expr.eval(parent_env))
-}
-
-pub fn let_star(args: Args) -> SResult<SExpr> {
-    let_generic(args, |expr, env, _| expr.eval(env))
-}
-
-pub fn let_rec(args: Args) -> SResult<SExpr> {
-    // FIXME: (letrec ([x y] [y x]) 3) will fail
-    let_star(args)
+pub fn values(args: Args) -> SResult<SExpr> {
+    Ok(SExpr::values(args.evaled()?.into_iter().collect()))
 }
 
 pub fn quote(args: Args) -> SResult<SExpr> {
@@ -87,7 +49,7 @@ pub fn quasiquote(mut args: Args) -> SResult<SExpr> {
 
     args.extra = Extra::QQLevel(level);
     if level == 1 {
-        eval_unquoted(args)
+        Ok(eval_unquoted(args)?.into_data())
     } else if level > 1 {
         Ok(quasiquote!(eval_unquoted(args)?))
     } else {
@@ -210,36 +172,6 @@ fn env_add(t: EnvAddType, args: Args) -> SResult<SExpr> {
     }
 }
 
-pub fn let_generic<F>(args: Args, mut eval_expr: F) -> SResult<SExpr>
-where F: (FnMut(&SExpr,/*env:*/ &EnvRef,/*parent_env:*/&EnvRef) -> SResult<SExpr>) {
-    let parent_env = args.env();
-    let (bindings, body) = args.own_one_rest()?;
-
-    let env = Env::new(parent_env.clone_ref())
-        .into_ref();
-    let bindings_list = bindings.into_list()?;
-
-    for x in bindings_list {
-        let bind = x.into_list()?;
-        let id = bind.get(0)
-            .ok_or_else(|| SErr::new_expr_not_found("nothing"))?
-            .clone()
-            .into_symbol()?;
-
-        let expr = bind.get(1)
-            .ok_or_else(|| SErr::new_expr_not_found("nothing"))?;
-
-        env.define(id, eval_expr(expr, &env, &parent_env)?);
-    }
-
-    let mut result = None;
-    for expr in body {
-        result = Some(expr.eval(&env));
-    }
-
-    result.unwrap()
-}
-
 pub fn exit(_args: Args) -> SResult<SExpr> {
     ::std::process::exit(0);
 }
@@ -1,37 +1,289 @@
-use parser::SExpr;
-use evaluator::Args;
+use std::collections::HashMap;
+use std::slice;
+
+use parser::{SExpr, SExprs};
+use evaluator::{self, Args};
+use env::EnvRef;
+use hashtable::{hash_eqv, hash_equal};
+use primitives::equivalence::{eqv, equal, builtin_predicate};
+use primitives::numeric::calc;
+use primitives::vector::index;
 use serr::{SErr, SResult};
 
-pub fn cons(args: Args) -> SResult<SExpr> {
-    let (x, xs) = args.evaled()?
-        .own_two()?;
+//
+// Helpers
+//
 
-    let result = match xs {
-        SExpr::List(mut xs) => {
-            xs.insert(0, x);
-            SExpr::List(xs)
-        },
-        SExpr::DottedList(mut xs, y) => {
-            xs.insert(0, x);
-            SExpr::DottedList(xs, y)
-        },
-        y => SExpr::DottedList(vec![x], Box::new(y))
+/// Lists that come from the code are kept as vectors, the procedures here
+/// walk pairs.
+fn as_pairs(x: SExpr) -> SExpr {
+    match x {
+        SExpr::List(ref xs) if xs.is_empty() => x,
+        x@SExpr::List(_) | x@SExpr::DottedList(_, _) => x.into_data(),
+        x => x
+    }
+}
+
+/// Walks the elements of a list without copying it, so circular lists can
+/// be walked too. Stops at the first cdr that isn't a pair.
+#[derive(Debug, Clone)]
+pub struct Elements(SExpr);
+
+impl Iterator for Elements {
+    type Item = SExpr;
+
+    fn next(&mut self) -> Option<SExpr> {
+        let pair = match self.0 {
+            SExpr::Pair(ref x) => x.clone(),
+            _ => return None
+        };
+        self.0 = pair.cdr();
+        Some(pair.car())
+    }
+}
+
+fn elements(list: SExpr) -> SResult<Elements> {
+    match as_pairs(list) {
+        x@SExpr::Pair(_) => Ok(Elements(x)),
+        ref x if x.is_null() => Ok(Elements(x.clone())),
+        x => bail!(TypeMismatch => "list", x)
+    }
+}
+
+/// The elements of several lists side by side, until the shortest one
+/// runs out.
+#[derive(Debug, Clone)]
+pub struct Rows(Vec<Elements>);
+
+impl Iterator for Rows {
+    type Item = SExprs;
+
+    fn next(&mut self) -> Option<SExprs> {
+        self.0.iter_mut().map(|x| x.next()).collect()
+    }
+}
+
+pub fn rows(lists: SExprs) -> SResult<Rows> {
+    if lists.is_empty() {
+        return Err(SErr::WrongArgCount(2, 1))
+    }
+    Ok(Rows(lists.into_iter().map(elements).collect::<SResult<_>>()?))
+}
+
+/// The cdrs of `lists` if all of them are pairs.
+fn cdrs(lists: &[SExpr]) -> Option<SExprs> {
+    lists.iter()
+        .map(|x| match x {
+            SExpr::Pair(x) => Some(x.cdr()),
+            _ => None
+        })
+        .collect()
+}
+
+/// Splits the arguments of procedures like `(map f list1 list2 ...)`.
+fn procedure_and_lists(args: Args) -> SResult<(SExpr, SExprs, EnvRef)> {
+    let env = args.env();
+    let (procedure, lists) = args.evaled()?.own_one_rest()?;
+    procedure.as_proc()?;
+    Ok((procedure, lists, env))
+}
+
+/// Splits the arguments of procedures like `(filter pred list)`.
+fn procedure_and_list(args: Args) -> SResult<(SExpr, SExpr, EnvRef)> {
+    let env = args.env();
+    let (procedure, list) = args.evaled()?.own_two()?;
+    procedure.as_proc()?;
+    Ok((procedure, list, env))
+}
+
+fn call(procedure: &SExpr, args: SExprs, env: &EnvRef) -> SResult<SExpr> {
+    evaluator::apply(procedure, args, env)
+}
+
+fn test(procedure: &SExpr, args: SExprs, env: &EnvRef) -> SResult<bool> {
+    Ok(call(procedure, args, env)?.to_bool())
+}
+
+/// The equality used by `member`, `delete`, `assoc`, the `lset`
+/// procedures and the like. `eq?`, `eqv?` and `equal?` are called natively.
+enum Same {
+    Eqv,
+    Equal,
+    Procedure(SExpr, EnvRef),
+}
+
+impl Same {
+    /// It's `equal?` when no procedure is given.
+    fn new(procedure: Option<SExpr>, env: &EnvRef) -> SResult<Same> {
+        let procedure = match procedure {
+            Some(x) => x,
+            None => return Ok(Same::Equal)
+        };
+
+        procedure.as_proc()?;
+        match builtin_predicate(&procedure, env) {
+            Some("eq?") | Some("eqv?") => Ok(Same::Eqv),
+            Some("equal?") => Ok(Same::Equal),
+            _ => Ok(Same::Procedure(procedure, env.clone_ref()))
+        }
+    }
+
+    fn test(&self, x: &SExpr, y: &SExpr) -> SResult<bool> {
+        match self {
+            Same::Eqv => Ok(eqv(x, y)),
+            Same::Equal => Ok(equal(x, y)),
+            Same::Procedure(f, env) => test(f, vec![x.clone(), y.clone()], env)
+        }
+    }
+
+    /// Whether some `y` of `ys` is the same as `x`, tested as `(= x y)`.
+    fn any(&self, x: &SExpr, ys: &[SExpr]) -> SResult<bool> {
+        for y in ys {
+            if self.test(x, y)? {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Walks `k` pairs of `list`, returning their cars and what follows them.
+fn split(list: SExpr, k: usize) -> SResult<(SExprs, SExpr)> {
+    let mut xs = vec![];
+    let mut rest = as_pairs(list);
+    for _ in 0..k {
+        let pair = match rest {
+            SExpr::Pair(ref x) => x.clone(),
+            x => bail!(TypeMismatch => "longer list", x)
+        };
+        xs.push(pair.car());
+        rest = pair.cdr();
+    }
+    Ok((xs, rest))
+}
+
+/// Number of pairs in a list, `None` if it's circular.
+fn pair_count(list: &SExpr) -> Option<usize> {
+    match as_pairs(list.clone()) {
+        SExpr::Pair(x) => x.elements().map(|(xs, _)| xs.len()),
+        _ => Some(0)
+    }
+}
+
+/// The last pair of a non-empty list.
+fn last_pair_of(list: SExpr) -> SResult<SExpr> {
+    let mut pair = match as_pairs(list) {
+        SExpr::Pair(x) => x,
+        x => bail!(TypeMismatch => "pair", x)
     };
+    while let SExpr::Pair(next) = pair.cdr() {
+        pair = next;
+    }
+    Ok(SExpr::Pair(pair))
+}
+
+fn list_arg(list: SExpr) -> SResult<SExprs> {
+    as_pairs(list).into_list()
+}
+
+/// The elements of `xs` that aren't the same as an element before them.
+fn unique(xs: SExprs, same: &Same) -> SResult<SExprs> {
+    let mut result: SExprs = vec![];
+    match same {
+        Same::Procedure(_, _) => {
+            for x in xs {
+                let mut seen = false;
+                for y in &result {
+                    if same.test(y, &x)? {
+                        seen = true;
+                        break
+                    }
+                }
+                if !seen {
+                    result.push(x);
+                }
+            }
+        },
+        _ => {
+            // Builtin equalities have hashes, which keeps long lists fast.
+            let mut buckets: HashMap<u64, SExprs> = HashMap::new();
+            for x in xs {
+                let hash = match same {
+                    Same::Eqv => hash_eqv(&x),
+                    _ => hash_equal(&x)
+                };
+                let bucket = buckets.entry(hash).or_default();
+                if !same.any(&x, bucket)? {
+                    bucket.push(x.clone());
+                    result.push(x);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Splits the arguments of the `lset` procedures, `(lset-op = list ...)`.
+fn same_and_lists(args: Args) -> SResult<(Same, Vec<SExprs>)> {
+    let env = args.env();
+    let (same, lists) = args.evaled()?.own_one_rest()?;
+    let same = Same::new(Some(same), &env)?;
+    let lists = lists.into_iter().map(list_arg).collect::<SResult<_>>()?;
+    Ok((same, lists))
+}
 
+/// The elements of `xs` that are, or aren't, in some list of `others`.
+fn sift(same: &Same, xs: SExprs, others: &[SExprs], in_any: bool) -> SResult<SExprs> {
+    let mut result = vec![];
+    for x in xs {
+        let mut found = false;
+        for ys in others {
+            if same.any(&x, ys)? {
+                found = true;
+                break
+            }
+        }
+        if found == in_any {
+            result.push(x);
+        }
+    }
     Ok(result)
 }
 
+fn append_all(lists: SExprs) -> SResult<SExpr> {
+    let mut lists = lists.into_iter();
+    let last = match lists.next_back() {
+        Some(x) => x,
+        None => return Ok(SExpr::List(vec![]))
+    };
+
+    let mut elements = vec![];
+    for list in lists {
+        elements.append(&mut list.into_list()?);
+    }
+
+    Ok(SExpr::dotted_list_from(elements, last))
+}
+
+//
+// Functions
+//
+
+pub fn cons(args: Args) -> SResult<SExpr> {
+    let (x, xs) = args.evaled()?
+        .own_two()?;
+
+    Ok(SExpr::cons(x, xs))
+}
+
 pub fn car(args: Args) -> SResult<SExpr> {
     let xs = args.evaled()?
         .own_one()?;
 
     match xs {
-        SExpr::List(ys) | SExpr::DottedList(ys, _) => {
-            ys.into_iter()
-                .next()
-                .ok_or_else(|| SErr::new_generic("car: empty list"))
-        },
-        x => bail!(UnexpectedForm => x)
+        SExpr::Pair(x) => Ok(x.car()),
+        ref x if x.is_null() => bail!("car: empty list"),
+        x => bail!(TypeMismatch => "pair", x)
     }
 }
 
@@ -39,25 +291,39 @@ pub fn cdr(args: Args) -> SResult<SExpr> {
     let xs = args.evaled()?
         .own_one()?;
 
-    let result = match xs {
-        SExpr::List(ys) => {
-            let mut iter = ys.into_iter();
-            iter.next() // Skip 1
-                .ok_or_else(|| SErr::new_generic("cdr: empty list"))?;
+    match xs {
+        SExpr::Pair(x) => Ok(x.cdr()),
+        ref x if x.is_null() => bail!("cdr: empty list"),
+        x => bail!(TypeMismatch => "pair", x)
+    }
+}
 
-            SExpr::List(iter.collect())
-        },
-        SExpr::DottedList(ys, y) => {
-            if ys.len() == 1 {
-                *y
-            } else {
-                SExpr::DottedList(ys.into_iter().skip(1).collect(), y)
-            }
-        },
-        x => bail!(UnexpectedForm => x)
-    };
+pub fn set_car_em(args: Args) -> SResult<SExpr> {
+    let (pair, x) = args.evaled()?
+        .own_two()?;
 
-    Ok(result)
+    pair.as_pair()?.set_car(x);
+    Ok(SExpr::Unspecified)
+}
+
+pub fn set_cdr_em(args: Args) -> SResult<SExpr> {
+    let (pair, x) = args.evaled()?
+        .own_two()?;
+
+    pair.as_pair()?.set_cdr(x);
+    Ok(SExpr::Unspecified)
+}
+
+pub fn pair_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.is_pair()))
+}
+
+pub fn null_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.is_null()))
+}
+
+pub fn list_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.is_proper_list()))
 }
 
 pub fn list_copy(args: Args) -> SResult<SExpr> {
@@ -86,39 +352,770 @@ pub fn list_copy(args: Args) -> SResult<SExpr> {
         bail!(WrongArgCount => 3 as usize, evaled.len())
     };
 
-    Ok(SExpr::List(list))
+    Ok(SExpr::list_from(list))
 }
 
+/// Copies every list but the last one, which becomes the tail of the
+/// result.
 pub fn append(args: Args) -> SResult<SExpr> {
-    let len = args.len();
-    if len == 1 {
-        return args[0].eval(&args.env)
-    }
-
-    let (xs, rest) = args.evaled()?
-        .own_one_rest()?;
-    let mut list = xs.into_list()?;
-    let iter = rest.into_iter();
-
-    for (i, expr) in iter.enumerate() {
-        // list is the first element, and i starts from 0, so -2
-        if i == len - 2 {
-            match expr {
-                SExpr::List(mut xs) => {
-                    list.append(&mut xs);
-                    return Ok(SExpr::List(list))
-                },
-                SExpr::DottedList(mut xs, y) => {
-                    list.append(&mut xs);
-                    return Ok(SExpr::dottedlist(list, *y))
-                },
-                x => return Ok(SExpr::dottedlist(list, x))
+    append_all(args.evaled()?.into_iter().collect())
+}
+
+//
+// SRFI-1
+//
+
+// Constructors
+
+/// (xcons a b) is `(cons b a)`.
+pub fn xcons(args: Args) -> SResult<SExpr> {
+    let (x, y) = args.evaled()?.own_two()?;
+    Ok(SExpr::cons(y, x))
+}
+
+/// (cons* x ... tail), like `list` but the last argument is the tail.
+pub fn cons_star(args: Args) -> SResult<SExpr> {
+    let mut xs: SExprs = args.evaled()?.into_iter().collect();
+    let tail = xs.pop().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    Ok(SExpr::dotted_list_from(xs, tail))
+}
+
+/// (make-list n [fill])
+pub fn make_list(args: Args) -> SResult<SExpr> {
+    let (n, mut rest) = args.evaled()?.own_one_rest()?;
+    let fill = rest.pop().unwrap_or(SExpr::Unspecified);
+    Ok(SExpr::list_from(vec![fill; index(n)?]))
+}
+
+/// (list-tabulate n proc) is `((proc 0) ... (proc (- n 1)))`.
+pub fn list_tabulate(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (n, procedure) = args.evaled()?.own_two()?;
+    let xs = (0..index(n)?)
+        .map(|i| call(&procedure, vec![sint!(i as i64)], &env))
+        .collect::<SResult<_>>()?;
+    Ok(SExpr::list_from(xs))
+}
+
+/// (iota count [start [step]]) is `(start (+ start step) ...)`.
+pub fn iota(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (count, rest) = args.evaled()?.own_one_rest()?;
+    let mut rest = rest.into_iter();
+    let start = rest.next().unwrap_or(sint!(0));
+    let step = rest.next().unwrap_or(sint!(1));
+
+    let xs = (0..index(count)?)
+        .map(|i| {
+            if i == 0 {
+                return Ok(start.clone())
+            }
+            let offset = calc('*', Args::evaluated(vec![sint!(i as i64), step.clone()], &env))?;
+            calc('+', Args::evaluated(vec![start.clone(), offset], &env))
+        })
+        .collect::<SResult<_>>()?;
+    Ok(SExpr::list_from(xs))
+}
+
+pub fn circular_list(args: Args) -> SResult<SExpr> {
+    let xs: SExprs = args.evaled()?.into_iter().collect();
+    if xs.is_empty() {
+        return Err(SErr::WrongArgCount(1, 0))
+    }
+
+    let list = SExpr::list_from(xs);
+    last_pair_of(list.clone())?.as_pair()?.set_cdr(list.clone());
+    Ok(list)
+}
+
+// Predicates
+
+pub fn circular_list_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(pair_count(&args.evaled()?.own_one()?).is_none()))
+}
+
+/// Lists that end with something other than `()`, including atoms.
+pub fn dotted_list_qm(args: Args) -> SResult<SExpr> {
+    let x = as_pairs(args.evaled()?.own_one()?);
+    let dotted = match x {
+        SExpr::Pair(ref pair) => match pair.elements() {
+            Some((_, tail)) => !tail.is_null(),
+            None => false
+        },
+        ref x => !x.is_null()
+    };
+    Ok(sbool!(dotted))
+}
+
+pub fn not_pair_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(!args.evaled()?.own_one()?.is_pair()))
+}
+
+/// Like `null?`, but only lists are allowed.
+pub fn null_list_qm(args: Args) -> SResult<SExpr> {
+    match args.evaled()?.own_one()? {
+        ref x if x.is_null() => Ok(sbool!(true)),
+        ref x if x.is_pair() => Ok(sbool!(false)),
+        x => bail!(TypeMismatch => "list", x)
+    }
+}
+
+/// (list= elt= list ...) compares each list with the next one.
+pub fn list_eq(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (same, lists) = args.evaled()?.own_one_rest()?;
+    let same = Same::new(Some(same), &env)?;
+    let lists = lists.into_iter().map(list_arg).collect::<SResult<Vec<_>>>()?;
+
+    for pair in lists.windows(2) {
+        let (xs, ys) = (&pair[0], &pair[1]);
+        if xs.len() != ys.len() {
+            return Ok(sbool!(false))
+        }
+        for (x, y) in xs.iter().zip(ys) {
+            if !same.test(x, y)? {
+                return Ok(sbool!(false))
             }
-        } else {
-            list.append(&mut expr.into_list()?);
         }
     }
+    Ok(sbool!(true))
+}
+
+// Selectors
+
+/// `first` through `tenth`, `(nth 0 args)` is `first`.
+pub fn nth(n: usize, args: Args) -> SResult<SExpr> {
+    let list = args.evaled()?.own_one()?;
+    let (_, rest) = split(list, n)?;
+    match rest {
+        SExpr::Pair(x) => Ok(x.car()),
+        x => bail!(TypeMismatch => "longer list", x)
+    }
+}
+
+/// (car+cdr pair) returns both as two values.
+pub fn car_plus_cdr(args: Args) -> SResult<SExpr> {
+    let pair = as_pairs(args.evaled()?.own_one()?);
+    let pair = pair.as_pair()?;
+    Ok(SExpr::values(vec![pair.car(), pair.cdr()]))
+}
+
+pub fn list_ref(args: Args) -> SResult<SExpr> {
+    let (list, k) = args.evaled()?.own_two()?;
+    let (_, rest) = split(list, index(k)?)?;
+    match rest {
+        SExpr::Pair(x) => Ok(x.car()),
+        x => bail!(TypeMismatch => "longer list", x)
+    }
+}
 
-    // Just for satisfying compiler
+/// (take list k) copies the first `k` elements.
+pub fn take(args: Args) -> SResult<SExpr> {
+    let (list, k) = args.evaled()?.own_two()?;
+    Ok(SExpr::list_from(split(list, index(k)?)?.0))
+}
+
+/// (drop list k) shares the rest of the list, it's also `list-tail`.
+pub fn drop(args: Args) -> SResult<SExpr> {
+    let (list, k) = args.evaled()?.own_two()?;
+    Ok(split(list, index(k)?)?.1)
+}
+
+/// (take-right list k) shares the last `k` pairs.
+pub fn take_right(args: Args) -> SResult<SExpr> {
+    let (list, k) = args.evaled()?.own_two()?;
+    let list = as_pairs(list);
+    let mut lead = split(list.clone(), index(k)?)?.1;
+    let mut lag = list;
+    while let SExpr::Pair(x) = lead {
+        lead = x.cdr();
+        lag = lag.as_pair()?.cdr();
+    }
+    Ok(lag)
+}
+
+/// (drop-right list k) copies all but the last `k` elements.
+pub fn drop_right(args: Args) -> SResult<SExpr> {
+    let (list, k) = args.evaled()?.own_two()?;
+    let k = index(k)?;
+    let count = match pair_count(&list) {
+        Some(x) if x >= k => x,
+        _ => bail!(TypeMismatch => "longer list", list)
+    };
+    Ok(SExpr::list_from(split(list, count - k)?.0))
+}
+
+/// (split-at list k) returns `(take list k)` and `(drop list k)`.
+pub fn split_at(args: Args) -> SResult<SExpr> {
+    let (list, k) = args.evaled()?.own_two()?;
+    let (xs, rest) = split(list, index(k)?)?;
+    Ok(SExpr::values(vec![SExpr::list_from(xs), rest]))
+}
+
+pub fn last(args: Args) -> SResult<SExpr> {
+    let list = args.evaled()?.own_one()?;
+    Ok(last_pair_of(list)?.as_pair()?.car())
+}
+
+pub fn last_pair(args: Args) -> SResult<SExpr> {
+    last_pair_of(args.evaled()?.own_one()?)
+}
+
+// Miscellaneous
+
+pub fn length(args: Args) -> SResult<SExpr> {
+    let list = args.evaled()?.own_one()?;
+    Ok(sint!(list_arg(list)?.len() as i64))
+}
+
+/// Like `length`, but it's `#f` for circular lists.
+pub fn length_plus(args: Args) -> SResult<SExpr> {
+    match pair_count(&args.evaled()?.own_one()?) {
+        Some(x) => Ok(sint!(x as i64)),
+        None => Ok(sbool!(false))
+    }
+}
+
+/// (concatenate lists) is `(apply append lists)`.
+pub fn concatenate(args: Args) -> SResult<SExpr> {
+    append_all(list_arg(args.evaled()?.own_one()?)?)
+}
+
+pub fn reverse(args: Args) -> SResult<SExpr> {
+    let mut xs = list_arg(args.evaled()?.own_one()?)?;
+    xs.reverse();
+    Ok(SExpr::list_from(xs))
+}
+
+/// (append-reverse rev-head tail) is `(append (reverse rev-head) tail)`.
+pub fn append_reverse(args: Args) -> SResult<SExpr> {
+    let (head, tail) = args.evaled()?.own_two()?;
+    let mut xs = list_arg(head)?;
+    xs.reverse();
+    Ok(SExpr::dotted_list_from(xs, tail))
+}
+
+/// (zip list1 list2 ...) is `(map list list1 list2 ...)`.
+pub fn zip(args: Args) -> SResult<SExpr> {
+    let lists = args.evaled()?.into_iter().collect();
+    let xs = rows(lists)?.map(SExpr::list_from).collect();
+    Ok(SExpr::list_from(xs))
+}
+
+/// `unzip1` through `unzip5`, which return the first `n` elements of each
+/// list as `n` lists.
+pub fn unzip(n: usize, args: Args) -> SResult<SExpr> {
+    let lists = list_arg(args.evaled()?.own_one()?)?;
+    let mut columns = vec![vec![]; n];
+    for list in lists {
+        for (column, x) in columns.iter_mut().zip(split(list, n)?.0) {
+            column.push(x);
+        }
+    }
+    Ok(SExpr::values(columns.into_iter().map(SExpr::list_from).collect()))
+}
+
+/// (count pred list1 list2 ...)
+pub fn count(args: Args) -> SResult<SExpr> {
+    let (pred, lists, env) = procedure_and_lists(args)?;
+    let mut count = 0;
+    for row in rows(lists)? {
+        if test(&pred, row, &env)? {
+            count += 1;
+        }
+    }
+    Ok(sint!(count))
+}
+
+// Folds, unfolds and maps
+//
+// `map`, `for-each`, `fold`, `any` and `every` call their procedure from
+// the evaluator, see `evaluator::Traversal`.
+
+/// Like `fold`, but from right to left.
+pub fn fold_right(args: Args) -> SResult<SExpr> {
+    let (kons, rest, env) = procedure_and_lists(args)?;
+    let (mut acc, lists) = SExpr::List(rest).list_own_one_rest()?;
+    let rows: Vec<SExprs> = rows(lists)?.collect();
+    for mut row in rows.into_iter().rev() {
+        row.push(acc);
+        acc = call(&kons, row, &env)?;
+    }
+    Ok(acc)
+}
+
+/// Like `fold`, but `kons` gets the pairs instead of the elements. The
+/// next pairs are taken before calling `kons`, so it can change their cdrs.
+pub fn pair_fold(args: Args) -> SResult<SExpr> {
+    let (kons, rest, env) = procedure_and_lists(args)?;
+    let (mut acc, lists) = SExpr::List(rest).list_own_one_rest()?;
+    let mut lists: SExprs = lists.into_iter().map(as_pairs).collect();
+    while let Some(next) = cdrs(&lists) {
+        lists.push(acc);
+        acc = call(&kons, lists, &env)?;
+        lists = next;
+    }
+    Ok(acc)
+}
+
+pub fn pair_fold_right(args: Args) -> SResult<SExpr> {
+    let (kons, rest, env) = procedure_and_lists(args)?;
+    let (mut acc, lists) = SExpr::List(rest).list_own_one_rest()?;
+    let mut tails = vec![];
+    let mut lists: SExprs = lists.into_iter().map(as_pairs).collect();
+    while let Some(next) = cdrs(&lists) {
+        tails.push(lists);
+        lists = next;
+    }
+    for mut row in tails.into_iter().rev() {
+        row.push(acc);
+        acc = call(&kons, row, &env)?;
+    }
+    Ok(acc)
+}
+
+/// (reduce f ridentity list) is `(fold f (car list) (cdr list))`, or
+/// `ridentity` if the list is empty.
+pub fn reduce(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (f, ridentity, list) = args.evaled()?.own_three()?;
+    f.as_proc()?;
+    let mut xs = elements(list)?;
+    let mut acc = match xs.next() {
+        Some(x) => x,
+        None => return Ok(ridentity)
+    };
+    for x in xs {
+        acc = call(&f, vec![x, acc], &env)?;
+    }
+    Ok(acc)
+}
+
+pub fn reduce_right(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (f, ridentity, list) = args.evaled()?.own_three()?;
+    f.as_proc()?;
+    let mut xs = list_arg(list)?;
+    let mut acc = match xs.pop() {
+        Some(x) => x,
+        None => return Ok(ridentity)
+    };
+    for x in xs.into_iter().rev() {
+        acc = call(&f, vec![x, acc], &env)?;
+    }
+    Ok(acc)
+}
+
+/// (unfold stop? mapper successor seed [tail-gen]) builds a list from
+/// `(mapper seed)`, `(mapper (successor seed))`, ... until `(stop? seed)`.
+pub fn unfold(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (stop, rest) = args.evaled()?.own_one_rest()?;
+    if rest.len() < 3 || rest.len() > 4 {
+        return Err(SErr::WrongArgCount(4, rest.len() + 1))
+    }
+    let mut rest = rest.into_iter();
+    let (mapper, successor, mut seed) = (rest.next().unwrap(), rest.next().unwrap(), rest.next().unwrap());
+    let tail_gen = rest.next();
+
+    let mut xs = vec![];
+    while !test(&stop, vec![seed.clone()], &env)? {
+        xs.push(call(&mapper, vec![seed.clone()], &env)?);
+        seed = call(&successor, vec![seed], &env)?;
+    }
+    let tail = match tail_gen {
+        Some(f) => call(&f, vec![seed], &env)?,
+        None => SExpr::List(vec![])
+    };
+    Ok(SExpr::dotted_list_from(xs, tail))
+}
+
+/// (unfold-right stop? mapper successor seed [tail]) builds the list
+/// backwards, so the first seed's element ends up last.
+pub fn unfold_right(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (stop, rest) = args.evaled()?.own_one_rest()?;
+    if rest.len() < 3 || rest.len() > 4 {
+        return Err(SErr::WrongArgCount(4, rest.len() + 1))
+    }
+    let mut rest = rest.into_iter();
+    let (mapper, successor, mut seed) = (rest.next().unwrap(), rest.next().unwrap(), rest.next().unwrap());
+    let mut acc = rest.next().unwrap_or(SExpr::List(vec![]));
+
+    while !test(&stop, vec![seed.clone()], &env)? {
+        acc = SExpr::cons(call(&mapper, vec![seed.clone()], &env)?, acc);
+        seed = call(&successor, vec![seed], &env)?;
+    }
+    Ok(acc)
+}
+
+/// (append-map f list1 list2 ...) is `(apply append (map f list1 ...))`.
+pub fn append_map(args: Args) -> SResult<SExpr> {
+    let (f, lists, env) = procedure_and_lists(args)?;
+    let xs = rows(lists)?
+        .map(|row| call(&f, row, &env))
+        .collect::<SResult<_>>()?;
+    append_all(xs)
+}
+
+/// Like `for-each`, but `f` gets the pairs instead of the elements.
+pub fn pair_for_each(args: Args) -> SResult<SExpr> {
+    let (f, lists, env) = procedure_and_lists(args)?;
+    let mut lists: SExprs = lists.into_iter().map(as_pairs).collect();
+    while let Some(next) = cdrs(&lists) {
+        call(&f, lists, &env)?;
+        lists = next;
+    }
     Ok(SExpr::Unspecified)
 }
+
+/// Like `map`, but the false results are left out.
+pub fn filter_map(args: Args) -> SResult<SExpr> {
+    let (f, lists, env) = procedure_and_lists(args)?;
+    let mut xs = vec![];
+    for row in rows(lists)? {
+        let x = call(&f, row, &env)?;
+        if x.to_bool() {
+            xs.push(x);
+        }
+    }
+    Ok(SExpr::list_from(xs))
+}
+
+// Filtering and partitioning
+
+/// The elements that `pred` is true, and false, for.
+fn partition_list(args: Args) -> SResult<(SExprs, SExprs)> {
+    let (pred, list, env) = procedure_and_list(args)?;
+    let (mut yes, mut no) = (vec![], vec![]);
+    for x in elements(list)? {
+        if test(&pred, vec![x.clone()], &env)? {
+            yes.push(x);
+        } else {
+            no.push(x);
+        }
+    }
+    Ok((yes, no))
+}
+
+pub fn filter(args: Args) -> SResult<SExpr> {
+    Ok(SExpr::list_from(partition_list(args)?.0))
+}
+
+pub fn remove(args: Args) -> SResult<SExpr> {
+    Ok(SExpr::list_from(partition_list(args)?.1))
+}
+
+/// (partition pred list) returns what `filter` and `remove` would.
+pub fn partition(args: Args) -> SResult<SExpr> {
+    let (yes, no) = partition_list(args)?;
+    Ok(SExpr::values(vec![SExpr::list_from(yes), SExpr::list_from(no)]))
+}
+
+// Searching
+
+/// The first pair of `list` whose car satisfies `pred`.
+fn find_pair<F>(list: SExpr, mut pred: F) -> SResult<Option<SExpr>>
+    where F: FnMut(&SExpr) -> SResult<bool>
+{
+    let mut rest = as_pairs(list);
+    while let SExpr::Pair(pair) = rest {
+        if pred(&pair.car())? {
+            return Ok(Some(SExpr::Pair(pair)))
+        }
+        rest = pair.cdr();
+    }
+    Ok(None)
+}
+
+fn member_with(args: Args, default: Same) -> SResult<SExpr> {
+    let env = args.env();
+    let (x, rest) = args.evaled()?.own_one_rest()?;
+    let mut rest = rest.into_iter();
+    let list = rest.next().ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+    let same = match rest.next() {
+        Some(f) => Same::new(Some(f), &env)?,
+        None => default
+    };
+
+    Ok(find_pair(list, |y| same.test(&x, y))?.unwrap_or(sbool!(false)))
+}
+
+/// (member x list [=]) returns the first pair of `list` whose car is the
+/// same as `x`.
+pub fn member(args: Args) -> SResult<SExpr> {
+    member_with(args, Same::Equal)
+}
+
+pub fn memv(args: Args) -> SResult<SExpr> {
+    member_with(args, Same::Eqv)
+}
+
+pub fn find(args: Args) -> SResult<SExpr> {
+    let (pred, list, env) = procedure_and_list(args)?;
+    match find_pair(list, |x| test(&pred, vec![x.clone()], &env))? {
+        Some(pair) => Ok(pair.as_pair()?.car()),
+        None => Ok(sbool!(false))
+    }
+}
+
+pub fn find_tail(args: Args) -> SResult<SExpr> {
+    let (pred, list, env) = procedure_and_list(args)?;
+    Ok(find_pair(list, |x| test(&pred, vec![x.clone()], &env))?.unwrap_or(sbool!(false)))
+}
+
+pub fn list_index(args: Args) -> SResult<SExpr> {
+    let (pred, lists, env) = procedure_and_lists(args)?;
+    for (i, row) in rows(lists)?.enumerate() {
+        if test(&pred, row, &env)? {
+            return Ok(sint!(i as i64))
+        }
+    }
+    Ok(sbool!(false))
+}
+
+/// The longest prefix of `list` whose elements satisfy `pred`, and the
+/// rest of the list.
+fn span_list(args: Args, negate: bool) -> SResult<(SExprs, SExpr)> {
+    let (pred, list, env) = procedure_and_list(args)?;
+    let mut prefix = vec![];
+    let mut rest = as_pairs(list);
+    while let SExpr::Pair(pair) = rest.clone() {
+        let x = pair.car();
+        if test(&pred, vec![x.clone()], &env)? == negate {
+            break
+        }
+        prefix.push(x);
+        rest = pair.cdr();
+    }
+    Ok((prefix, rest))
+}
+
+pub fn take_while(args: Args) -> SResult<SExpr> {
+    Ok(SExpr::list_from(span_list(args, false)?.0))
+}
+
+pub fn drop_while(args: Args) -> SResult<SExpr> {
+    Ok(span_list(args, false)?.1)
+}
+
+/// (span pred list) returns `(take-while pred list)` and
+/// `(drop-while pred list)`.
+pub fn span(args: Args) -> SResult<SExpr> {
+    let (prefix, rest) = span_list(args, false)?;
+    Ok(SExpr::values(vec![SExpr::list_from(prefix), rest]))
+}
+
+/// Like `span`, but the prefix is the elements that don't satisfy `pred`.
+pub fn break_(args: Args) -> SResult<SExpr> {
+    let (prefix, rest) = span_list(args, true)?;
+    Ok(SExpr::values(vec![SExpr::list_from(prefix), rest]))
+}
+
+// Deletion
+
+/// (delete x list [=]) removes the elements that are the same as `x`.
+pub fn delete(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (x, rest) = args.evaled()?.own_one_rest()?;
+    let mut rest = rest.into_iter();
+    let list = rest.next().ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+    let same = Same::new(rest.next(), &env)?;
+
+    let mut xs = vec![];
+    for y in elements(list)? {
+        if !same.test(&x, &y)? {
+            xs.push(y);
+        }
+    }
+    Ok(SExpr::list_from(xs))
+}
+
+/// (delete-duplicates list [=]) keeps the first of the elements that are
+/// the same.
+pub fn delete_duplicates(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (list, mut rest) = args.evaled()?.own_one_rest()?;
+    let same = Same::new(rest.pop(), &env)?;
+    Ok(SExpr::list_from(unique(list_arg(list)?, &same)?))
+}
+
+// Association lists
+
+fn assoc_with(args: Args, default: Same) -> SResult<SExpr> {
+    let env = args.env();
+    let (key, rest) = args.evaled()?.own_one_rest()?;
+    let mut rest = rest.into_iter();
+    let alist = rest.next().ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+    let same = match rest.next() {
+        Some(f) => Same::new(Some(f), &env)?,
+        None => default
+    };
+
+    for entry in elements(alist)? {
+        let entry = as_pairs(entry);
+        if same.test(&key, &entry.as_pair()?.car())? {
+            return Ok(entry)
+        }
+    }
+    Ok(sbool!(false))
+}
+
+/// (assoc key alist [=]) returns the first association whose key is the
+/// same as `key`.
+pub fn assoc(args: Args) -> SResult<SExpr> {
+    assoc_with(args, Same::Equal)
+}
+
+pub fn assv(args: Args) -> SResult<SExpr> {
+    assoc_with(args, Same::Eqv)
+}
+
+/// (alist-cons key value alist) is `(cons (cons key value) alist)`.
+pub fn alist_cons(args: Args) -> SResult<SExpr> {
+    let (key, value, alist) = args.evaled()?.own_three()?;
+    Ok(SExpr::cons(SExpr::cons(key, value), alist))
+}
+
+/// Copies the associations too, not just the spine of the list.
+pub fn alist_copy(args: Args) -> SResult<SExpr> {
+    let entries = elements(args.evaled()?.own_one()?)?
+        .map(|entry| {
+            let entry = as_pairs(entry);
+            let pair = entry.as_pair()?;
+            Ok(SExpr::cons(pair.car(), pair.cdr()))
+        })
+        .collect::<SResult<_>>()?;
+    Ok(SExpr::list_from(entries))
+}
+
+/// (alist-delete key alist [=]) removes all the associations of `key`.
+pub fn alist_delete(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (key, rest) = args.evaled()?.own_one_rest()?;
+    let mut rest = rest.into_iter();
+    let alist = rest.next().ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+    let same = Same::new(rest.next(), &env)?;
+
+    let mut entries = vec![];
+    for entry in elements(alist)? {
+        let entry = as_pairs(entry);
+        if !same.test(&key, &entry.as_pair()?.car())? {
+            entries.push(entry);
+        }
+    }
+    Ok(SExpr::list_from(entries))
+}
+
+// Sets as lists
+
+/// (lset<= = list1 list2 ...) checks that each list is a subset of the
+/// next one.
+pub fn lset_lte(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    for pair in lists.windows(2) {
+        if !sift(&same, pair[0].clone(), &pair[1..], false)?.is_empty() {
+            return Ok(sbool!(false))
+        }
+    }
+    Ok(sbool!(true))
+}
+
+pub fn lset_eq(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    for pair in lists.windows(2) {
+        if !sift(&same, pair[0].clone(), &pair[1..], false)?.is_empty()
+            || !sift(&same, pair[1].clone(), &pair[..1], false)?.is_empty() {
+            return Ok(sbool!(false))
+        }
+    }
+    Ok(sbool!(true))
+}
+
+/// (lset-adjoin = list elt ...) conses the elements that aren't in the
+/// list yet onto it.
+pub fn lset_adjoin(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (same, rest) = args.evaled()?.own_one_rest()?;
+    let same = Same::new(Some(same), &env)?;
+    let (list, elts) = SExpr::List(rest).list_own_one_rest()?;
+
+    let mut xs = list_arg(list.clone())?;
+    let mut result = list;
+    for elt in elts {
+        if !same.any(&elt, &xs)? {
+            xs.push(elt.clone());
+            result = SExpr::cons(elt, result);
+        }
+    }
+    Ok(result)
+}
+
+/// The elements of the other lists that aren't in the first one are
+/// consed onto it.
+pub fn lset_union(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    let mut lists = lists.into_iter();
+    let mut xs = lists.next().unwrap_or_default();
+    let mut added = vec![];
+    for list in lists {
+        for y in list {
+            if !same.any(&y, &xs)? {
+                xs.push(y.clone());
+                added.push(y);
+            }
+        }
+    }
+
+    let original = xs.len() - added.len();
+    added.reverse();
+    added.extend(xs.into_iter().take(original));
+    Ok(SExpr::list_from(added))
+}
+
+/// The elements of the first list that are in all the others.
+pub fn lset_intersection(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    let mut lists = lists.into_iter();
+    let mut xs = lists.next().unwrap_or_default();
+    for list in lists {
+        xs = sift(&same, xs, &[list], true)?;
+    }
+    Ok(SExpr::list_from(xs))
+}
+
+/// The elements of the first list that aren't in any of the others.
+pub fn lset_difference(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    let (xs, others) = match lists.split_first() {
+        Some((xs, others)) => (xs.clone(), others),
+        None => return Err(SErr::WrongArgCount(2, 1))
+    };
+    Ok(SExpr::list_from(sift(&same, xs, others, false)?))
+}
+
+/// The elements that are in an odd number of the lists.
+pub fn lset_xor(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    let mut xs = vec![];
+    for ys in lists {
+        let mut only_xs = sift(&same, xs.clone(), slice::from_ref(&ys), false)?;
+        let only_ys = sift(&same, ys, slice::from_ref(&xs), false)?;
+        only_xs.extend(only_ys);
+        xs = only_xs;
+    }
+    Ok(SExpr::list_from(xs))
+}
+
+/// Returns what `lset-difference` and `lset-intersection` would, except
+/// that the elements are compared with the union of the other lists.
+pub fn lset_diff_plus_intersection(args: Args) -> SResult<SExpr> {
+    let (same, lists) = same_and_lists(args)?;
+    let (xs, others) = match lists.split_first() {
+        Some((xs, others)) => (xs.clone(), others),
+        None => return Err(SErr::WrongArgCount(2, 1))
+    };
+    let difference = sift(&same, xs.clone(), others, false)?;
+    let intersection = sift(&same, xs, others, true)?;
+    Ok(SExpr::values(vec![SExpr::list_from(difference), SExpr::list_from(intersection)]))
+}
@@ -12,15 +12,19 @@ pub fn type_of(args: Args) -> SResult<SExpr> {
 
     Ok(match item {
         Atom(Symbol(_)) => ssymbol!("symbol"),
-        Atom(Integer(_)) => ssymbol!("integer"),
+        Atom(Integer(_)) | Atom(BigInt(_)) => ssymbol!("integer"),
         Atom(Fraction(_)) => ssymbol!("fraction"),
         Atom(Float(_)) => ssymbol!("float"),
+        Atom(Complex(_)) => ssymbol!("complex"),
         Atom(Boolean(_)) => ssymbol!("boolean"),
         Atom(Chr(_)) => ssymbol!("chr"),
         Atom(Str(_)) => ssymbol!("str"),
         Atom(_) => ssymbol!("atom"),
         List(_) => ssymbol!("list"),
         DottedList(_,_) => ssymbol!("list-dotted"),
+        x@Pair(_) => if x.is_proper_list() { ssymbol!("list") } else { ssymbol!("list-dotted") },
+        Vector(_) => ssymbol!("vector"),
+        Bytevector(_) => ssymbol!("bytevector"),
         Procedure(_) => ssymbol!("procedure"),
         Port(TextualFileInput(_,_)) => ssymbol!("port-textual-in"),
         Port(TextualFileOutput(_,_)) => ssymbol!("port-textual-out"),
@@ -28,7 +32,18 @@ pub fn type_of(args: Args) -> SResult<SExpr> {
         Port(BinaryFileOutput(_,_)) => ssymbol!("port-binary-out"),
         Port(StdInput(_)) => ssymbol!("port-std-in"),
         Port(StdOutput(_)) => ssymbol!("port-std-out"),
+        Port(StdError(_)) => ssymbol!("port-std-err"),
+        Port(StringInput(_)) => ssymbol!("port-string-in"),
+        Port(StringOutput(_)) => ssymbol!("port-string-out"),
+        Port(BytevectorInput(_)) => ssymbol!("port-bytevector-in"),
+        Port(BytevectorOutput(_)) => ssymbol!("port-bytevector-out"),
         Port(Closed) => ssymbol!("port-closed"),
+        Condition(_) => ssymbol!("condition"),
+        Promise(_) => ssymbol!("promise"),
+        Eof => ssymbol!("eof"),
+        Record(x) => ssymbol!(x.record_type().name()),
+        RecordType(_) => ssymbol!("record-type"),
+        HashTable(_) => ssymbol!("hash-table"),
         _ => bail!(Generic => "Is that a thing?")
     })
 }
@@ -62,7 +77,7 @@ pub fn convert_type(args: Args) -> SResult<SExpr> {
             x => bail!(Cast => "chr", x)
         },
         Atom(Symbol(ref t)) if t == "integer" => match arg {
-            x@Atom(Integer(_)) => x,
+            x@Atom(Integer(_)) | x@Atom(BigInt(_)) => x,
             Atom(Chr(x)) => sint!(x as i64),
             x => bail!(Cast => "chr", x)
         },
@@ -85,6 +100,18 @@ pub fn convert_type(args: Args) -> SResult<SExpr> {
 
                 sstr!(result)
             },
+            Pair(x) => {
+                let (xs, tail) = x.elements()
+                    .ok_or_else(|| SErr::Cast("str".to_string(), Pair(x.clone())))?;
+                let mut result = xs.into_iter()
+                    .map(|x| x.into_chr())
+                    .collect::<SResult<String>>()?;
+                if !tail.is_null() {
+                    result.push(tail.into_chr()?);
+                }
+
+                sstr!(result)
+            },
             x => bail!(Cast => "str", x)
         },
         Atom(Symbol(ref t)) if t == "list" => match arg {
@@ -94,12 +121,21 @@ pub fn convert_type(args: Args) -> SResult<SExpr> {
                     .map(|c| schr!(c))
                     .collect();
 
-                List(result)
+                SExpr::list_from(result)
             },
-            x@List(_) => x,
+            x@List(_) => x.into_data(),
             DottedList(mut xs, y) => {
                 xs.push(*y);
-                List(xs)
+                SExpr::list_from(xs)
+            },
+            Pair(x) => {
+                let (mut xs, tail) = x.elements()
+                    .ok_or_else(|| SErr::Cast("list".to_string(), Pair(x.clone())))?;
+                if tail.is_null() {
+                    return Ok(Pair(x))
+                }
+                xs.push(tail);
+                SExpr::list_from(xs)
             },
             x => bail!(Cast => "list", x)
         },
@@ -3,44 +3,47 @@ pub mod equivalence;
 #[macro_use]
 pub mod numeric;
 pub mod ordering;
-pub mod conditionals;
 pub mod list;
+pub mod vector;
+pub mod bytevector;
+pub mod promise;
+pub mod record;
+pub mod hashtable;
 #[macro_use]
 pub mod string;
 pub mod io;
 pub mod system;
 pub mod prelude;
 pub mod meta;
+pub mod control;
+pub mod exception;
 
 use primitives::prelude::PRELUDE;
 use env::{EnvRef, EnvValues};
-use lexer::tokenize;
-use parser::parse;
+use lexer::TokenIterator;
+use parser::parse_single;
+use expander::expand;
+use port::port_parameters;
+use procedure::ProcedureData;
 use serr::SResult;
 
+/// The prelude is evaluated without its source locations, so an error in
+/// one of its procedures is reported at the user's code that called it.
 pub fn load_prelude(env: &EnvRef) -> SResult<()> {
-    for sexpr in parse(tokenize(&mut PRELUDE.to_string().chars().into_iter().peekable()))? {
-        sexpr.eval(&env)?;
+    let mut tokens = TokenIterator::with_file(PRELUDE.chars(), "<prelude>");
+    while tokens.peek()?.is_some() {
+        expand(parse_single(&mut tokens)?.strip_locations())?.eval(env)?;
     }
     Ok(())
 }
 
 pub fn env() -> EnvValues {
-    environment! {
+    let mut env = environment! {
         "typeof"        => meta::type_of,
         "convert-type"  => meta::convert_type,
 
-        "define"      => lang::define,
-        "set!"        => lang::set,
-        "λ"           => lang::lambda,
-        "lambda"      => lang::lambda,
-        "apply"       => lang::apply,
-        "let"         => lang::let_,
-        "let*"        => lang::let_star,
-        "letrec"      => lang::let_rec,
-        "quote"       => lang::quote,
-        "quasiquote"  => lang::quasiquote,
         "exit"        => lang::exit,
+        "values"      => lang::values,
 
         "eqv?"   => equivalence::eqv_qm,
         "eq?"    => equivalence::eq_qm,
@@ -50,24 +53,57 @@ pub fn env() -> EnvValues {
         "-"  => |args| numeric::calc('-', args),
         "*"  => |args| numeric::calc('*', args),
         "/"  => |args| numeric::calc('/', args),
+        "quotient"    => numeric::quotient,
         "remainder"   => numeric::remainder,
         "modulo"      => numeric::modulo,
+        "floor/"      => numeric::floor_div,
+        "truncate/"   => numeric::truncate_div,
+        "floor-quotient"     => numeric::floor_quotient,
+        "floor-remainder"    => numeric::modulo,
+        "truncate-quotient"  => numeric::quotient,
+        "truncate-remainder" => numeric::remainder,
         "numerator"   => numeric::numerator,
         "denominator" => numeric::denominator,
-        "sqrt"        => call_float_fun!(sqrt),
-        "expt"        => call_float_fun!(sqrt),
-        "ceiling"     => call_float_fun!(ceil),
-        "floor"       => call_float_fun!(floor),
-        "truncate"    => call_float_fun!(trunc),
-        "round"       => call_float_fun!(round),
-        "exp"         => call_float_fun!(exp),
-        "log"         => call_float_fun!(ln, log),
-        "sin"         => call_float_fun!(sin),
-        "cos"         => call_float_fun!(cos),
-        "tan"         => call_float_fun!(tan),
-        "asin"        => call_float_fun!(asin),
-        "acos"        => call_float_fun!(acos),
-        "atan"        => call_float_fun!(atan, atan2),
+        "sqrt"        => numeric::sqrt,
+        "exact-integer-sqrt" => numeric::exact_integer_sqrt,
+        "expt"        => numeric::expt,
+        "square"      => numeric::square,
+        "ceiling"     => numeric::ceiling,
+        "floor"       => numeric::floor,
+        "truncate"    => numeric::truncate,
+        "round"       => numeric::round,
+        "rationalize" => numeric::rationalize,
+        "exact"       => numeric::exact,
+        "inexact"     => numeric::inexact,
+        "exact->inexact" => numeric::inexact,
+        "inexact->exact" => numeric::exact,
+        "number?"     => numeric::number_qm,
+        "complex?"    => numeric::number_qm,
+        "real?"       => numeric::real_qm,
+        "rational?"   => numeric::rational_qm,
+        "integer?"    => numeric::integer_qm,
+        "exact?"      => numeric::exact_qm,
+        "inexact?"    => numeric::inexact_qm,
+        "exact-integer?"  => numeric::exact_integer_qm,
+        "exact-rational?" => numeric::exact_rational_qm,
+        "exact-nonnegative-integer?" => numeric::exact_nonnegative_integer_qm,
+        "nan?"        => numeric::nan_qm,
+        "infinite?"   => numeric::infinite_qm,
+        "finite?"     => numeric::finite_qm,
+        "exp"         => numeric::exp,
+        "log"         => numeric::log,
+        "sin"         => numeric::sin,
+        "cos"         => numeric::cos,
+        "tan"         => numeric::tan,
+        "asin"        => numeric::asin,
+        "acos"        => numeric::acos,
+        "atan"        => numeric::atan,
+        "make-rectangular" => numeric::make_rectangular,
+        "make-polar"  => numeric::make_polar,
+        "real-part"   => numeric::real_part,
+        "imag-part"   => numeric::imag_part,
+        "magnitude"   => numeric::magnitude,
+        "angle"       => numeric::angle,
         "number->string" => numeric::number_string,
         "string->number" => numeric::string_number,
 
@@ -77,20 +113,188 @@ pub fn env() -> EnvValues {
         ">=" => ordering::gte,
         "="  => ordering::eq,
 
-        "cond" => conditionals::cond,
-        "case" => conditionals::case,
-        "and"  => conditionals::and,
-        "or"   => conditionals::or,
-
         "cons"   => list::cons,
         "car"    => list::car,
         "cdr"    => list::cdr,
+        "set-car!" => list::set_car_em,
+        "set-cdr!" => list::set_cdr_em,
+        "pair?"  => list::pair_qm,
+        "null?"  => list::null_qm,
+        "list?"  => list::list_qm,
         "append" => list::append,
         "list-copy" => list::list_copy,
 
+        "xcons"         => list::xcons,
+        "cons*"         => list::cons_star,
+        "make-list"     => list::make_list,
+        "list-tabulate" => list::list_tabulate,
+        "iota"          => list::iota,
+        "circular-list" => list::circular_list,
+        "proper-list?"  => list::list_qm,
+        "circular-list?" => list::circular_list_qm,
+        "dotted-list?"  => list::dotted_list_qm,
+        "not-pair?"     => list::not_pair_qm,
+        "null-list?"    => list::null_list_qm,
+        "list="         => list::list_eq,
+        "first"   => |args| list::nth(0, args),
+        "second"  => |args| list::nth(1, args),
+        "third"   => |args| list::nth(2, args),
+        "fourth"  => |args| list::nth(3, args),
+        "fifth"   => |args| list::nth(4, args),
+        "sixth"   => |args| list::nth(5, args),
+        "seventh" => |args| list::nth(6, args),
+        "eighth"  => |args| list::nth(7, args),
+        "ninth"   => |args| list::nth(8, args),
+        "tenth"   => |args| list::nth(9, args),
+        "car+cdr"     => list::car_plus_cdr,
+        "list-ref"    => list::list_ref,
+        "take"        => list::take,
+        "take!"       => list::take,
+        "drop"        => list::drop,
+        "list-tail"   => list::drop,
+        "take-right"  => list::take_right,
+        "drop-right"  => list::drop_right,
+        "drop-right!" => list::drop_right,
+        "split-at"    => list::split_at,
+        "split-at!"   => list::split_at,
+        "last"        => list::last,
+        "last-pair"   => list::last_pair,
+        "length"      => list::length,
+        "length+"     => list::length_plus,
+        "append!"     => list::append,
+        "concatenate"  => list::concatenate,
+        "concatenate!" => list::concatenate,
+        "reverse"     => list::reverse,
+        "reverse!"    => list::reverse,
+        "append-reverse"  => list::append_reverse,
+        "append-reverse!" => list::append_reverse,
+        "zip"    => list::zip,
+        "unzip1" => |args| list::unzip(1, args),
+        "unzip2" => |args| list::unzip(2, args),
+        "unzip3" => |args| list::unzip(3, args),
+        "unzip4" => |args| list::unzip(4, args),
+        "unzip5" => |args| list::unzip(5, args),
+        "count"  => list::count,
+        "fold-right"      => list::fold_right,
+        "pair-fold"       => list::pair_fold,
+        "pair-fold-right" => list::pair_fold_right,
+        "reduce"          => list::reduce,
+        "reduce-right"    => list::reduce_right,
+        "unfold"          => list::unfold,
+        "unfold-right"    => list::unfold_right,
+        "append-map"      => list::append_map,
+        "append-map!"     => list::append_map,
+        "pair-for-each"   => list::pair_for_each,
+        "filter-map"      => list::filter_map,
+        "filter"     => list::filter,
+        "filter!"    => list::filter,
+        "remove"     => list::remove,
+        "remove!"    => list::remove,
+        "partition"  => list::partition,
+        "partition!" => list::partition,
+        "member"     => list::member,
+        "memq"       => list::memv,
+        "memv"       => list::memv,
+        "find"       => list::find,
+        "find-tail"  => list::find_tail,
+        "list-index" => list::list_index,
+        "take-while"  => list::take_while,
+        "take-while!" => list::take_while,
+        "drop-while"  => list::drop_while,
+        "span"   => list::span,
+        "span!"  => list::span,
+        "break"  => list::break_,
+        "break!" => list::break_,
+        "delete"  => list::delete,
+        "delete!" => list::delete,
+        "delete-duplicates"  => list::delete_duplicates,
+        "delete-duplicates!" => list::delete_duplicates,
+        "assoc" => list::assoc,
+        "assq"  => list::assv,
+        "assv"  => list::assv,
+        "alist-cons"    => list::alist_cons,
+        "alist-copy"    => list::alist_copy,
+        "alist-delete"  => list::alist_delete,
+        "alist-delete!" => list::alist_delete,
+        "lset<="        => list::lset_lte,
+        "lset="         => list::lset_eq,
+        "lset-adjoin"   => list::lset_adjoin,
+        "lset-union"    => list::lset_union,
+        "lset-union!"   => list::lset_union,
+        "lset-intersection"  => list::lset_intersection,
+        "lset-intersection!" => list::lset_intersection,
+        "lset-difference"    => list::lset_difference,
+        "lset-difference!"   => list::lset_difference,
+        "lset-xor"           => list::lset_xor,
+        "lset-xor!"          => list::lset_xor,
+        "lset-diff+intersection"  => list::lset_diff_plus_intersection,
+        "lset-diff+intersection!" => list::lset_diff_plus_intersection,
+
+        "vector"        => vector::vector,
+        "vector?"       => vector::vector_qm,
+        "make-vector"   => vector::make_vector,
+        "vector-length" => vector::vector_length,
+        "vector-ref"    => vector::vector_ref,
+        "vector-set!"   => vector::vector_set_em,
+        "vector-fill!"  => vector::vector_fill_em,
+        "vector-copy"   => vector::vector_copy,
+        "vector-copy!"  => vector::vector_copy_em,
+        "vector-append" => vector::vector_append,
+        "vector->list"  => vector::vector_list,
+        "list->vector"  => vector::list_vector,
+
+        "bytevector"         => bytevector::bytevector,
+        "bytevector?"        => bytevector::bytevector_qm,
+        "make-bytevector"    => bytevector::make_bytevector,
+        "bytevector-length"  => bytevector::bytevector_length,
+        "bytevector-u8-ref"  => bytevector::bytevector_u8_ref,
+        "bytevector-u8-set!" => bytevector::bytevector_u8_set_em,
+        "bytevector-copy"    => bytevector::bytevector_copy,
+        "bytevector-copy!"   => bytevector::bytevector_copy_em,
+        "bytevector-append"  => bytevector::bytevector_append,
+        "utf8->string"       => bytevector::utf8_string,
+        "string->utf8"       => bytevector::string_utf8,
+
+        "make-promise" => promise::make_promise,
+        "promise?"     => promise::promise_qm,
+
+        "make-record-type"        => record::make_record_type,
+        "record-constructor"      => record::record_constructor,
+        "record-predicate"        => record::record_predicate,
+        "record-accessor"         => record::record_accessor,
+        "record-modifier"         => record::record_modifier,
+        "record?"                 => record::record_qm,
+        "record-type-descriptor"  => record::record_type_descriptor,
+        "record-type-name"        => record::record_type_name,
+        "record-type-field-names" => record::record_type_field_names,
+        "record-fields"           => record::record_fields,
+
+        "make-hash-table"         => hashtable::make_hash_table,
+        "hash-table?"             => hashtable::hash_table_qm,
+        "hash-table-ref/default"  => hashtable::hash_table_ref_default,
+        "hash-table-contains?"    => hashtable::hash_table_contains_qm,
+        "hash-table-set!"         => hashtable::hash_table_set_em,
+        "hash-table-delete!"      => hashtable::hash_table_delete_em,
+        "hash-table-size"         => hashtable::hash_table_size,
+        "hash-table-keys"         => hashtable::hash_table_keys,
+        "hash-table-values"       => hashtable::hash_table_values,
+        "hash-table->alist"       => hashtable::hash_table_to_alist,
+        "hash-table-copy"         => hashtable::hash_table_copy,
+        "hash-table-clear!"       => hashtable::hash_table_clear_em,
+        "hash"                    => hashtable::hash,
+        "string-hash"             => hashtable::string_hash,
+        "hash-by-identity"        => hashtable::hash_by_identity,
+
+        "error"                  => exception::error,
+        "error-object?"          => exception::error_object_qm,
+        "error-object-message"   => exception::error_object_message,
+        "error-object-irritants" => exception::error_object_irritants,
+        "file-error?"            => exception::file_error_qm,
+        "read-error?"            => exception::read_error_qm,
+
         "string-upcase"         => call_str_fun!(to_uppercase),
         "string-downcase"       => call_str_fun!(to_lowercase),
-        "string-length"         => call_str_fun!(len),
+        "string-length"         => string::string_length,
         "char-upcase"           => call_chr_fun!(to_uppercase !),
         "char-downcase"         => call_chr_fun!(to_lowercase !),
         "char-upper-case?"      => call_chr_fun!(is_uppercase),
@@ -103,6 +307,26 @@ pub fn env() -> EnvValues {
         "string-append"         => string::string_append,
         "string-replace-range!" => string::string_replace_range_em,
         "make-string"           => string::make_string,
+        "string-ref"            => string::string_ref,
+        "substring"             => string::string_copy,
+        "string-index"          => string::string_index,
+        "string-index-right"    => string::string_index_right,
+        "string-search-forward" => string::string_search_forward,
+        "string-contains"       => string::string_contains,
+        "string-prefix?"        => string::string_prefix_qm,
+        "string-suffix?"        => string::string_suffix_qm,
+        "string-pad"            => string::string_pad,
+        "string-pad-right"      => string::string_pad_right,
+        "string-trim"           => string::string_trim,
+        "string-trim-right"     => string::string_trim_right,
+        "string-trim-both"      => string::string_trim_both,
+        "string-join"           => string::string_join,
+        "string-split"          => string::string_split,
+        "string-map"            => string::string_map,
+        "string-for-each"       => string::string_for_each,
+        "string-count"          => string::string_count,
+        "string-reverse"        => string::string_reverse,
+        "string-tokenize"       => string::string_tokenize,
 
         "load"         => system::load,
         "file-exists?" => system::file_exists_qm,
@@ -115,15 +339,48 @@ pub fn env() -> EnvValues {
         "open-binary-output-file" => io::open_binary_output_file,
         "open-input-file"  => io::open_input_file,
         "open-output-file" => io::open_output_file,
+        "open-input-string"      => io::open_input_string,
+        "open-output-string"     => io::open_output_string,
+        "get-output-string"      => io::get_output_string,
+        "open-input-bytevector"  => io::open_input_bytevector,
+        "open-output-bytevector" => io::open_output_bytevector,
+        "get-output-bytevector"  => io::get_output_bytevector,
         "read"             => io::read,
         "read-u8"          => io::read_u8,
         "read-line"        => io::read_line,
         "read-char"        => io::read_char,
         "read-all"         => io::read_all,
+        "eof-object"       => io::eof_object,
+        "eof-object?"      => io::eof_object_qm,
+        "read-bytevector"  => io::read_bytevector,
+        "read-bytevector!" => io::read_bytevector_em,
         "write"            => io::write,
+        "write-u8"         => io::write_u8,
+        "write-bytevector" => io::write_bytevector,
         "write-string"     => io::write_string,
         "display"          => io::display,
-        "newline"          => io::newline,
-        "close-port"       => io::close_port
+        "newline"          => io::newline
+    };
+
+    env.extend(special_forms());
+    env.extend(control::env());
+    for (name, parameter) in port_parameters().iter() {
+        env.insert(name.to_string(), ProcedureData::new_parameter(parameter.clone()));
+    }
+    env
+}
+
+/// Primitives that take their arguments unevaluated.
+fn special_forms() -> EnvValues {
+    special_forms! {
+        "define"      => lang::define,
+        "set!"        => lang::set,
+        "λ"           => lang::lambda,
+        "lambda"      => lang::lambda,
+        "case-lambda" => lang::case_lambda,
+        "quote"       => lang::quote,
+        "quasiquote"  => lang::quasiquote,
+
+        "close-port"  => io::close_port
     }
 }
@@ -1,13 +1,332 @@
-use std::ops::{Add, Sub, Mul, Div};
-
-use utils::fraction;
-use utils::radix::Radix;
-use lexer::Token;
+use utils::bigint::BigInt;
+use utils::fraction::Fraction;
+use utils::complex::{self, Complex, Parts};
+use lexer::{Token, parse_number, parse_exact_number};
 use parser::SExpr;
 use evaluator::Args;
 use serr::{SErr, SResult};
 
+//
+// Helpers
+//
+
+/// Applies one of `+ - * /` to two numbers.
+fn arith(op: char, a: SExpr, b: SExpr) -> SResult<SExpr> {
+    match (a, b) {
+        (SExpr::Atom(a), SExpr::Atom(b)) if a.is_number() && b.is_number() => {
+            Ok(SExpr::Atom(apply_op(op, a, b)?))
+        },
+        (a, b) => bail!(TypeMismatch => "number", SExpr::List(vec![a, b]))
+    }
+}
+
+/// Same as `arith` for numbers that are already checked. Both are converted
+/// to the simplest representation that can hold them: integers, then
+/// fractions, then floats. Integers that overflow become bignums.
+fn apply_op(op: char, a: Token, b: Token) -> SResult<Token> {
+    // Fast path for small integers, falls through if the result overflows
+    if let (Token::Integer(x), Token::Integer(y)) = (&a, &b) {
+        let result = match op {
+            '+' => x.checked_add(*y),
+            '-' => x.checked_sub(*y),
+            '*' => x.checked_mul(*y),
+            _ if x.checked_rem(*y) == Some(0) => x.checked_div(*y),
+            _ => None
+        };
+
+        if let Some(result) = result {
+            return Ok(Token::Integer(result))
+        }
+    }
+
+    if !(a.is_real() && b.is_real()) {
+        return complex_op(op, a, b)
+    }
+
+    if let (Some(x), Some(y)) = (a.to_bigint(), b.to_bigint()) {
+        match op {
+            '+' => return Ok(Token::from(x + y)),
+            '-' => return Ok(Token::from(x - y)),
+            '*' => return Ok(Token::from(x * y)),
+            _ => ()
+        }
+    }
+
+    if let (Some(x), Some(y)) = (a.to_fraction(), b.to_fraction()) {
+        return Ok(Token::from(match op {
+            '+' => x + y,
+            '-' => x - y,
+            '*' => x * y,
+            _ if y.is_zero() => serr!(DivisionByZero),
+            _ => x / y
+        }))
+    }
+
+    let (x, y) = (a.to_float().unwrap(), b.to_float().unwrap());
+    Ok(Token::Float(match op {
+        '+' => x + y,
+        '-' => x - y,
+        '*' => x * y,
+        _ => x / y
+    }))
+}
+
+/// `apply_op` for complex numbers, works on the parts so exact numbers
+/// stay exact.
+fn complex_op(op: char, a: Token, b: Token) -> SResult<Token> {
+    let (x, y) = (a.real_part(), a.imag_part());
+    let (u, v) = (b.real_part(), b.imag_part());
+    let mul = |a: &Token, b: &Token| apply_op('*', a.clone(), b.clone());
+
+    let (re, im) = match op {
+        '+' | '-' => (apply_op(op, x, u)?, apply_op(op, y, v)?),
+        '*' => (
+            apply_op('-', mul(&x, &u)?, mul(&y, &v)?)?,
+            apply_op('+', mul(&x, &v)?, mul(&y, &u)?)?
+        ),
+        _ => {
+            let d = apply_op('+', mul(&u, &u)?, mul(&v, &v)?)?;
+            let re = apply_op('+', mul(&x, &u)?, mul(&y, &v)?)?;
+            let im = apply_op('-', mul(&y, &u)?, mul(&x, &v)?)?;
+            (apply_op('/', re, d.clone())?, apply_op('/', im, d)?)
+        }
+    };
+
+    Ok(Complex::make(re, im))
+}
+
+/// Raises any number to an integer power by repeated squaring.
+fn expt_by_squaring(base: Token, exponent: u64) -> SResult<Token> {
+    let (mut base, mut exponent) = (base, exponent);
+    let mut result = Token::Integer(1);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = apply_op('*', result, base.clone())?;
+        }
+        base = apply_op('*', base.clone(), base)?;
+        exponent >>= 1;
+    }
+
+    Ok(result)
+}
+
+/// Applies a function that is computed with floats. `real` is used for real
+/// numbers unless `leaves_reals` says that the result is complex for them,
+/// like it is for the logarithm of a negative number.
+fn transcendental(x: Token, real: fn(f64) -> f64, complex: fn(Parts) -> Parts, leaves_reals: fn(f64) -> bool) -> Token {
+    match x {
+        Token::Complex(z) => Complex::from_floats(complex(z.to_floats())),
+        x => {
+            let x = x.to_float().unwrap();
+            if leaves_reals(x) {
+                Complex::from_floats(complex((x, 0.0)))
+            } else {
+                Token::Float(real(x))
+            }
+        }
+    }
+}
+
+fn unary_transcendental(args: Args, real: fn(f64) -> f64, complex: fn(Parts) -> Parts, leaves_reals: fn(f64) -> bool) -> SResult<SExpr> {
+    let x = number(args.evaled()?.own_one()?)?;
+    Ok(SExpr::Atom(transcendental(x, real, complex, leaves_reals)))
+}
+
+/// Exact for exact numbers whose root is exact, like -4 or 9/16.
+fn square_root(x: Token) -> SResult<Token> {
+    if let Some(f) = x.to_fraction() {
+        let n = f.n.abs();
+        let (rn, rd) = (n.sqrt(), f.d.sqrt());
+        if rn.clone() * rn.clone() == n && rd.clone() * rd.clone() == f.d {
+            let root = Token::from(Fraction::new(rn, rd)?);
+            return Ok(if f.n.is_negative() { Complex::make(Token::Integer(0), root) } else { root })
+        }
+    }
+
+    Ok(transcendental(x, f64::sqrt, complex::sqrt, |x| x < 0.0))
+}
+
+fn number(x: SExpr) -> SResult<Token> {
+    match x {
+        SExpr::Atom(x) if x.is_number() => Ok(x),
+        x => bail!(TypeMismatch => "number", x)
+    }
+}
+
+fn real(x: SExpr) -> SResult<Token> {
+    match x {
+        SExpr::Atom(x) if x.is_real() => Ok(x),
+        x => bail!(TypeMismatch => "real number", x)
+    }
+}
+
+fn to_exact_number(x: Token) -> SResult<Token> {
+    match x {
+        Token::Float(x) => Ok(Token::from(float_to_exact(x)?)),
+        Token::Complex(z) => {
+            let Complex { re, im } = *z;
+            Ok(Complex::make(to_exact_number(re)?, to_exact_number(im)?))
+        },
+        x => Ok(x)
+    }
+}
+
+fn float_to_exact(x: f64) -> SResult<Fraction> {
+    Fraction::from_f64(x)
+        .ok_or_else(|| SErr::Cast("an exact number".to_string(), sfloat!(x)))
+}
+
+/// Like `Token::to_fraction` but also converts floats, except infinities
+/// and NaN.
+fn to_exact(x: &Token) -> Option<Fraction> {
+    match x {
+        Token::Float(x) => Fraction::from_f64(*x),
+        x => x.to_fraction()
+    }
+}
+
+/// Divides two integers and returns the quotient and the remainder. The
+/// quotient is rounded towards negative infinity if `floor` is set,
+/// towards zero otherwise. Inexact integers give inexact results.
+fn integer_division(args: Args, floor: bool) -> SResult<(SExpr, SExpr)> {
+    let (x, y) = args.evaled()?.own_two()?;
+    let (x, y) = (real(x)?, real(y)?);
+
+    if let (Some(n), Some(d)) = (x.to_bigint(), y.to_bigint()) {
+        if d.is_zero() {
+            serr!(DivisionByZero)
+        }
+
+        let (q, r) = n.div_rem(&d);
+        if floor && !r.is_zero() && r.is_negative() != d.is_negative() {
+            return Ok((SExpr::from(q - BigInt::from(1)), SExpr::from(r + d)))
+        }
+        return Ok((SExpr::from(q), SExpr::from(r)))
+    }
+
+    let (n, d) = match (x.to_float(), y.to_float()) {
+        (Some(n), Some(d)) if n.fract() == 0.0 && d.fract() == 0.0 => (n, d),
+        _ => bail!(TypeMismatch => "integer", SExpr::List(vec![SExpr::Atom(x), SExpr::Atom(y)]))
+    };
+    if d == 0.0 {
+        serr!(DivisionByZero)
+    }
+
+    let q = if floor { (n / d).floor() } else { (n / d).trunc() };
+    Ok((sfloat!(q), sfloat!(n - q * d)))
+}
+
+/// `floor`, `ceiling`, `truncate` and `round`. Exact numbers stay exact.
+fn round_with(args: Args, float_op: fn(f64) -> f64, exact_op: fn(&Fraction) -> BigInt) -> SResult<SExpr> {
+    match real(args.evaled()?.own_one()?)? {
+        Token::Float(x) => Ok(sfloat!(float_op(x))),
+        Token::Fraction(x) => Ok(SExpr::from(exact_op(&x))),
+        x => Ok(SExpr::Atom(x))
+    }
+}
+
+/// `nan?`, `infinite?` and `finite?`, `exact` is the answer for exact
+/// numbers. Inexact complex numbers are finite if both parts are, the
+/// other predicates need just one of them.
+fn float_predicate(args: Args, f: fn(f64) -> bool, exact: bool) -> SResult<SExpr> {
+    match number(args.evaled()?.own_one()?)? {
+        Token::Float(x) => Ok(sbool!(f(x))),
+        Token::Complex(ref z) if !z.re.is_exact() => {
+            let (re, im) = z.to_floats();
+            Ok(sbool!(if exact { f(re) && f(im) } else { f(re) || f(im) }))
+        },
+        _ => Ok(sbool!(exact))
+    }
+}
+
+/// Raises an exact number to an integer power, by repeated squaring.
+fn exact_expt(base: Fraction, exponent: BigInt) -> SResult<Fraction> {
+    if exponent.is_negative() {
+        if base.is_zero() {
+            serr!(DivisionByZero)
+        }
+        return Ok(Fraction::from(1) / exact_expt(base, -exponent)?)
+    }
+
+    match exponent.to_i64() {
+        Some(e) if e <= i64::from(u32::MAX) => {
+            let e = e as u32;
+            Fraction::new(base.n.pow(e), base.d.pow(e))
+        },
+        // Only 0, 1 and -1 can be raised to powers this big
+        _ if base.is_zero() || base == Fraction::from(1) => Ok(base),
+        _ if base == Fraction::from(-1) => {
+            Ok(Fraction::from(if exponent.is_even() { 1 } else { -1 }))
+        },
+        _ => bail!("Exponent is too large: {}", exponent)
+    }
+}
+
+/// `expt` for complex results. Integer powers are computed by repeated
+/// squaring, so exact numbers stay exact.
+fn complex_expt(base: Token, exponent: Token) -> SResult<Token> {
+    if let Some(e) = exponent.to_bigint().and_then(|e| e.to_i64()) {
+        if e.unsigned_abs() <= u64::from(u32::MAX) {
+            let result = expt_by_squaring(base, e.unsigned_abs())?;
+            return if e < 0 { apply_op('/', Token::Integer(1), result) } else { Ok(result) }
+        }
+    }
+
+    let (z, w) = (float_parts(&base), float_parts(&exponent));
+    Ok(Complex::from_floats(complex::expt(z, w)))
+}
+
+fn float_parts(x: &Token) -> Parts {
+    match x {
+        Token::Complex(z) => z.to_floats(),
+        x => (x.to_float().unwrap(), 0.0)
+    }
+}
+
+/// The simplest rational between `lo` and `hi`, the one with the smallest
+/// denominator.
+fn simplest_between(lo: Fraction, hi: Fraction) -> Fraction {
+    let zero = Fraction::from(0);
+    if lo > zero {
+        simplest_positive(lo, hi)
+    } else if hi < zero {
+        -simplest_positive(-hi, -lo)
+    } else {
+        zero
+    }
+}
+
+/// Same as `simplest_between`, for `0 < lo <= hi`.
+fn simplest_positive(lo: Fraction, hi: Fraction) -> Fraction {
+    let one = Fraction::from(1);
+    let floor = Fraction::from(lo.floor());
+    if floor == lo {
+        floor
+    } else if floor < Fraction::from(hi.floor()) {
+        floor + one
+    } else {
+        // Both have the same integer part, continue with the reciprocals
+        // of the fractional parts; this walks the continued fraction.
+        let rest = simplest_positive(one.clone() / (hi - floor.clone()), one.clone() / (lo - floor.clone()));
+        floor + one / rest
+    }
+}
+
+fn radix(x: SExpr) -> SResult<u32> {
+    match x.into_int()? {
+        x@2..=36 => Ok(x as u32),
+        _ => bail!(Generic => "Unsupported radix")
+    }
+}
+
+//
+// Functions
+//
 pub fn calc(op_str: char, args: Args) -> SResult<SExpr> {
+    if !"+-*/".contains(op_str) {
+        bail!("Not an arithmetic op: {}", op_str)
+    }
+
     let mut args_iter = args.eval()?
         .into_iter();
 
@@ -17,166 +336,368 @@ pub fn calc(op_str: char, args: Args) -> SResult<SExpr> {
         _ => args_iter.next().ok_or_else(|| SErr::WrongArgCount(1,0))?
     };
 
+    args_iter.try_fold(init, |acc, x| arith(op_str, acc, x))
+}
+
+pub fn quotient(args: Args) -> SResult<SExpr> {
+    Ok(integer_division(args, false)?.0)
+}
+
+pub fn remainder(args: Args) -> SResult<SExpr> {
+    Ok(integer_division(args, false)?.1)
+}
+
+pub fn modulo(args: Args) -> SResult<SExpr> {
+    Ok(integer_division(args, true)?.1)
+}
+
+/// Returns the quotient and the remainder as two values.
+pub fn floor_div(args: Args) -> SResult<SExpr> {
+    let (q, r) = integer_division(args, true)?;
+    Ok(SExpr::Values(vec![q, r]))
+}
+
+pub fn floor_quotient(args: Args) -> SResult<SExpr> {
+    Ok(integer_division(args, true)?.0)
+}
+
+/// Returns the quotient and the remainder as two values.
+pub fn truncate_div(args: Args) -> SResult<SExpr> {
+    let (q, r) = integer_division(args, false)?;
+    Ok(SExpr::Values(vec![q, r]))
+}
+
+pub fn floor(args: Args) -> SResult<SExpr> {
+    round_with(args, f64::floor, Fraction::floor)
+}
+
+pub fn ceiling(args: Args) -> SResult<SExpr> {
+    round_with(args, f64::ceil, Fraction::ceil)
+}
+
+pub fn truncate(args: Args) -> SResult<SExpr> {
+    round_with(args, f64::trunc, Fraction::trunc)
+}
+
+/// Rounds to even on ties, `(round 2.5)` is 2.0.
+pub fn round(args: Args) -> SResult<SExpr> {
+    round_with(args, f64::round_ties_even, Fraction::round)
+}
+
+/// Exact for exact numbers whose root is exact, like 4 or -9/16. Negative
+/// numbers have imaginary roots.
+pub fn sqrt(args: Args) -> SResult<SExpr> {
+    let x = number(args.evaled()?.own_one()?)?;
+    Ok(SExpr::Atom(square_root(x)?))
+}
+
+/// Exact numbers raised to exact integer powers stay exact, `(expt 2/3 -2)`
+/// is 9/4. Anything else is computed with floats. Negative numbers raised
+/// to fractional powers are complex.
+pub fn expt(args: Args) -> SResult<SExpr> {
+    let (base, exponent) = args.evaled()?.own_two()?;
+    let (base, exponent) = (number(base)?, number(exponent)?);
 
-    type I = fn(i64,i64)->i64;
-    type Fl = fn(f64,f64)->f64;
-    type Fr = fn(fraction::Fraction, fraction::Fraction)->fraction::Fraction;
-    let (opi,opfl,opfr): (I, Fl, Fr) = match op_str {
-        '+' => (Add::add,Add::add,Add::add),
-        '-' => (Sub::sub, Sub::sub, Sub::sub),
-        '*' => (Mul::mul, Mul::mul, Mul::mul),
-        '/' => (Div::div, Div::div, Div::div),
-        _   => bail!("Not an arithmetic op: {}", op_str)
+    let fractional = exponent.to_float().is_some_and(|e| e.fract() != 0.0);
+    if !(base.is_real() && exponent.is_real()) || (base.to_float() < Some(0.0) && fractional) {
+        return Ok(SExpr::Atom(complex_expt(base, exponent)?))
+    }
+
+    let b = base.to_float().unwrap();
+
+    let e = match exponent.to_bigint() {
+        Some(e) => e,
+        None => return Ok(sfloat!(b.powf(exponent.to_float().unwrap())))
     };
 
-    use lexer::Token::*;
-    use parser::SExpr::*;
-    // Here we go, couldn't come up with something better
-    let result = args_iter.fold(Ok(init), |acc, x|{
-        let result = match (acc?, x) {
-            (Atom(Integer(a)), Atom(Integer(b))) => {
-                // Like it isnt ugly already
-                if op_str == '/' && b == 0 {
-                    serr!(DivisionByZero)
-                }
-                if op_str == '/' && a % b != 0 {
-                    Atom(Fraction(fraction::Fraction::new(a,b)))
-                } else {
-                    Atom(Integer(opi(a,b)))
-                }
-            },
-            (Atom(Integer(a)), Atom(Float(b))) =>
-                Atom(Float(opfl(a as f64, b))),
-            (Atom(Float(a)), Atom(Integer(b))) =>
-                Atom(Float(opfl(a,b as f64))),
-            (Atom(Float(a)), Atom(Float(b))) =>
-                Atom(Float(opfl(a,b))),
-            (Atom(Fraction(a)), Atom(Fraction(b))) =>
-                Atom(Fraction(opfr(a,b))),
-            (Atom(Fraction(a)), Atom(Integer(b))) =>
-                Atom(Fraction(opfr(a,From::from(b)))),
-            (Atom(Integer(a)), Atom(Fraction(b))) =>
-                Atom(Fraction(opfr(From::from(a), b))),
-            (Atom(Fraction(a)), Atom(Float(b))) =>
-                Atom(Float(opfl(a.into(),b))),
-            (Atom(Float(a)), Atom(Fraction(b))) =>
-                Atom(Float(opfl(a,b.into()))),
-            (a,b) => bail!(TypeMismatch => "number", SExpr::List(vec![a, b]))
-        };
+    match base.to_fraction() {
+        Some(base) => Ok(SExpr::from(exact_expt(base, e)?)),
+        None => match e.to_i64() {
+            Some(e) if e.abs() <= i64::from(i32::MAX) => Ok(sfloat!(b.powi(e as i32))),
+            _ => Ok(sfloat!(b.powf(e.to_f64())))
+        }
+    }
+}
+
+pub fn square(args: Args) -> SResult<SExpr> {
+    let x = args.evaled()?.own_one()?;
+    arith('*', x.clone(), x)
+}
+
+/// Returns the root and the remainder as a list.
+pub fn exact_integer_sqrt(args: Args) -> SResult<SExpr> {
+    let k = args.evaled()?.own_one()?;
+    let n = k.clone().into_bigint()?;
+    if n.is_negative() {
+        bail!(TypeMismatch => "non-negative integer", k)
+    }
 
-        Ok(result)
-    })?;
+    let s = n.sqrt();
+    let r = n - s.clone() * s.clone();
+    Ok(SExpr::Values(vec![SExpr::from(s), SExpr::from(r)]))
+}
+
+pub fn exact(args: Args) -> SResult<SExpr> {
+    let x = number(args.evaled()?.own_one()?)?;
+    Ok(SExpr::Atom(to_exact_number(x)?))
+}
+
+pub fn inexact(args: Args) -> SResult<SExpr> {
+    match number(args.evaled()?.own_one()?)? {
+        Token::Complex(z) => Ok(SExpr::Atom(Complex::from_floats(z.to_floats()))),
+        x => Ok(sfloat!(x.to_float().unwrap()))
+    }
+}
+
+/// (rationalize x y) returns the simplest rational that differs from `x`
+/// by no more than `y`.
+pub fn rationalize(args: Args) -> SResult<SExpr> {
+    let (x, y) = args.evaled()?.own_two()?;
+    let (x, y) = (real(x)?, real(y)?);
 
-    // If it's an whole fraction, return it as int
-    let fixed_result = if let Atom(Fraction(f)) = result {
-        if f.is_int() {
-            Atom(Integer(f.n))
-        } else {
-            result
+    let (fx, fy) = match (to_exact(&x), to_exact(&y)) {
+        (Some(fx), Some(fy)) => (fx, fy),
+        _ => {
+            let (x, y) = (x.to_float().unwrap(), y.to_float().unwrap());
+            let result = if y.is_infinite() && x.is_finite() {
+                0.0
+            } else if y.is_finite() {
+                x
+            } else {
+                f64::NAN
+            };
+            return Ok(sfloat!(result))
         }
-    } else {
-        result
     };
 
-    Ok(fixed_result)
+    let fy = if fy.n.is_negative() { -fy } else { fy };
+    let result = simplest_between(fx.clone() - fy.clone(), fx + fy);
+    if let (Token::Float(_), _) | (_, Token::Float(_)) = (&x, &y) {
+        Ok(sfloat!(f64::from(&result)))
+    } else {
+        Ok(SExpr::from(result))
+    }
 }
 
-pub fn modulo(args: Args) -> SResult<SExpr> {
-    let (x_, y_) = args.evaled()?.own_two()?;
-    let x = x_.as_int()?;
-    let y = y_.as_int()?;
+pub fn number_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.is_numeric()))
+}
 
-    Ok(sint!((x % y + y) % y))
+pub fn real_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(matches!(args.evaled()?.own_one()?, SExpr::Atom(x) if x.is_real())))
 }
 
-pub fn remainder(args: Args) -> SResult<SExpr> {
-    let (x, y) = args.evaled()?.own_two()?;
+pub fn integer_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(match args.evaled()?.own_one()? {
+        SExpr::Atom(Token::Float(x)) => x.fract() == 0.0,
+        x => x.is_integer()
+    }))
+}
 
-    Ok(sint!((x.as_int()? % y.as_int()?)))
+pub fn rational_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(match args.evaled()?.own_one()? {
+        SExpr::Atom(Token::Float(x)) => x.is_finite(),
+        SExpr::Atom(x) => x.is_real(),
+        _ => false
+    }))
 }
 
-pub fn numerator(args: Args) -> SResult<SExpr> {
-    let num = args.evaled()?.own_one()?;
-    let result = match num {
-        SExpr::Atom(Token::Integer(i)) => (fraction::Fraction::from(i).n),
-        SExpr::Atom(Token::Float(i)) => (fraction::Fraction::from(i).n),
-        SExpr::Atom(Token::Fraction(f)) => f.n,
-        x => bail!(TypeMismatch => "number", x)
-    };
+pub fn exact_qm(args: Args) -> SResult<SExpr> {
+    let x = number(args.evaled()?.own_one()?)?;
+    Ok(sbool!(x.is_exact()))
+}
+
+pub fn inexact_qm(args: Args) -> SResult<SExpr> {
+    let x = number(args.evaled()?.own_one()?)?;
+    Ok(sbool!(!x.is_exact()))
+}
+
+pub fn exact_integer_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.is_integer()))
+}
+
+pub fn exact_nonnegative_integer_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(match args.evaled()?.own_one()? {
+        SExpr::Atom(Token::Integer(x)) => x >= 0,
+        SExpr::Atom(Token::BigInt(x)) => !x.is_negative(),
+        _ => false
+    }))
+}
 
-    Ok(sint!(result))
+pub fn exact_rational_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(match args.evaled()?.own_one()? {
+        SExpr::Atom(x) => x.to_fraction().is_some(),
+        _ => false
+    }))
+}
+
+pub fn nan_qm(args: Args) -> SResult<SExpr> {
+    float_predicate(args, f64::is_nan, false)
+}
+
+pub fn infinite_qm(args: Args) -> SResult<SExpr> {
+    float_predicate(args, f64::is_infinite, false)
+}
+
+pub fn finite_qm(args: Args) -> SResult<SExpr> {
+    float_predicate(args, f64::is_finite, true)
+}
+
+/// The numerator of a float is a float too.
+pub fn numerator(args: Args) -> SResult<SExpr> {
+    match real(args.evaled()?.own_one()?)? {
+        Token::Float(x) => Ok(sfloat!(float_to_exact(x)?.n.to_f64())),
+        x => Ok(SExpr::from(x.to_fraction().unwrap().n))
+    }
 }
 
 pub fn denominator(args: Args) -> SResult<SExpr> {
-    let num = args.evaled()?.own_one()?;
-    let result = match num {
-        SExpr::Atom(Token::Integer(i)) => (fraction::Fraction::from(i).d),
-        SExpr::Atom(Token::Float(i)) => (fraction::Fraction::from(i).d),
-        SExpr::Atom(Token::Fraction(f)) => f.d,
-        x => bail!(TypeMismatch => "number", x)
-    };
+    match real(args.evaled()?.own_one()?)? {
+        Token::Float(x) => Ok(sfloat!(float_to_exact(x)?.d.to_f64())),
+        x => Ok(SExpr::from(x.to_fraction().unwrap().d))
+    }
+}
 
-    Ok(sint!(result))
+pub fn exp(args: Args) -> SResult<SExpr> {
+    unary_transcendental(args, f64::exp, complex::exp, |_| false)
 }
 
-pub fn number_string(args: Args) -> SResult<SExpr> {
-    if args.len() == 1 {
-        let num = args.evaled()?.own_one()?;
-        Ok(sstr!(num.to_string()))
-    } else if args.len() == 2 {
-        let (num, radix) = args.evaled()?.own_two()?;
-        Ok(sstr!(Radix::new(num.into_float()?, radix.into_int()? as u32)?.to_string()))
-    } else {
-        bail!(WrongArgCount => 2 as usize, args.len())
+/// (log z [base])
+pub fn log(args: Args) -> SResult<SExpr> {
+    let evaled = args.evaled()?;
+    let ln = |x| -> SResult<Token> {
+        Ok(transcendental(number(x)?, f64::ln, complex::ln, |x| x < 0.0))
+    };
+    match evaled.len() {
+        1 => Ok(SExpr::Atom(ln(evaled.own_one()?)?)),
+        2 => {
+            let (z, base) = evaled.own_two()?;
+            Ok(SExpr::Atom(apply_op('/', ln(z)?, ln(base)?)?))
+        },
+        x => Err(SErr::WrongArgCount(2, x))
     }
 }
 
-pub fn string_number(args: Args) -> SResult<SExpr> {
-    if args.len() == 1 {
-        use lexer::parse_number;
-        let num_str = args.evaled()?.own_one()?.into_str()?;
-        let num_token = parse_number(&num_str)
-            .ok_or_else(|| SErr::new_generic(&format!("Can't parse as number: {}", num_str)))?;
-        Ok(SExpr::Atom(num_token))
-    } else if args.len() == 2 {
-        bail!(Generic => "// FIXME: not implemented")
-    } else {
-        bail!(WrongArgCount => 2 as usize, args.len())
+pub fn sin(args: Args) -> SResult<SExpr> {
+    unary_transcendental(args, f64::sin, complex::sin, |_| false)
+}
+
+pub fn cos(args: Args) -> SResult<SExpr> {
+    unary_transcendental(args, f64::cos, complex::cos, |_| false)
+}
+
+pub fn tan(args: Args) -> SResult<SExpr> {
+    unary_transcendental(args, f64::tan, complex::tan, |_| false)
+}
+
+pub fn asin(args: Args) -> SResult<SExpr> {
+    unary_transcendental(args, f64::asin, complex::asin, |x| x.abs() > 1.0)
+}
+
+pub fn acos(args: Args) -> SResult<SExpr> {
+    unary_transcendental(args, f64::acos, complex::acos, |x| x.abs() > 1.0)
+}
+
+/// (atan z) or (atan y x), the latter only takes real numbers.
+pub fn atan(args: Args) -> SResult<SExpr> {
+    let evaled = args.evaled()?;
+    match evaled.len() {
+        1 => {
+            let z = number(evaled.own_one()?)?;
+            Ok(SExpr::Atom(transcendental(z, f64::atan, complex::atan, |_| false)))
+        },
+        2 => {
+            let (y, x) = evaled.own_two()?;
+            let (y, x) = (real(y)?.to_float().unwrap(), real(x)?.to_float().unwrap());
+            Ok(sfloat!(y.atan2(x)))
+        },
+        x => Err(SErr::WrongArgCount(2, x))
     }
+}
 
+pub fn make_rectangular(args: Args) -> SResult<SExpr> {
+    let (re, im) = args.evaled()?.own_two()?;
+    Ok(SExpr::Atom(Complex::make(real(re)?, real(im)?)))
 }
 
-#[macro_export]
-macro_rules! call_float_fun(
-    ($e: ident) => {
-        |args| {
-            let num = args.evaled()?.own_one()?;
-            let result = num.into_float()?.$e();
-            if result.trunc() == result {
-                Ok((result as i64).into())
-            } else {
-                Ok(result.into())
-            }
-        }
+pub fn make_polar(args: Args) -> SResult<SExpr> {
+    let (magnitude, angle) = args.evaled()?.own_two()?;
+    Ok(SExpr::Atom(Complex::polar(real(magnitude)?, real(angle)?)))
+}
+
+pub fn real_part(args: Args) -> SResult<SExpr> {
+    let z = number(args.evaled()?.own_one()?)?;
+    Ok(SExpr::Atom(z.real_part()))
+}
+
+pub fn imag_part(args: Args) -> SResult<SExpr> {
+    let z = number(args.evaled()?.own_one()?)?;
+    Ok(SExpr::Atom(z.imag_part()))
+}
+
+/// Exact when it can be, `(magnitude 3+4i)` is 5.
+pub fn magnitude(args: Args) -> SResult<SExpr> {
+    let z = number(args.evaled()?.own_one()?)?;
+    let (re, im) = (z.real_part(), z.imag_part());
+    let square = |x: Token| apply_op('*', x.clone(), x);
+
+    let result = match z {
+        Token::Complex(_) => square_root(apply_op('+', square(re)?, square(im)?)?)?,
+        x if x < Token::Integer(0) => apply_op('-', Token::Integer(0), x)?,
+        x => x
     };
-    ($e: ident, $e1: ident) => {
-        |args| {
-            use serr::SErr;
-            let evaled = args.evaled()?;
-            let result = match evaled.len() {
-                1 => evaled.own_one()?.into_float()?.$e(),
-                2 => {
-                    let (f1, f2) = evaled.own_two()?;
-                    f1.into_float()?.$e1(f2.into_float()?)
-                },
-                x => bail!(WrongArgCount => 2 as usize, x)
-            };
+    Ok(SExpr::Atom(result))
+}
 
-            if result.trunc() == result {
-                Ok((result as i64).into())
-            } else {
-                Ok(result.into())
-            }
+/// The angle of non-negative exact numbers is an exact zero.
+pub fn angle(args: Args) -> SResult<SExpr> {
+    match number(args.evaled()?.own_one()?)? {
+        x if x.is_exact() && x >= Token::Integer(0) => Ok(sint!(0)),
+        z => {
+            let (re, im) = float_parts(&z);
+            Ok(sfloat!(im.atan2(re)))
         }
     }
-);
+}
+
+pub fn number_string(args: Args) -> SResult<SExpr> {
+    let evaled = args.evaled()?;
+    let (num, radix) = match evaled.len() {
+        1 => (evaled.own_one()?, 10),
+        2 => {
+            let (num, r) = evaled.own_two()?;
+            (num, radix(r)?)
+        },
+        x => return Err(SErr::WrongArgCount(2, x))
+    };
+
+    match num {
+        SExpr::Atom(Token::Float(_)) | SExpr::Atom(Token::Complex(_)) if radix != 10 => {
+            bail!(Generic => "Inexact and complex numbers can only be written in radix 10")
+        },
+        x@SExpr::Atom(Token::Float(_)) | x@SExpr::Atom(Token::Complex(_)) => Ok(sstr!(x.to_string())),
+        x => Ok(sstr!(x.into_fraction()?.to_str_radix(radix)))
+    }
+}
+
+pub fn string_number(args: Args) -> SResult<SExpr> {
+    let evaled = args.evaled()?;
+    let (num_str, num_token) = match evaled.len() {
+        1 => {
+            let num_str = evaled.own_one()?.into_str()?;
+            let num_token = parse_number(&num_str);
+            (num_str, num_token)
+        },
+        2 => {
+            let (num_str, r) = evaled.own_two()?;
+            let num_str = num_str.into_str()?;
+            let num_token = parse_exact_number(&num_str, radix(r)?);
+            (num_str, num_token)
+        },
+        x => return Err(SErr::WrongArgCount(2, x))
+    };
+
+    let num_token = num_token
+        .ok_or_else(|| SErr::new_generic(&format!("Can't parse as number: {}", num_str)))?;
+    Ok(SExpr::Atom(num_token))
+}
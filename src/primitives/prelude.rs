@@ -5,8 +5,9 @@ pub const PRELUDE: &'static str = "
 (define (id x) x)
 (define (curry func arg1) (lambda arg (apply func (cons arg1 arg))))
 (define (compose f g) (lambda (arg) (f (apply g arg))))
+(define (flip func) (lambda (arg1 arg2) (func arg2 arg1)))
 
-;; folds
+;; folds, the SRFI-1 ones are primitives
 (define (foldr func end lst)
   (if (null? lst)
       end
@@ -17,54 +18,38 @@ pub const PRELUDE: &'static str = "
       accum
       (foldl func (func accum (car lst)) (cdr lst))))
 
-(define (unfold func init pred)
-  (if (pred init)
-      (cons init '())
-      (cons init (unfold func (func init) pred))))
-
-(define fold foldl)
-(define reduce foldr)
-
 ;; ??
 (define (procedure? x) (eq? (typeof x) 'procedure))
 (define (boolean? x) (eq? (typeof x) 'boolean))
 (define (char? x) (eq? (typeof x) 'chr))
 (define (string? x) (eq? (typeof x) 'str))
-(define (integer? x) (eq? (typeof x) 'integer))
-(define (inexact? x) (not (exact? x)))
-(define (exact? x)
-  (define type (typeof x))
-  (or (eq? type 'integer)
-      (eq? type 'fraction)))
-(define (number? x)
-  (define type (typeof x))
-  (or (eq? type 'integer)
-      (eq? type 'fraction)
-      (eq? type 'fraction)))
-(define (pair? x)
-  (define type (typeof x))
-  (and (not (null? x))
-    (or (eq? type 'list)
-        (eq? type 'list-dotted))))
-(define (list? x) (eq? (typeof x) 'list))
 (define (output-port? x)
   (define type (typeof x))
   (or (eq? type 'port-std-out)
+      (eq? type 'port-std-err)
       (eq? type 'port-binary-out)
-      (eq? type 'port-textual-out)))
+      (eq? type 'port-textual-out)
+      (eq? type 'port-string-out)
+      (eq? type 'port-bytevector-out)))
 (define (input-port? x)
   (define type (typeof x))
   (or (eq? type 'port-std-in)
       (eq? type 'port-binary-in)
-      (eq? type 'port-textual-in)))
+      (eq? type 'port-textual-in)
+      (eq? type 'port-string-in)
+      (eq? type 'port-bytevector-in)))
 (define (textual-port? x)
   (define type (typeof x))
   (or (eq? type 'port-textual-in)
-      (eq? type 'port-textual-out)))
+      (eq? type 'port-textual-out)
+      (eq? type 'port-string-in)
+      (eq? type 'port-string-out)))
 (define (binary-port? x)
   (define type (typeof x))
   (or (eq? type 'port-binary-in)
-      (eq? type 'port-binary-out)))
+      (eq? type 'port-binary-out)
+      (eq? type 'port-bytevector-in)
+      (eq? type 'port-bytevector-out)))
 
 ;; booleans
 (define (not x) (if x #f #t))
@@ -84,29 +69,11 @@ pub const PRELUDE: &'static str = "
 ;; lists
 (define (list . xs) xs)
 (define sublist list-copy)
-(define (list-ref s i) (list-copy s i (+ i 1)))
-(define (null? x) (if (eqv? x '()) #t #f))
 (define (sum . lst) (fold + 0 lst))
 (define (product . lst) (fold * 1 lst))
-(define (map func lst) (foldr (lambda (x y) (cons (func x) y)) '() lst))
-(define (filter pred lst) (foldr (lambda (x y) (if (pred x) (cons x y) y)) '() lst))
-(define (reverse lst) (fold (flip cons) '() lst))
-(define (length lst) (fold (lambda (x y) (+ x 1)) 0 lst))
-(define (max first . rest) (fold (lambda (old new) (if (> old new) old new)) first rest))
-(define (min first . rest) (fold (lambda (old new) (if (< old new) old new)) first rest))
-(define (list-tail lst n) (if (<= n 0) lst (list-tail (cdr lst) (- n 1))))
-(define (list-head lst n) (if (<= n 0) '() (cons (car lst) (list-head (cdr lst) (- n 1)))))
-(define (list-ref lst n) (car (list-tail lst n)))
-
-
-(define (mem-helper pred op) (lambda (acc next) (if (and (not acc) (pred (op next))) next acc)))
-(define (memq obj lst)       (fold (mem-helper (curry eq? obj) id) #f lst))
-(define (memv obj lst)       (fold (mem-helper (curry eqv? obj) id) #f lst))
-(define (member obj lst)     (fold (mem-helper (curry equal? obj) id) #f lst))
-(define (assq obj alist)     (fold (mem-helper (curry eq? obj) car) #f alist))
-(define (assv obj alist)     (fold (mem-helper (curry eqv? obj) car) #f alist))
-(define (assoc obj alist)    (fold (mem-helper (curry equal? obj) car) #f alist))
-
+(define (max first . rest) (fold (lambda (new old) (if (> old new) old new)) first rest))
+(define (min first . rest) (fold (lambda (new old) (if (< old new) old new)) first rest))
+(define list-head take)
 
 (define (caar x) (car (car x)))
 (define (cadr x) (car (cdr x)))
@@ -166,8 +133,6 @@ pub const PRELUDE: &'static str = "
 (define string-ci<=? (curry string-ci <=))
 (define string-ci>=? (curry string-ci >=))
 
-(define substring string-copy)
-(define (string-ref s i) (convert-type 'chr (string-copy s i (+ i 1))))
 (define (string . xs) (convert-type 'str xs))
 
 (define (string-set! str k chr)
@@ -195,4 +160,240 @@ pub const PRELUDE: &'static str = "
   (define f (open-input-file str))
   (proc f)
   (close-port f))
+
+(define (call-with-output-string proc)
+  (define port (open-output-string))
+  (proc port)
+  (get-output-string port))
+
+(define (with-output-to-string thunk)
+  (define port (open-output-string))
+  (parameterize ((current-output-port port))
+    (thunk))
+  (get-output-string port))
+
+;; vectors
+(define (vector-map func vec . vecs)
+  (define len (apply min (vector-length vec) (map vector-length vecs)))
+  (define result (make-vector len))
+  (define (loop i)
+    (if (< i len)
+        (begin
+          (vector-set! result i (apply func (vector-ref vec i)
+                                       (map (lambda (v) (vector-ref v i)) vecs)))
+          (loop (+ i 1)))))
+  (loop 0)
+  result)
+
+(define (vector-for-each func vec . vecs)
+  (define len (apply min (vector-length vec) (map vector-length vecs)))
+  (define (loop i)
+    (if (< i len)
+        (begin
+          (apply func (vector-ref vec i) (map (lambda (v) (vector-ref v i)) vecs))
+          (loop (+ i 1)))))
+  (loop 0))
+
+;; streams (SRFI-41), a stream is a promise of either '() or a pair of a
+;; promise of the element and the rest of the stream
+(define stream-null (make-promise '()))
+
+(define-syntax stream-cons
+  (syntax-rules ()
+    ((_ obj strm) (delay (cons (delay obj) (delay-force strm))))))
+
+(define-syntax stream
+  (syntax-rules ()
+    ((_) stream-null)
+    ((_ x y ...) (stream-cons x (stream y ...)))))
+
+(define stream? promise?)
+(define (stream-null? s) (null? (force s)))
+(define (stream-pair? s) (and (promise? s) (pair? (force s))))
+(define (stream-car s) (force (car (force s))))
+(define (stream-cdr s) (cdr (force s)))
+
+;; The loops below are toplevel procedures instead of named lets, a closure
+;; would keep the head of the stream alive while it is being walked.
+(define (any-stream-null? ss) (memq #t (map stream-null? ss)))
+
+(define (list->stream lst)
+  (delay-force
+    (if (null? lst)
+        stream-null
+        (stream-cons (car lst) (list->stream (cdr lst))))))
+
+(define (stream->list-loop n s acc)
+  (if (or (= n 0) (stream-null? s))
+      (reverse acc)
+      (stream->list-loop (- n 1) (stream-cdr s) (cons (stream-car s) acc))))
+
+(define stream->list
+  (case-lambda
+    ((s) (stream->list-loop -1 s '()))
+    ((n s) (stream->list-loop n s '()))))
+
+(define (port->stream . port)
+  (delay-force
+    (let ((c (apply read-char port)))
+      (if (eof-object? c)
+          stream-null
+          (stream-cons c (apply port->stream port))))))
+
+(define (stream-map-loop f ss)
+  (delay-force
+    (if (any-stream-null? ss)
+        stream-null
+        (stream-cons (apply f (map stream-car ss))
+                     (stream-map-loop f (map stream-cdr ss))))))
+
+(define (stream-map-1 f s)
+  (delay-force
+    (if (stream-null? s)
+        stream-null
+        (stream-cons (f (stream-car s)) (stream-map-1 f (stream-cdr s))))))
+
+(define (stream-map f s . ss)
+  (if (null? ss)
+      (stream-map-1 f s)
+      (stream-map-loop f (cons s ss))))
+
+(define (stream-for-each-loop f ss)
+  (unless (any-stream-null? ss)
+    (apply f (map stream-car ss))
+    (stream-for-each-loop f (map stream-cdr ss))))
+
+(define (stream-for-each-1 f s)
+  (unless (stream-null? s)
+    (f (stream-car s))
+    (stream-for-each-1 f (stream-cdr s))))
+
+(define (stream-for-each f s . ss)
+  (if (null? ss)
+      (stream-for-each-1 f s)
+      (stream-for-each-loop f (cons s ss))))
+
+(define (stream-filter pred s)
+  (delay-force
+    (cond ((stream-null? s) stream-null)
+          ((pred (stream-car s))
+           (stream-cons (stream-car s) (stream-filter pred (stream-cdr s))))
+          (else (stream-filter pred (stream-cdr s))))))
+
+(define (stream-take n s)
+  (delay-force
+    (if (or (<= n 0) (stream-null? s))
+        stream-null
+        (stream-cons (stream-car s) (stream-take (- n 1) (stream-cdr s))))))
+
+(define (stream-drop n s)
+  (delay-force
+    (if (or (<= n 0) (stream-null? s))
+        s
+        (stream-drop (- n 1) (stream-cdr s)))))
+
+(define (stream-ref s n)
+  (if (= n 0)
+      (stream-car s)
+      (stream-ref (stream-cdr s) (- n 1))))
+
+;; exceptions
+(define-syntax guard
+  (syntax-rules ()
+    ((_ (var clause ...) body ...)
+     ((call/cc
+       (lambda (guard-k)
+         (with-exception-handler
+          (lambda (condition)
+            ((lambda (handle)
+               (if handle
+                   (guard-k handle)
+                   (raise-continuable condition)))
+             ((lambda (var) (guard-aux clause ...)) condition)))
+          (lambda ()
+            ((lambda (result) (lambda () result))
+             ((lambda () body ...)))))))))))
+
+;; Returns a thunk for the body of the first clause that matches, or #f.
+(define-syntax guard-aux
+  (syntax-rules (else =>)
+    ((_) #f)
+    ((_ (else e1 e2 ...)) (lambda () e1 e2 ...))
+    ((_ (test => f) clause ...)
+     ((lambda (t) (if t (lambda () (f t)) (guard-aux clause ...))) test))
+    ((_ (test) clause ...)
+     ((lambda (t) (if t (lambda () t) (guard-aux clause ...))) test))
+    ((_ (test e1 e2 ...) clause ...)
+     (if test (lambda () e1 e2 ...) (guard-aux clause ...)))))
+
+;; hash tables (SRFI-69 and SRFI-125), the rest is in Rust
+(define (hash-table-update! table key updater . rest)
+  (hash-table-set! table key (updater (apply hash-table-ref table key rest))))
+
+(define (hash-table-update!/default table key updater default)
+  (hash-table-set! table key (updater (hash-table-ref/default table key default))))
+
+(define hash-table-exists? hash-table-contains?)
+
+(define (hash-table-walk table proc)
+  (let loop ((entries (hash-table->alist table)))
+    (if (pair? entries)
+        (begin
+          (proc (caar entries) (cdar entries))
+          (loop (cdr entries))))))
+
+;; Takes the SRFI-69 (table kons knil) and the SRFI-125 (kons knil table)
+;; argument orders.
+(define (hash-table-fold x y z)
+  (define-values (table kons knil)
+    (if (hash-table? x) (values x y z) (values z x y)))
+  (let loop ((entries (hash-table->alist table)) (acc knil))
+    (if (pair? entries)
+        (loop (cdr entries) (kons (caar entries) (cdar entries) acc))
+        acc)))
+
+;; (hash-table-count table) is the number of associations and
+;; (hash-table-count pred table) the number of the ones that satisfy pred.
+(define (hash-table-count x . rest)
+  (if (null? rest)
+      (hash-table-size x)
+      (hash-table-fold (car rest) (lambda (k v n) (if (x k v) (+ n 1) n)) 0)))
+
+;; The first association of a key wins.
+(define (alist->hash-table alist . rest)
+  (define table (apply make-hash-table rest))
+  (let loop ((alist alist))
+    (if (pair? alist)
+        (begin
+          (if (not (hash-table-contains? table (caar alist)))
+              (hash-table-set! table (caar alist) (cdar alist)))
+          (loop (cdr alist)))))
+  table)
+
+;; records (SRFI-9), the constructor can also be a bare name that takes all
+;; the fields or #f for none
+(define-syntax define-record-type
+  (syntax-rules ()
+    ((_ type #f pred field ...)
+     (begin
+       (define type (make-record-type 'type (map car '(field ...))))
+       (define pred (record-predicate type))
+       (define-record-field type field) ...))
+    ((_ type (constructor arg ...) pred field ...)
+     (begin
+       (define-record-type type #f pred field ...)
+       (define constructor (record-constructor type '(arg ...)))))
+    ((_ type constructor pred field ...)
+     (begin
+       (define-record-type type #f pred field ...)
+       (define constructor (record-constructor type))))))
+
+(define-syntax define-record-field
+  (syntax-rules ()
+    ((_ type (field accessor))
+     (define accessor (record-accessor type 'field)))
+    ((_ type (field accessor modifier))
+     (begin
+       (define accessor (record-accessor type 'field))
+       (define modifier (record-modifier type 'field))))))
 ";
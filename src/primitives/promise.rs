@@ -0,0 +1,18 @@
+use parser::SExpr;
+use evaluator::Args;
+use promise::{Promise, PromiseData};
+use serr::SResult;
+
+//
+// Functions
+//
+pub fn make_promise(args: Args) -> SResult<SExpr> {
+    match args.evaled()?.own_one()? {
+        x@SExpr::Promise(_) => Ok(x),
+        x => Ok(SExpr::Promise(PromiseData::new(Promise::Done(x))))
+    }
+}
+
+pub fn promise_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(matches!(args.evaled()?.own_one()?, SExpr::Promise(_))))
+}
@@ -0,0 +1,110 @@
+use parser::SExpr;
+use evaluator::Args;
+use procedure::ProcedureData;
+use record::{RecordData, RecordProcedure, RecordTypeData};
+use serr::{SErr, SResult};
+
+//
+// Helpers
+//
+fn as_record_type(x: &SExpr) -> SResult<&RecordTypeData> {
+    match x {
+        SExpr::RecordType(x) => Ok(x),
+        x => bail!(TypeMismatch => "record type", x)
+    }
+}
+
+fn as_record(x: &SExpr) -> SResult<&RecordData> {
+    match x {
+        SExpr::Record(x) => Ok(x),
+        x => bail!(TypeMismatch => "record", x)
+    }
+}
+
+fn field_index(rtype: &RecordTypeData, field: SExpr) -> SResult<usize> {
+    rtype.field_index(&field.into_symbol()?)
+}
+
+//
+// Functions
+//
+
+/// (make-record-type name (field ...))
+pub fn make_record_type(args: Args) -> SResult<SExpr> {
+    let (name, fields) = args.evaled()?.own_two()?;
+    let fields = fields.into_list()?
+        .into_iter()
+        .map(|x| x.into_symbol())
+        .collect::<SResult<Vec<_>>>()?;
+
+    Ok(SExpr::RecordType(RecordTypeData::new(&name.into_symbol()?, fields)))
+}
+
+/// (record-constructor type [(field ...)]) takes the values of all the
+/// fields if they aren't given.
+pub fn record_constructor(args: Args) -> SResult<SExpr> {
+    let (rtype, mut rest) = args.evaled()?.own_one_rest()?;
+    let rtype = as_record_type(&rtype)?;
+    let indexes = match rest.pop() {
+        Some(fields) => fields.into_list()?
+            .into_iter()
+            .map(|x| field_index(rtype, x))
+            .collect::<SResult<_>>()?,
+        None => (0..rtype.fields().len()).collect()
+    };
+
+    Ok(ProcedureData::new_record(RecordProcedure::Constructor(rtype.clone(), indexes)))
+}
+
+pub fn record_predicate(args: Args) -> SResult<SExpr> {
+    let rtype = args.evaled()?.own_one()?;
+    Ok(ProcedureData::new_record(RecordProcedure::Predicate(as_record_type(&rtype)?.clone())))
+}
+
+pub fn record_accessor(args: Args) -> SResult<SExpr> {
+    let (rtype, field) = args.evaled()?.own_two()?;
+    let rtype = as_record_type(&rtype)?;
+    let index = field_index(rtype, field)?;
+    Ok(ProcedureData::new_record(RecordProcedure::Accessor(rtype.clone(), index)))
+}
+
+pub fn record_modifier(args: Args) -> SResult<SExpr> {
+    let (rtype, field) = args.evaled()?.own_two()?;
+    let rtype = as_record_type(&rtype)?;
+    let index = field_index(rtype, field)?;
+    Ok(ProcedureData::new_record(RecordProcedure::Modifier(rtype.clone(), index)))
+}
+
+pub fn record_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(matches!(args.evaled()?.own_one()?, SExpr::Record(_))))
+}
+
+pub fn record_type_descriptor(args: Args) -> SResult<SExpr> {
+    let record = args.evaled()?.own_one()?;
+    Ok(SExpr::RecordType(as_record(&record)?.record_type().clone()))
+}
+
+pub fn record_type_name(args: Args) -> SResult<SExpr> {
+    let rtype = args.evaled()?.own_one()?;
+    Ok(ssymbol!(as_record_type(&rtype)?.name()))
+}
+
+pub fn record_type_field_names(args: Args) -> SResult<SExpr> {
+    let rtype = args.evaled()?.own_one()?;
+    let names = as_record_type(&rtype)?.fields()
+        .iter()
+        .map(|x| ssymbol!(x.clone()))
+        .collect();
+    Ok(SExpr::list_from(names))
+}
+
+/// (record-fields record) returns the fields as an association list, like
+/// `((x . 1) (y . 2))`.
+pub fn record_fields(args: Args) -> SResult<SExpr> {
+    let record = args.evaled()?.own_one()?;
+    let fields = as_record(&record)?.fields()
+        .into_iter()
+        .map(|(name, value)| SExpr::cons(ssymbol!(name), value))
+        .collect();
+    Ok(SExpr::list_from(fields))
+}
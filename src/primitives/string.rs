@@ -1,7 +1,10 @@
-use parser::SExpr;
-use evaluator::Args;
-use serr::{SErr, SResult};
+use std::iter;
 
+use parser::{SExpr, SExprs};
+use evaluator::{self, Args};
+use env::EnvRef;
+use primitives::vector::{index, range};
+use serr::{SErr, SResult};
 
 #[macro_export]
 macro_rules! call_chr_fun(
@@ -32,32 +35,158 @@ macro_rules! call_str_fun(
     };
 );
 
-pub fn string_copy(args: Args) -> SResult<SExpr> {
-    let evaled = args.evaled()?;
-    let string = if evaled.len() == 1 {
-        evaled.own_one()?.into_str()?
-    } else if evaled.len() == 2 {
-        let (string_, start_) = evaled.own_two()?;
-        let string = string_.into_str()?;
-        let start = start_.into_int()? as usize;
-        let len = string.len();
-        string.get(start..)
-            .ok_or_else(|| SErr::IndexOutOfBounds(len, start))?
-            .to_string()
-    } else if evaled.len() == 3 {
-        let (string_, start_, end_) = evaled.own_three()?;
-        let string = string_.into_str()?;
-        let start = start_.into_int()? as usize;
-        let end = end_.into_int()? as usize;
-        let len = string.len();
-        string.get(start..end)
-            .ok_or_else(|| SErr::IndexOutOfBounds(len, end))?
-            .to_string()
-    } else {
-        bail!(WrongArgCount => 3 as usize, evaled.len())
-    };
+//
+// Helpers
+//
+
+/// The characters of a string argument and the part of them that the
+/// optional `start` and `end` arguments select. Indexes count characters,
+/// not bytes.
+struct Substring {
+    chars: Vec<char>,
+    start: usize,
+    end: usize,
+}
+
+impl Substring {
+    fn new(string: SExpr, start: Option<SExpr>, end: Option<SExpr>) -> SResult<Substring> {
+        let chars: Vec<char> = string.into_str()?.chars().collect();
+        let (start, end) = range(chars.len(), start, end)?;
+        Ok(Substring { chars, start, end })
+    }
+
+    /// Takes the string and its `start` and `end` from `args`.
+    fn from_args<I>(string: SExpr, args: &mut I) -> SResult<Substring>
+        where I: Iterator<Item=SExpr>
+    {
+        let start = args.next();
+        Substring::new(string, start, args.next())
+    }
+
+    fn chars(&self) -> &[char] {
+        &self.chars[self.start..self.end]
+    }
+
+    fn to_sexpr(&self) -> SExpr {
+        sstr!(self.chars().iter().collect::<String>())
+    }
+}
+
+/// What `string-index`, `string-count`, `string-trim` and the like look for,
+/// a character or a predicate on characters.
+enum CharMatcher {
+    Char(char),
+    Whitespace,
+    Predicate(SExpr, EnvRef),
+}
+
+impl CharMatcher {
+    fn new(x: SExpr, env: &EnvRef) -> SResult<CharMatcher> {
+        match x {
+            SExpr::Atom(_) if x.is_chr() => Ok(CharMatcher::Char(x.into_chr()?)),
+            SExpr::Procedure(_) => Ok(CharMatcher::Predicate(x, env.clone_ref())),
+            x => bail!(TypeMismatch => "char or predicate", x)
+        }
+    }
+
+    fn matches(&self, c: char) -> SResult<bool> {
+        match self {
+            CharMatcher::Char(x) => Ok(*x == c),
+            CharMatcher::Whitespace => Ok(c.is_whitespace()),
+            CharMatcher::Predicate(f, env) => Ok(evaluator::apply(f, vec![c.into()], env)?.to_bool())
+        }
+    }
+
+    /// Position of the first character of `chars` that matches, or doesn't.
+    fn position(&self, chars: &[char], matching: bool) -> SResult<Option<usize>> {
+        for (i, c) in chars.iter().enumerate() {
+            if self.matches(*c)? == matching {
+                return Ok(Some(i))
+            }
+        }
+        Ok(None)
+    }
+
+    fn rposition(&self, chars: &[char], matching: bool) -> SResult<Option<usize>> {
+        for (i, c) in chars.iter().enumerate().rev() {
+            if self.matches(*c)? == matching {
+                return Ok(Some(i))
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn check_arg_count(args: &Args, min: usize, max: usize) -> SResult<()> {
+    if args.len() < min {
+        return Err(SErr::WrongArgCount(min, args.len()))
+    }
+    if args.len() > max {
+        return Err(SErr::WrongArgCount(max, args.len()))
+    }
+    Ok(())
+}
+
+fn index_or_false(i: Option<usize>) -> SExpr {
+    match i {
+        Some(i) => sint!(i as i64),
+        None => sbool!(false)
+    }
+}
+
+/// Where `pattern` first occurs in `chars`.
+fn search(chars: &[char], pattern: &[char]) -> Option<usize> {
+    if pattern.is_empty() {
+        return Some(0)
+    }
+    chars.windows(pattern.len()).position(|x| x == pattern)
+}
+
+/// Byte offset of the character at `i`, `i` can be the length of `string`.
+fn byte_offset(string: &str, i: usize) -> SResult<usize> {
+    string.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(iter::once(string.len()))
+        .nth(i)
+        .ok_or_else(|| SErr::IndexOutOfBounds(string.chars().count(), i))
+}
 
-    Ok(sstr!(string))
+/// Takes the two strings of `string-prefix?`, `string-contains` and the
+/// like, each followed by its optional `start` and `end`.
+fn two_substrings(args: Args) -> SResult<(Substring, Substring)> {
+    check_arg_count(&args, 2, 6)?;
+    let mut args = args.evaled()?.into_iter();
+    let s1 = args.next().unwrap();
+    let s2 = args.next().unwrap();
+    let (start1, end1) = (args.next(), args.next());
+    let (start2, end2) = (args.next(), args.next());
+    Ok((Substring::new(s1, start1, end1)?, Substring::new(s2, start2, end2)?))
+}
+
+//
+// Functions
+//
+
+pub fn string_length(args: Args) -> SResult<SExpr> {
+    let string = args.evaled()?.own_one()?;
+    Ok(sint!(string.into_str()?.chars().count() as i64))
+}
+
+pub fn string_ref(args: Args) -> SResult<SExpr> {
+    let (string, k) = args.evaled()?.own_two()?;
+    let string = string.into_str()?;
+    let k = index(k)?;
+    match string.chars().nth(k) {
+        Some(c) => Ok(c.into()),
+        None => bail!(IndexOutOfBounds => string.chars().count(), k)
+    }
+}
+
+/// (string-copy string [start [end]]), also `substring`.
+pub fn string_copy(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 1, 3)?;
+    let (string, rest) = args.evaled()?.own_one_rest()?;
+    Ok(Substring::from_args(string, &mut rest.into_iter())?.to_sexpr())
 }
 
 pub fn string_append(args: Args) -> SResult<SExpr> {
@@ -72,16 +201,18 @@ pub fn string_append(args: Args) -> SResult<SExpr> {
 
 pub fn string_replace_range_em(args: Args) -> SResult<SExpr> {
     let (string_, start_, end_, replacement_) = args.evaled()?.own_four()?;
-    let start = start_.into_int()? as usize;
-    let end = end_.into_int()? as usize;
     let replacement = replacement_.into_str()?;
 
     let string = string_.as_str()?;
+    let (start, end) = {
+        let string = string.borrow();
+        let (start, end) = range(string.chars().count(), Some(start_), Some(end_))?;
+        (byte_offset(&string, start)?, byte_offset(&string, end)?)
+    };
     string.borrow_mut().replace_range(start..end, &replacement);
     Ok(SExpr::Unspecified)
 }
 
-
 pub fn make_string(args: Args) -> SResult<SExpr> {
     let evaled = args.evaled()?;
     if evaled.len() == 1 {
@@ -102,3 +233,308 @@ pub fn make_string(args: Args) -> SResult<SExpr> {
         bail!(WrongArgCount => 2 as usize, evaled.len())
     }
 }
+
+/// (string-index string pred [start end]) returns the index of the first
+/// character that matches `pred`, which is a character or a predicate.
+pub fn string_index(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 2, 4)?;
+    let env = args.env();
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let matcher = CharMatcher::new(args.next().unwrap(), &env)?;
+    let s = Substring::from_args(string, &mut args)?;
+    Ok(index_or_false(matcher.position(s.chars(), true)?.map(|i| i + s.start)))
+}
+
+/// Like `string-index`, but searches from the end.
+pub fn string_index_right(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 2, 4)?;
+    let env = args.env();
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let matcher = CharMatcher::new(args.next().unwrap(), &env)?;
+    let s = Substring::from_args(string, &mut args)?;
+    Ok(index_or_false(matcher.rposition(s.chars(), true)?.map(|i| i + s.start)))
+}
+
+/// (string-search-forward pattern string [start end]) returns the index of
+/// the first occurrence of `pattern` in `string`.
+pub fn string_search_forward(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 2, 4)?;
+    let mut args = args.evaled()?.into_iter();
+    let pattern: Vec<char> = args.next().unwrap().into_str()?.chars().collect();
+    let string = args.next().unwrap();
+    let s = Substring::from_args(string, &mut args)?;
+    Ok(index_or_false(search(s.chars(), &pattern).map(|i| i + s.start)))
+}
+
+/// (string-contains s1 s2 [start1 end1 start2 end2]) returns the index of
+/// the first occurrence of `s2` in `s1`.
+pub fn string_contains(args: Args) -> SResult<SExpr> {
+    let (s1, s2) = two_substrings(args)?;
+    Ok(index_or_false(search(s1.chars(), s2.chars()).map(|i| i + s1.start)))
+}
+
+/// (string-prefix? s1 s2 [start1 end1 start2 end2]) checks if `s1` is a
+/// prefix of `s2`.
+pub fn string_prefix_qm(args: Args) -> SResult<SExpr> {
+    let (s1, s2) = two_substrings(args)?;
+    Ok(sbool!(s2.chars().starts_with(s1.chars())))
+}
+
+pub fn string_suffix_qm(args: Args) -> SResult<SExpr> {
+    let (s1, s2) = two_substrings(args)?;
+    Ok(sbool!(s2.chars().ends_with(s1.chars())))
+}
+
+/// Pads or truncates the string to `len` characters, keeping its right end
+/// for `string-pad` and its left end for `string-pad-right`.
+fn pad(args: Args, left: bool) -> SResult<SExpr> {
+    check_arg_count(&args, 2, 5)?;
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let len = index(args.next().unwrap())?;
+    let fill = match args.next() {
+        Some(x) => x.into_chr()?,
+        None => ' '
+    };
+    let s = Substring::from_args(string, &mut args)?;
+    let chars = s.chars();
+
+    let result: String = if left {
+        let skip = chars.len().saturating_sub(len);
+        iter::repeat_n(fill, len.saturating_sub(chars.len()))
+            .chain(chars[skip..].iter().cloned())
+            .collect()
+    } else {
+        chars.iter().cloned()
+            .chain(iter::repeat(fill))
+            .take(len)
+            .collect()
+    };
+    Ok(sstr!(result))
+}
+
+/// (string-pad string len [char start end])
+pub fn string_pad(args: Args) -> SResult<SExpr> {
+    pad(args, true)
+}
+
+pub fn string_pad_right(args: Args) -> SResult<SExpr> {
+    pad(args, false)
+}
+
+/// Removes the characters that match from the left and/or right end of the
+/// string, whitespace by default.
+fn trim(args: Args, left: bool, right: bool) -> SResult<SExpr> {
+    check_arg_count(&args, 1, 4)?;
+    let env = args.env();
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let matcher = match args.next() {
+        Some(x) => CharMatcher::new(x, &env)?,
+        None => CharMatcher::Whitespace
+    };
+    let s = Substring::from_args(string, &mut args)?;
+    let chars = s.chars();
+
+    let start = if left {
+        matcher.position(chars, false)?.unwrap_or(chars.len())
+    } else {
+        0
+    };
+    let end = if right {
+        matcher.rposition(chars, false)?.map_or(start, |i| i + 1)
+    } else {
+        chars.len()
+    };
+    Ok(sstr!(chars[start..end.max(start)].iter().collect::<String>()))
+}
+
+/// (string-trim string [pred start end])
+pub fn string_trim(args: Args) -> SResult<SExpr> {
+    trim(args, true, false)
+}
+
+pub fn string_trim_right(args: Args) -> SResult<SExpr> {
+    trim(args, false, true)
+}
+
+pub fn string_trim_both(args: Args) -> SResult<SExpr> {
+    trim(args, true, true)
+}
+
+/// (string-join strings [delimiter [grammar]]), the delimiter is a space by
+/// default and the grammar is one of `infix` (the default), `strict-infix`,
+/// `prefix` and `suffix`.
+pub fn string_join(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 1, 3)?;
+    let mut args = args.evaled()?.into_iter();
+    let strings = args.next().unwrap()
+        .into_list()?
+        .into_iter()
+        .map(|x| x.into_str())
+        .collect::<SResult<Vec<_>>>()?;
+    let delimiter = match args.next() {
+        Some(x) => x.into_str()?,
+        None => " ".to_string()
+    };
+    let grammar = match args.next() {
+        Some(x) => x.into_symbol()?,
+        None => "infix".to_string()
+    };
+
+    let joined = strings.join(&delimiter);
+    let result = match grammar.as_ref() {
+        "infix" => joined,
+        "strict-infix" if strings.is_empty() => bail!("string-join: no strings to join"),
+        "strict-infix" => joined,
+        _ if strings.is_empty() => joined,
+        "prefix" => delimiter + &joined,
+        "suffix" => joined + &delimiter,
+        x => bail!("string-join: unknown grammar {}", x)
+    };
+    Ok(sstr!(result))
+}
+
+/// (string-split string delimiter [start end]) splits at each occurrence of
+/// `delimiter`, which is a string, a character or a predicate. Empty fields
+/// are kept.
+pub fn string_split(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 2, 4)?;
+    let env = args.env();
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let delimiter = args.next().unwrap();
+    let s = Substring::from_args(string, &mut args)?;
+    let chars = s.chars();
+
+    let mut fields = vec![];
+    let mut field_start = 0;
+    if delimiter.is_str() {
+        let delimiter: Vec<char> = delimiter.into_str()?.chars().collect();
+        if delimiter.is_empty() {
+            bail!("string-split: empty delimiter")
+        }
+        while let Some(i) = search(&chars[field_start..], &delimiter) {
+            fields.push(&chars[field_start..field_start + i]);
+            field_start += i + delimiter.len();
+        }
+    } else {
+        let matcher = CharMatcher::new(delimiter, &env)?;
+        while let Some(i) = matcher.position(&chars[field_start..], true)? {
+            fields.push(&chars[field_start..field_start + i]);
+            field_start += i + 1;
+        }
+    }
+    fields.push(&chars[field_start..]);
+
+    let fields = fields.into_iter()
+        .map(|x| sstr!(x.iter().collect::<String>()))
+        .collect();
+    Ok(SExpr::list_from(fields))
+}
+
+/// The characters that `string-map` and `string-for-each` go through. Either
+/// one string with optional `start` and `end` as in SRFI-13, or several
+/// strings as in R7RS, which are walked together until the shortest one
+/// runs out.
+fn char_rows(args: SExprs) -> SResult<Vec<SExprs>> {
+    let mut args = args.into_iter();
+    let string = args.next().ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+    let rest: SExprs = args.collect();
+
+    if rest.first().is_some_and(|x| x.is_str()) {
+        let strings = iter::once(string).chain(rest)
+            .map(|x| Ok(x.into_str()?.chars().collect::<Vec<_>>()))
+            .collect::<SResult<Vec<_>>>()?;
+        let len = strings.iter().map(|x| x.len()).min().unwrap_or(0);
+        Ok((0..len).map(|i| strings.iter().map(|x| x[i].into()).collect()).collect())
+    } else {
+        if rest.len() > 2 {
+            return Err(SErr::WrongArgCount(4, rest.len() + 2))
+        }
+        let s = Substring::from_args(string, &mut rest.into_iter())?;
+        Ok(s.chars().iter().map(|c| vec![(*c).into()]).collect())
+    }
+}
+
+/// (string-map proc string [start end]) or (string-map proc string1 ...)
+pub fn string_map(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (procedure, rest) = args.evaled()?.own_one_rest()?;
+    procedure.as_proc()?;
+    let result = char_rows(rest)?
+        .into_iter()
+        .map(|row| evaluator::apply(&procedure, row, &env)?.into_chr())
+        .collect::<SResult<String>>()?;
+    Ok(sstr!(result))
+}
+
+/// (string-for-each proc string [start end]) or
+/// (string-for-each proc string1 ...)
+pub fn string_for_each(args: Args) -> SResult<SExpr> {
+    let env = args.env();
+    let (procedure, rest) = args.evaled()?.own_one_rest()?;
+    procedure.as_proc()?;
+    for row in char_rows(rest)? {
+        evaluator::apply(&procedure, row, &env)?;
+    }
+    Ok(SExpr::Unspecified)
+}
+
+/// (string-count string pred [start end])
+pub fn string_count(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 2, 4)?;
+    let env = args.env();
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let matcher = CharMatcher::new(args.next().unwrap(), &env)?;
+    let s = Substring::from_args(string, &mut args)?;
+
+    let mut count = 0;
+    for c in s.chars() {
+        if matcher.matches(*c)? {
+            count += 1;
+        }
+    }
+    Ok(sint!(count))
+}
+
+/// (string-reverse string [start end])
+pub fn string_reverse(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 1, 3)?;
+    let (string, rest) = args.evaled()?.own_one_rest()?;
+    let s = Substring::from_args(string, &mut rest.into_iter())?;
+    Ok(sstr!(s.chars().iter().rev().collect::<String>()))
+}
+
+/// (string-tokenize string [token-chars start end]) returns the longest
+/// runs of characters that match `token-chars`, which are the ones that
+/// aren't whitespace by default.
+pub fn string_tokenize(args: Args) -> SResult<SExpr> {
+    check_arg_count(&args, 1, 4)?;
+    let env = args.env();
+    let mut args = args.evaled()?.into_iter();
+    let string = args.next().unwrap();
+    let (matcher, token_chars) = match args.next() {
+        Some(x) => (CharMatcher::new(x, &env)?, true),
+        None => (CharMatcher::Whitespace, false)
+    };
+    let s = Substring::from_args(string, &mut args)?;
+
+    let mut tokens = vec![];
+    let mut token = String::new();
+    for c in s.chars() {
+        if matcher.matches(*c)? == token_chars {
+            token.push(*c);
+        } else if !token.is_empty() {
+            tokens.push(sstr!(token.clone()));
+            token.clear();
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(sstr!(token));
+    }
+    Ok(SExpr::list_from(tokens))
+}
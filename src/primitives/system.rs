@@ -3,8 +3,9 @@ use std::fs::{remove_file, read_to_string};
 use std::env;
 use std::process::Command;
 
-use lexer::tokenize;
-use parser::{parse, SExpr};
+use lexer::TokenIterator;
+use parser::{parse_single, SExpr};
+use expander::expand;
 use evaluator::Args;
 use port::current_output_port;
 use serr::SResult;
@@ -35,15 +36,17 @@ pub fn get_environment_variables(_args: Args) -> SResult<SExpr> {
         .map(|(key, val)| sdottedlist![sstr!(key); sstr!(val)])
         .collect();
 
-    Ok(SExpr::List(vars))
+    Ok(SExpr::list_from(vars))
 }
 
 pub fn load(args: Args) -> SResult<SExpr> {
     let env = args.env();
-    let scm = read_to_string(get_path_from_args(args)?)?;
+    let path = get_path_from_args(args)?;
+    let scm = read_to_string(&path)?;
 
-    for sexpr in parse(tokenize(&mut scm.chars().peekable()))? {
-        let result = sexpr.eval(&env)?;
+    let mut tokens = TokenIterator::with_file(scm.chars(), &path);
+    while tokens.peek()?.is_some() {
+        let result = expand(parse_single(&mut tokens)?)?.eval(&env)?;
         if !result.is_unspecified() {
             current_output_port().write_string(&format!("{}\n", result))?;
         }
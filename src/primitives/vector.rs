@@ -0,0 +1,150 @@
+use parser::SExpr;
+use parser::SExprs;
+use evaluator::Args;
+use serr::{SErr, SResult};
+
+//
+// Helpers
+//
+pub fn index(x: SExpr) -> SResult<usize> {
+    let i = x.as_int()?;
+    if i < 0 {
+        bail!(TypeMismatch => "non-negative integer", x)
+    }
+    Ok(i as usize)
+}
+
+/// Reads the optional `start` and `end` arguments that follow a vector or
+/// a bytevector, checking them against its length.
+pub fn range(len: usize, start: Option<SExpr>, end: Option<SExpr>) -> SResult<(usize, usize)> {
+    let start = start.map_or(Ok(0), index)?;
+    let end = end.map_or(Ok(len), index)?;
+
+    if end > len {
+        bail!(IndexOutOfBounds => len, end)
+    }
+    if start > end {
+        bail!(IndexOutOfBounds => end, start)
+    }
+
+    Ok((start, end))
+}
+
+//
+// Functions
+//
+pub fn vector(args: Args) -> SResult<SExpr> {
+    Ok(SExpr::vector_from(args.evaled()?.into_iter().collect()))
+}
+
+pub fn vector_qm(args: Args) -> SResult<SExpr> {
+    Ok(sbool!(args.evaled()?.own_one()?.as_vector().is_ok()))
+}
+
+pub fn make_vector(args: Args) -> SResult<SExpr> {
+    let evaled = args.evaled()?;
+    let (len, fill) = match evaled.len() {
+        1 => (evaled.own_one()?, SExpr::Unspecified),
+        _ => evaled.own_two()?
+    };
+
+    Ok(SExpr::vector_from(vec![fill; index(len)?]))
+}
+
+pub fn vector_length(args: Args) -> SResult<SExpr> {
+    let v = args.evaled()?.own_one()?;
+    let len = v.as_vector()?.borrow().len();
+    Ok(sint!(len as i64))
+}
+
+pub fn vector_ref(args: Args) -> SResult<SExpr> {
+    let (v, i) = args.evaled()?.own_two()?;
+    let xs = v.as_vector()?.borrow();
+    let i = index(i)?;
+
+    xs.get(i)
+        .cloned()
+        .ok_or_else(|| SErr::IndexOutOfBounds(xs.len(), i))
+}
+
+pub fn vector_set_em(args: Args) -> SResult<SExpr> {
+    let (v, i, x) = args.evaled()?.own_three()?;
+    let mut xs = v.as_vector()?.borrow_mut();
+    let i = index(i)?;
+    let len = xs.len();
+
+    let elem = xs.get_mut(i)
+        .ok_or_else(|| SErr::IndexOutOfBounds(len, i))?;
+    *elem = x;
+    Ok(SExpr::Unspecified)
+}
+
+pub fn vector_fill_em(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let v = iter.next().ok_or_else(|| SErr::WrongArgCount(2, 0))?;
+    let fill = iter.next().ok_or_else(|| SErr::WrongArgCount(2, 1))?;
+    let mut xs = v.as_vector()?.borrow_mut();
+    let (start, end) = range(xs.len(), iter.next(), iter.next())?;
+
+    for x in &mut xs[start..end] {
+        *x = fill.clone();
+    }
+    Ok(SExpr::Unspecified)
+}
+
+pub fn vector_copy(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let v = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    let xs = v.as_vector()?.borrow();
+    let (start, end) = range(xs.len(), iter.next(), iter.next())?;
+
+    Ok(SExpr::vector_from(xs[start..end].to_vec()))
+}
+
+/// (vector-copy! to at from [start [end]])
+pub fn vector_copy_em(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let to = iter.next().ok_or_else(|| SErr::WrongArgCount(3, 0))?;
+    let at = index(iter.next().ok_or_else(|| SErr::WrongArgCount(3, 1))?)?;
+    let from = iter.next().ok_or_else(|| SErr::WrongArgCount(3, 2))?;
+
+    // Copy first, `to` and `from` may be the same vector.
+    let elems = {
+        let xs = from.as_vector()?.borrow();
+        let (start, end) = range(xs.len(), iter.next(), iter.next())?;
+        xs[start..end].to_vec()
+    };
+
+    let mut ys = to.as_vector()?.borrow_mut();
+    if at + elems.len() > ys.len() {
+        bail!(IndexOutOfBounds => ys.len(), at + elems.len())
+    }
+
+    for (i, x) in elems.into_iter().enumerate() {
+        ys[at + i] = x;
+    }
+    Ok(SExpr::Unspecified)
+}
+
+pub fn vector_append(args: Args) -> SResult<SExpr> {
+    let mut result = vec![];
+    for v in args.evaled()?.iter() {
+        result.extend(v.as_vector()?.borrow().iter().cloned());
+    }
+
+    Ok(SExpr::vector_from(result))
+}
+
+pub fn vector_list(args: Args) -> SResult<SExpr> {
+    let mut iter = args.evaled()?.into_iter();
+    let v = iter.next().ok_or_else(|| SErr::WrongArgCount(1, 0))?;
+    let xs = v.as_vector()?.borrow();
+    let (start, end) = range(xs.len(), iter.next(), iter.next())?;
+
+    Ok(SExpr::list_from(xs[start..end].to_vec()))
+}
+
+pub fn list_vector(args: Args) -> SResult<SExpr> {
+    let xs: SExprs = args.evaled()?.own_one()?.into_list()?;
+    Ok(SExpr::vector_from(xs))
+}
@@ -3,21 +3,31 @@ use env::EnvRef;
 use lexer::Token;
 use parser::SExpr;
 use parser::SExprs;
-use evaluator::Args;
+use evaluator::{Args, Control, ContinuationData};
+use parameter::ParameterData;
+use record::RecordProcedure;
 use serr::{SErr, SResult};
 
 type PrimitiveProcedure = fn(Args) -> SResult<SExpr>;
 
 /// A `Procedure` may be either primitive or compound(user-defined).
+/// Continuations and the procedures that manipulate them are handled by
+/// the evaluator itself.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ProcedureData {
     Primitive(PrimitiveData),
-    Compound(CompoundData)
+    Compound(CompoundData),
+    Continuation(ContinuationData),
+    Control(Control),
+    Parameter(ParameterData),
+    Record(RecordProcedure)
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PrimitiveData {
     fun: PrimitiveProcedure,
+    /// Special forms get their arguments unevaluated.
+    special_form: bool
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,12 +42,13 @@ pub enum Param {
     Single(String),
     Fixed(Vec<String>),
     Multi(Vec<String>, String),
+    /// `case-lambda`: every clause has its own parameters and body, the
+    /// first one that accepts the arguments is used.
+    Case(Vec<(Param, SExpr)>),
 }
 
-impl ProcedureData {
-    /// Creates user defined procedure,
-    /// a `SExpr::Procedure(ProcedureData::Compound)`.
-    pub fn new_compound(params_expr: SExpr, mut body: SExprs, env: &EnvRef) -> SResult<SExpr> {
+impl Param {
+    pub fn parse(params_expr: SExpr) -> SResult<Param> {
         let params = match params_expr {
             SExpr::Atom(Token::Symbol(x)) => {
                 Param::Single(x)
@@ -61,79 +72,162 @@ impl ProcedureData {
             x => bail!(TypeMismatch => "parameter list", x)
         };
 
-
-        // Wrap body in begin: (begin body)
-        let body_expr = if body.len() == 1 {
-            body.into_iter().next().unwrap()
-        } else {
-            let mut body_vec = vec![ssymbol!("begin")];
-            body_vec.append(&mut body);
-            SExpr::List(body_vec)
-        };
-
-
-        let proc = SExpr::Procedure(ProcedureData::Compound(CompoundData {
-            params,
-            body: Box::new(body_expr),
-            env: env.clone_ref()
-        }));
-
-        Ok(proc)
+        Ok(params)
     }
 
-    /// Creates a primitive function,
-    /// a `SExpr::Procedure(ProcedureData::Primitive)`
-    pub fn new_primitive(fun: PrimitiveProcedure) -> SExpr {
-        SExpr::Procedure(ProcedureData::Primitive(PrimitiveData { fun }))
+    /// Whether a procedure with these parameters can be called with `n`
+    /// arguments.
+    fn accepts(&self, n: usize) -> bool {
+        match self {
+            Param::Single(_) => true,
+            Param::Fixed(xs) => xs.len() == n,
+            Param::Multi(xs, _) => n >= xs.len(),
+            Param::Case(clauses) => clauses.iter().any(|(x, _)| x.accepts(n))
+        }
     }
 
-    pub fn apply(&self, args: Args) -> SResult<SExpr> {
+    pub fn names(&self) -> Vec<&String> {
         match self {
-            ProcedureData::Primitive(x) => x.apply(args),
-            ProcedureData::Compound(x) => x.apply(args),
+            Param::Single(x) => vec![x],
+            Param::Fixed(xs) => xs.iter().collect(),
+            Param::Multi(xs, y) => xs.iter().chain(Some(y)).collect(),
+            Param::Case(_) => vec![]
         }
     }
-}
 
-impl CompoundData {
-    pub fn build_env(&self, args: Args) -> SResult<EnvRef> {
-        let mut inner_env = Env::new(self.env.clone_ref());
-        match self.params {
-            Param::Single(ref x) => {
-                inner_env.define(x.to_string(), SExpr::List(args.eval()?));
+    /// Binds `args` to the parameters, calling `define` with each name and
+    /// its value.
+    pub fn bind<F>(&self, args: SExprs, mut define: F) -> SResult<()>
+    where F: FnMut(String, SExpr) {
+        match self {
+            Param::Single(x) => {
+                define(x.to_string(), SExpr::list_from(args));
             },
-            Param::Fixed(ref xs) => {
+            Param::Fixed(xs) => {
                 if xs.len() != args.len() {
                     bail!(WrongArgCount => xs.len(), args.len())
                 }
-                inner_env.pack(xs.as_slice(), args.eval()?);
+                for (name, arg) in xs.iter().zip(args) {
+                    define(name.clone(), arg);
+                }
             },
-            Param::Multi(ref xs, ref y) => {
+            Param::Multi(xs, y) => {
                 if args.len() < xs.len() {
                     bail!(WrongArgCount => xs.len(), args.len())
                 }
 
-                let mut evaled_args = args.eval()?.into_iter();
+                let mut evaled_args = args.into_iter();
                 for name in xs {
-                    inner_env.define(name.clone(), evaled_args.next().unwrap());
+                    define(name.clone(), evaled_args.next().unwrap());
                 }
 
-                let rest = evaled_args.take_while(|_| true).collect::<SExprs>();
-                inner_env.define(y.clone(), SExpr::List(rest));
-            }
+                let rest = evaled_args.collect::<SExprs>();
+                define(y.clone(), SExpr::list_from(rest));
+            },
+            // The clauses of a `case-lambda` can't be `case-lambda`s.
+            Param::Case(_) => unreachable!()
         };
 
-        Ok(inner_env.into_ref())
+        Ok(())
     }
+}
 
-    pub fn apply(&self, args: Args) -> SResult<SExpr> {
-        let inner_env = self.build_env(args)?;
-        self.body.eval(&inner_env)
+/// Wraps a body in begin: (begin body)
+fn body_expr(mut body: SExprs) -> SExpr {
+    if body.len() == 1 {
+        body.into_iter().next().unwrap()
+    } else {
+        let mut body_vec = vec![ssymbol!("begin")];
+        body_vec.append(&mut body);
+        SExpr::List(body_vec)
+    }
+}
+
+impl ProcedureData {
+    /// Creates user defined procedure,
+    /// a `SExpr::Procedure(ProcedureData::Compound)`.
+    pub fn new_compound(params_expr: SExpr, body: SExprs, env: &EnvRef) -> SResult<SExpr> {
+        let proc = SExpr::Procedure(ProcedureData::Compound(CompoundData {
+            params: Param::parse(params_expr)?,
+            body: Box::new(body_expr(body)),
+            env: env.clone_ref()
+        }));
+
+        Ok(proc)
+    }
+
+    /// Creates a `case-lambda` procedure from its `(params body...)`
+    /// clauses.
+    pub fn new_case_lambda(clauses: SExprs, env: &EnvRef) -> SResult<SExpr> {
+        let clauses = clauses.into_iter()
+            .map(|clause| {
+                let mut iter = clause.into_list()?.into_iter();
+                let params = iter.next()
+                    .ok_or_else(|| SErr::new_unexpected_form(&SExpr::List(vec![])))?;
+                Ok((Param::parse(params)?, body_expr(iter.collect())))
+            })
+            .collect::<SResult<_>>()?;
+
+        Ok(SExpr::Procedure(ProcedureData::Compound(CompoundData {
+            params: Param::Case(clauses),
+            body: Box::new(SExpr::Unspecified),
+            env: env.clone_ref()
+        })))
+    }
+
+    /// Creates a primitive function,
+    /// a `SExpr::Procedure(ProcedureData::Primitive)`
+    pub fn new_primitive(fun: PrimitiveProcedure) -> SExpr {
+        SExpr::Procedure(ProcedureData::Primitive(PrimitiveData { fun, special_form: false }))
+    }
+
+    /// Creates a primitive function that takes its arguments unevaluated.
+    pub fn new_special_form(fun: PrimitiveProcedure) -> SExpr {
+        SExpr::Procedure(ProcedureData::Primitive(PrimitiveData { fun, special_form: true }))
+    }
+
+    pub fn new_control(control: Control) -> SExpr {
+        SExpr::Procedure(ProcedureData::Control(control))
+    }
+
+    pub fn new_parameter(parameter: ParameterData) -> SExpr {
+        SExpr::Procedure(ProcedureData::Parameter(parameter))
+    }
+
+    pub fn new_record(procedure: RecordProcedure) -> SExpr {
+        SExpr::Procedure(ProcedureData::Record(procedure))
+    }
+}
+
+impl CompoundData {
+    /// Returns the body to run with `args` and the environment that it runs
+    /// in, binding `args` to parameters.
+    pub fn bind(self, args: SExprs) -> SResult<(SExpr, EnvRef)> {
+        match self.params {
+            Param::Case(clauses) => {
+                let n = args.len();
+                let (params, body) = clauses.into_iter()
+                    .find(|(x, _)| x.accepts(n))
+                    .ok_or_else(|| SErr::new_generic(&format!("No case-lambda clause accepts {} arguments", n)))?;
+                Ok((body, bind_params(&params, args, &self.env)?))
+            },
+            params => Ok((*self.body, bind_params(&params, args, &self.env)?))
+        }
     }
 }
 
+fn bind_params(params: &Param, args: SExprs, env: &EnvRef) -> SResult<EnvRef> {
+    let mut inner_env = Env::new(env.clone_ref());
+    params.bind(args, |name, value| inner_env.define(name, value))?;
+    Ok(inner_env.into_ref())
+}
+
 
 impl PrimitiveData {
+    pub fn is_special_form(&self) -> bool {
+        self.special_form
+    }
+
     pub fn apply(&self, args: Args) -> SResult<SExpr> {
         (self.fun)(args)
     }
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use env::EnvRef;
+use parser::SExpr;
+use utils::{new_rc_ref_cell, RcRefCell};
+
+/// The state of a promise.
+#[derive(Debug, Clone)]
+pub enum Promise {
+    /// Already forced, or made by `make-promise`.
+    Done(SExpr),
+    /// `(delay expr)`: the value of `expr` is the value of the promise.
+    Delay(SExpr, EnvRef),
+    /// `(delay-force expr)`: `expr` returns another promise and the value
+    /// of that one is the value of this one.
+    DelayForce(SExpr, EnvRef),
+}
+
+/// A promise made by `delay`, `delay-force` or `make-promise`. Forcing a
+/// `delay-force` promise makes the promise that its expression returned
+/// share the same state, so the state is one more reference away.
+#[derive(Clone)]
+pub struct PromiseData(RcRefCell<RcRefCell<Promise>>);
+
+impl PromiseData {
+    pub fn new(promise: Promise) -> PromiseData {
+        PromiseData(new_rc_ref_cell(new_rc_ref_cell(promise)))
+    }
+
+    pub fn get(&self) -> Promise {
+        self.0.borrow().borrow().clone()
+    }
+
+    pub fn set(&self, promise: Promise) {
+        *self.0.borrow().borrow_mut() = promise;
+    }
+
+    /// Takes over the state of `other`, which shares the state of this
+    /// promise from then on.
+    pub fn adopt(&self, other: &PromiseData) {
+        self.set(other.get());
+        let state = Rc::clone(&self.0.borrow());
+        *other.0.borrow_mut() = state;
+    }
+
+    pub fn ptr_eq(&self, other: &PromiseData) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Takes the value out of a forced promise that isn't shared.
+    fn take_unique_value(&mut self) -> Option<SExpr> {
+        let state = Rc::get_mut(&mut self.0)?.get_mut();
+        match Rc::get_mut(state)?.get_mut() {
+            Promise::Done(x) => Some(mem::replace(x, SExpr::Unspecified)),
+            _ => None
+        }
+    }
+}
+
+/// A forced stream is a chain of promises and pairs, dropping a long one
+/// recursively would overflow the stack. So the values that aren't shared
+/// are unlinked in a loop, like `PairData` does with lists.
+impl Drop for PromiseData {
+    fn drop(&mut self) {
+        let mut next = self.take_unique_value();
+        while let Some(x) = next {
+            next = match x {
+                SExpr::Promise(mut promise) => promise.take_unique_value(),
+                SExpr::Pair(mut pair) => pair.take_unique_cdr(),
+                _ => None
+            };
+        }
+    }
+}
+
+impl PartialEq for PromiseData {
+    fn eq(&self, other: &PromiseData) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl fmt::Debug for PromiseData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PromiseData({:#x})", &*self.0 as *const _ as usize)
+    }
+}
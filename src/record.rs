@@ -0,0 +1,185 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use parser::{SExpr, SExprs};
+use serr::{SErr, SResult};
+
+/// A record type made by `define-record-type` or `make-record-type`.
+#[derive(Clone)]
+pub struct RecordTypeData(Rc<RecordType>);
+
+struct RecordType {
+    name: String,
+    fields: Vec<String>,
+}
+
+/// An instance of a record type. Records are compared by identity.
+#[derive(Clone)]
+pub struct RecordData(Rc<Record>);
+
+struct Record {
+    rtype: RecordTypeData,
+    values: RefCell<SExprs>,
+}
+
+/// The procedures that `define-record-type` defines for a record type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordProcedure {
+    /// Takes the values of the given fields, the others are unspecified.
+    Constructor(RecordTypeData, Vec<usize>),
+    Predicate(RecordTypeData),
+    Accessor(RecordTypeData, usize),
+    Modifier(RecordTypeData, usize),
+}
+
+impl RecordTypeData {
+    /// A `<name>` type is called `name`, like `<point>` is `point`.
+    pub fn new(name: &str, fields: Vec<String>) -> RecordTypeData {
+        let name = name.trim_start_matches('<').trim_end_matches('>');
+        RecordTypeData(Rc::new(RecordType { name: name.to_string(), fields }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.0.fields
+    }
+
+    /// The index of the field called `field`.
+    pub fn field_index(&self, field: &str) -> SResult<usize> {
+        self.0.fields.iter()
+            .position(|x| x == field)
+            .ok_or_else(|| SErr::Generic(format!("Record type {} has no field called {}", self.name(), field)))
+    }
+
+    pub fn ptr_eq(&self, other: &RecordTypeData) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl RecordData {
+    pub fn new(rtype: RecordTypeData, values: SExprs) -> RecordData {
+        RecordData(Rc::new(Record { rtype, values: RefCell::new(values) }))
+    }
+
+    pub fn record_type(&self) -> &RecordTypeData {
+        &self.0.rtype
+    }
+
+    /// The fields with their current values, in the order of the type.
+    pub fn fields(&self) -> Vec<(String, SExpr)> {
+        self.0.rtype.fields().iter()
+            .cloned()
+            .zip(self.0.values.borrow().iter().cloned())
+            .collect()
+    }
+
+    pub fn get(&self, index: usize) -> SExpr {
+        self.0.values.borrow()[index].clone()
+    }
+
+    pub fn set(&self, index: usize, value: SExpr) {
+        self.0.values.borrow_mut()[index] = value;
+    }
+
+    pub fn is_a(&self, rtype: &RecordTypeData) -> bool {
+        self.0.rtype.ptr_eq(rtype)
+    }
+
+    /// Identity of the record, used for detecting cycles.
+    pub fn id(&self) -> usize {
+        &*self.0 as *const _ as usize
+    }
+
+    pub fn ptr_eq(&self, other: &RecordData) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl RecordProcedure {
+    pub fn apply(&self, args: SExprs) -> SResult<SExpr> {
+        match self {
+            RecordProcedure::Constructor(rtype, indexes) => {
+                if args.len() != indexes.len() {
+                    return Err(SErr::WrongArgCount(indexes.len(), args.len()))
+                }
+
+                let mut values = vec![SExpr::Unspecified; rtype.fields().len()];
+                for (i, x) in indexes.iter().zip(args) {
+                    values[*i] = x;
+                }
+                Ok(SExpr::Record(RecordData::new(rtype.clone(), values)))
+            },
+            RecordProcedure::Predicate(rtype) => {
+                let x = one_arg(args)?;
+                Ok(sbool!(matches!(x, SExpr::Record(ref r) if r.is_a(rtype))))
+            },
+            RecordProcedure::Accessor(rtype, i) => {
+                let record = one_arg(args)?;
+                Ok(as_record_of(&record, rtype)?.get(*i))
+            },
+            RecordProcedure::Modifier(rtype, i) => {
+                if args.len() != 2 {
+                    return Err(SErr::WrongArgCount(2, args.len()))
+                }
+
+                let mut iter = args.into_iter();
+                let record = iter.next().unwrap();
+                as_record_of(&record, rtype)?.set(*i, iter.next().unwrap());
+                Ok(SExpr::Unspecified)
+            }
+        }
+    }
+}
+
+fn one_arg(args: SExprs) -> SResult<SExpr> {
+    if args.len() != 1 {
+        return Err(SErr::WrongArgCount(1, args.len()))
+    }
+    Ok(args.into_iter().next().unwrap())
+}
+
+fn as_record_of<'a>(x: &'a SExpr, rtype: &RecordTypeData) -> SResult<&'a RecordData> {
+    match x {
+        SExpr::Record(r) if r.is_a(rtype) => Ok(r),
+        x => bail!(TypeMismatch => rtype.name(), x)
+    }
+}
+
+impl fmt::Display for RecordProcedure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordProcedure::Constructor(t, _) => write!(f, "#<record-constructor {}>", t.name()),
+            RecordProcedure::Predicate(t) => write!(f, "#<record-predicate {}>", t.name()),
+            RecordProcedure::Accessor(t, i) => write!(f, "#<record-accessor {} {}>", t.name(), t.fields()[*i]),
+            RecordProcedure::Modifier(t, i) => write!(f, "#<record-modifier {} {}>", t.name(), t.fields()[*i]),
+        }
+    }
+}
+
+impl PartialEq for RecordTypeData {
+    fn eq(&self, other: &RecordTypeData) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl PartialEq for RecordData {
+    fn eq(&self, other: &RecordData) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl fmt::Debug for RecordTypeData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RecordTypeData({})", self.name())
+    }
+}
+
+impl fmt::Debug for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RecordData({}, {:#x})", self.0.rtype.name(), &*self.0 as *const _ as usize)
+    }
+}
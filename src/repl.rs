@@ -1,8 +1,9 @@
 use std::io;
 use std::io::prelude::*;
 
-use lexer;
+use lexer::TokenIterator;
 use parser;
+use expander::expand;
 use env::EnvRef;
 
 pub fn run(env: &EnvRef) {
@@ -14,20 +15,21 @@ pub fn run(env: &EnvRef) {
         io::stdout().flush().unwrap();
         io::stdin().read_line(&mut line).unwrap();
 
-        let tokens = lexer::tokenize(&mut line.chars().peekable());
-        let sexprs = parser::parse(tokens);
+        let sexprs = parser::parse(TokenIterator::new(line.chars()));
 
         match sexprs {
             Ok(sexprs) => {
                 for sexpr in sexprs {
-                    let evaluated = sexpr.eval(env);
+                    let evaluated = expand(sexpr).and_then(|x| x.eval(env));
 
                     match evaluated {
                         Ok(evaluated) => {
-                            if !evaluated.is_unspecified() {
-                                println!("${} = {}", i, evaluated);
-                                env.define(format!("${}", i), evaluated);
-                                i += 1;
+                            for value in evaluated.into_values() {
+                                if !value.is_unspecified() {
+                                    println!("${} = {}", i, value);
+                                    env.define(format!("${}", i), value);
+                                    i += 1;
+                                }
                             }
                         },
                         Err(e) => println!("{}", e)
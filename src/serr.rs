@@ -3,8 +3,10 @@ use std::error::Error;
 use std::io;
 use std::env;
 
-use lexer::Token;
+use lexer::{ReadErrorData, Span};
 use parser::SExpr;
+use evaluator::Jump;
+use expander::base_name;
 
 pub type SResult<T> = Result<T, SErr>;
 
@@ -15,10 +17,14 @@ pub enum SErr {
     EnvNotFound,
     DivisionByZero,
     UnexpectedForm(SExpr),
-    UnexpectedToken(Token),
-    NotExpectedToken(Token, Token),
+    /// Malformed syntax, with where it was found.
+    ReadError(Box<ReadErrorData>),
+    /// An error raised while evaluating the code at this location.
+    Located(Span, Box<SErr>),
     Cast(String, SExpr),
     UnboundVar(String),
+    /// A `letrec` variable that is used before it gets its value.
+    UnassignedVar(String),
     NotAProcedure(SExpr),
     WrongArgCount(/*expected: */usize, /*found: */usize),
     IndexOutOfBounds(/*max: */usize, /*requested: */usize),
@@ -26,6 +32,14 @@ pub enum SErr {
     WrongPort(/*proc: */String, /*port: */String),
     //TODO: what about Trace(String, Box<SErr>)
 
+    /// An object raised by `raise` or `error` that no handler took care of.
+    Raise(SExpr),
+    /// An error that has already been offered to the exception handlers.
+    Unhandled(Box<SErr>),
+    /// Not an error, a continuation on its way to the evaluator run that
+    /// it belongs to.
+    Jump(Box<Jump>),
+
     // Converted errors
     IOErr(io::Error),
     VarErr(env::VarError)
@@ -39,15 +53,20 @@ impl fmt::Display for SErr {
             SErr::EnvNotFound => "Environment not found. (Probably an unbound variable)".to_string(),
             SErr::DivisionByZero => "Division by zero".to_string(),
             SErr::UnexpectedForm(x) => format!("Expression is in unexpected form: {}", x),
-            SErr::UnexpectedToken(x) => format!("Not expected this token: {}", x),
-            SErr::NotExpectedToken(x, y) => format!("Expected one of {}, found {}", x, y),
+            SErr::ReadError(x) => x.to_string(),
+            SErr::Located(span, x) => format!("{}: {}", span, x),
             SErr::Cast(typ, x) => format!("Can't convert {} to {}", x, typ),
             SErr::UnboundVar(x) => format!("Unbound variable: {}", x),
+            SErr::UnassignedVar(x) => format!("Variable used before its initialization: {}", base_name(x)),
             SErr::NotAProcedure(x) => format!("Wrong type to apply, not a procedure: {}", x),
             SErr::WrongArgCount(x, y) => format!("Wrong arg count; expected: {}, found: {}", x, y),
             SErr::IndexOutOfBounds(x, y) => format!("Index out of bounds. Max size: {}, requested: {}", x, y),
             SErr::TypeMismatch(x, y) => format!("Expected a {}, found this: {}", x, y),
-            SErr::WrongPort(x, y) => format!("Can't apply function `{}` to a port type of {}", x, y),
+            SErr::WrongPort(x, y) => format!("Can't apply function `{}` to the {}", x, y),
+            SErr::Raise(SExpr::Condition(x)) => x.to_string(),
+            SErr::Raise(x) => format!("Uncaught exception: {}", x),
+            SErr::Unhandled(x) => x.to_string(),
+            SErr::Jump(_) => "Continuation invoked outside of its extent".to_string(),
             SErr::IOErr(x) => x.to_string(),
             SErr::VarErr(x) => x.to_string()
         };
@@ -64,15 +83,19 @@ impl Error for SErr {
             SErr::EnvNotFound => "Environment not found. (Probably an unbound variable)",
             SErr::DivisionByZero => "Division by zero",
             SErr::UnexpectedForm(_) => "Expression is in unexpected form.",
-            SErr::UnexpectedToken(_) => "Unexpected token.",
-            SErr::NotExpectedToken(_, _) => "Unexpected token.",
+            SErr::ReadError(_) => "Read error.",
+            SErr::Located(_, _) => "Error in located code.",
             SErr::Cast(_, _) => "Failed conversion.",
             SErr::UnboundVar(_) => "Unbound variable.",
+            SErr::UnassignedVar(_) => "Variable used before its initialization.",
             SErr::NotAProcedure(_) => "Not a procedure.",
             SErr::WrongArgCount(_, _) => "Wrong arg count.",
             SErr::IndexOutOfBounds(_, _) => "Index out of bounds.",
             SErr::TypeMismatch(_, _) => "Type mismatch.",
             SErr::WrongPort(_, _) => "Wrong type of port.",
+            SErr::Raise(_) => "Uncaught exception.",
+            SErr::Unhandled(_) => "Unhandled error.",
+            SErr::Jump(_) => "Continuation invoked outside of its extent.",
             SErr::IOErr(_) => "IO error.",
             SErr::VarErr(_) => "Variable error."
 
@@ -103,6 +126,19 @@ impl SErr {
     pub fn new_expr_not_found(s: &str) -> SErr {
         SErr::new_generic(&format!("Expected an expression, found: {}", s))
     }
+
+    /// Tells that the error happened at `location`, unless it already
+    /// knows where it happened.
+    pub fn located(self, location: Option<&Span>) -> SErr {
+        match (self, location) {
+            (SErr::Unhandled(x), _) => SErr::Unhandled(Box::new(x.located(location))),
+            (x@SErr::Located(_, _), _)
+                | (x@SErr::ReadError(_), _)
+                | (x@SErr::Jump(_), _)
+                | (x, None) => x,
+            (x, Some(span)) => SErr::Located(span.clone(), Box::new(x))
+        }
+    }
 }
 
 impl From<io::Error> for SErr {
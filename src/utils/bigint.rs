@@ -0,0 +1,503 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::f64;
+use std::ops::{Add, Sub, Mul, Div, Rem, Neg};
+
+/// An arbitrary-precision integer.
+///
+/// The magnitude is stored in base 2^32, least significant digit first and
+/// without leading zeros, so zero has no digits at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>
+}
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt { negative: false, digits: vec![] }
+    }
+
+    fn new(negative: bool, digits: Vec<u32>) -> BigInt {
+        let mut result = BigInt { negative, digits };
+        while result.digits.last() == Some(&0) {
+            result.digits.pop();
+        }
+        if result.digits.is_empty() {
+            result.negative = false;
+        }
+
+        result
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.digits.first().is_none_or(|x| x % 2 == 0)
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt { negative: false, digits: self.digits.clone() }
+    }
+
+    /// Number of bits that are needed to represent the magnitude.
+    pub fn bits(&self) -> usize {
+        match self.digits.last() {
+            Some(x) => self.digits.len() * 32 - x.leading_zeros() as usize,
+            None => 0
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None
+        }
+
+        let magnitude = self.digits.iter()
+            .rev()
+            .fold(0u64, |acc, x| (acc << 32) | u64::from(*x));
+
+        if self.negative && magnitude <= i64::MAX as u64 + 1 {
+            Some((magnitude as i64).wrapping_neg())
+        } else if !self.negative && magnitude <= i64::MAX as u64 {
+            Some(magnitude as i64)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.digits.iter()
+            .rev()
+            .fold(0.0, |acc, x| acc * 4_294_967_296.0 + f64::from(*x));
+
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    /// Converts the integral part of a finite float, exactly.
+    pub fn from_f64(x: f64) -> Option<BigInt> {
+        if !x.is_finite() {
+            return None
+        }
+
+        let bits = x.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        if exponent == 0 {
+            // Subnormal numbers are all smaller than 1
+            return Some(BigInt::zero())
+        }
+
+        let mantissa = BigInt::from(((bits & ((1 << 52) - 1)) | (1 << 52)) as i64);
+        let shift = exponent - 1075;
+        let magnitude = if shift >= 0 {
+            mantissa.shl(shift as usize)
+        } else {
+            mantissa.shr((-shift) as usize)
+        };
+
+        Some(if x < 0.0 { -magnitude } else { magnitude })
+    }
+
+    pub fn shl(&self, n: usize) -> BigInt {
+        let mut digits = vec![0; n / 32];
+        digits.extend(shl_digits(&self.digits, (n % 32) as u32));
+        BigInt::new(self.negative, digits)
+    }
+
+    /// Shifts the magnitude to the right, rounding towards zero.
+    pub fn shr(&self, n: usize) -> BigInt {
+        if n / 32 >= self.digits.len() {
+            return BigInt::zero()
+        }
+
+        let digits = shr_digits(&self.digits[n / 32..], (n % 32) as u32);
+        BigInt::new(self.negative, digits)
+    }
+
+    /// Truncating division, the remainder has the sign of `self`.
+    ///
+    /// Panics if `other` is zero.
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        if other.is_zero() {
+            panic!("BigInt division by zero")
+        }
+
+        let (q, r) = div_rem_digits(&self.digits, &other.digits);
+        (BigInt::new(self.negative != other.negative, q), BigInt::new(self.negative, r))
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = result * base.clone();
+            }
+            base = base.clone() * base;
+            exponent /= 2;
+        }
+
+        result
+    }
+
+    /// The biggest integer whose square is not bigger than `self`, which
+    /// must not be negative.
+    pub fn sqrt(&self) -> BigInt {
+        if self.is_zero() {
+            return BigInt::zero()
+        }
+
+        // Newton's method, starting from a power of two that is bigger
+        // than the root so the iterations decrease monotonically.
+        let mut x = BigInt::from(1).shl(self.bits().div_ceil(2));
+        loop {
+            let y = (x.clone() + self.div_rem(&x).0).shr(1);
+            if y >= x {
+                return x
+            }
+            x = y;
+        }
+    }
+
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let mut a = self.abs();
+        let mut b = other.abs();
+        while !b.is_zero() {
+            let r = a.div_rem(&b).1;
+            a = b;
+            b = r;
+        }
+
+        a
+    }
+
+    pub fn to_str_radix(&self, radix: u32) -> String {
+        if self.is_zero() {
+            return "0".to_string()
+        }
+
+        // Divide by the biggest power of radix that fits in a digit and
+        // convert each remainder to `chunk_len` characters.
+        let mut chunk = radix;
+        let mut chunk_len = 1;
+        while let Some(x) = chunk.checked_mul(radix) {
+            chunk = x;
+            chunk_len += 1;
+        }
+
+        let mut chars = vec![];
+        let mut digits = self.digits.clone();
+        while !digits.is_empty() {
+            let (q, mut r) = div_rem_small(&digits, chunk);
+            digits = q;
+            for _ in 0..chunk_len {
+                chars.push(::std::char::from_digit(r % radix, radix).unwrap());
+                r /= radix;
+                if digits.is_empty() && r == 0 {
+                    break
+                }
+            }
+        }
+
+        if self.negative {
+            chars.push('-');
+        }
+
+        chars.into_iter().rev().collect()
+    }
+
+    /// Parses an optionally signed integer, returns `None` if `s` is not
+    /// a valid integer in the given radix.
+    pub fn from_str_radix(s: &str, radix: u32) -> Option<BigInt> {
+        let (negative, s) = if let Some(s) = s.strip_prefix('-') {
+            (true, s)
+        } else {
+            (false, s.strip_prefix('+').unwrap_or(s))
+        };
+
+        if s.is_empty() {
+            return None
+        }
+
+        let mut digits: Vec<u32> = vec![];
+        for c in s.chars() {
+            let mut carry = u64::from(c.to_digit(radix)?);
+            for d in &mut digits {
+                let x = u64::from(*d) * u64::from(radix) + carry;
+                *d = x as u32;
+                carry = x >> 32;
+            }
+            if carry != 0 {
+                digits.push(carry as u32);
+            }
+        }
+
+        Some(BigInt::new(negative, digits))
+    }
+}
+
+//
+// Helpers
+//
+// These work on magnitudes, digits are in base 2^32 and least significant
+// first.
+fn cmp_digits(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for (i, x) in a.iter().enumerate() {
+        let sum = u64::from(*x) + u64::from(*b.get(i).unwrap_or(&0)) + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+
+    result
+}
+
+/// `a` must be bigger than or equal to `b`.
+fn sub_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, x) in a.iter().enumerate() {
+        let diff = i64::from(*x) - i64::from(*b.get(i).unwrap_or(&0)) - borrow;
+        result.push(diff as u32);
+        borrow = if diff < 0 { 1 } else { 0 };
+    }
+
+    result
+}
+
+fn mul_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, y) in b.iter().enumerate() {
+            let product = u64::from(*x) * u64::from(*y) + u64::from(result[i + j]) + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+
+    result
+}
+
+/// Shifts left by `n` bits, `n` must be smaller than 32.
+fn shl_digits(a: &[u32], n: u32) -> Vec<u32> {
+    if n == 0 {
+        return a.to_vec()
+    }
+
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for x in a {
+        result.push((x << n) | carry);
+        carry = x >> (32 - n);
+    }
+    if carry != 0 {
+        result.push(carry);
+    }
+
+    result
+}
+
+/// Shifts right by `n` bits, `n` must be smaller than 32.
+fn shr_digits(a: &[u32], n: u32) -> Vec<u32> {
+    if n == 0 {
+        return a.to_vec()
+    }
+
+    let mut result = vec![0; a.len()];
+    for i in 0..a.len() {
+        let high = a.get(i + 1).map_or(0, |x| x << (32 - n));
+        result[i] = (a[i] >> n) | high;
+    }
+
+    result
+}
+
+fn div_rem_small(a: &[u32], b: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0; a.len()];
+    let mut r = 0u64;
+    for i in (0..a.len()).rev() {
+        let x = (r << 32) | u64::from(a[i]);
+        q[i] = (x / u64::from(b)) as u32;
+        r = x % u64::from(b);
+    }
+
+    while q.last() == Some(&0) {
+        q.pop();
+    }
+
+    (q, r as u32)
+}
+
+/// Long division, algorithm D from Knuth's TAOCP vol. 2, 4.3.1.
+fn div_rem_digits(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_digits(a, b) == Ordering::Less {
+        return (vec![], a.to_vec())
+    }
+    if b.len() == 1 {
+        let (q, r) = div_rem_small(a, b[0]);
+        return (q, vec![r])
+    }
+
+    // Normalize so that the top digit of the divisor has its highest bit
+    // set, this keeps the estimated quotient digits off by at most 2.
+    let shift = b[b.len() - 1].leading_zeros();
+    let v = shl_digits(b, shift);
+    let mut u = shl_digits(a, shift);
+    if u.len() == a.len() {
+        u.push(0);
+    }
+
+    let n = v.len();
+    let m = u.len() - n - 1;
+    let base = 1u64 << 32;
+    let mut q = vec![0u32; m + 1];
+
+    for j in (0..=m).rev() {
+        let top = (u64::from(u[j + n]) << 32) | u64::from(u[j + n - 1]);
+        let mut qhat = top / u64::from(v[n - 1]);
+        let mut rhat = top % u64::from(v[n - 1]);
+        while qhat >= base || qhat * u64::from(v[n - 2]) > ((rhat << 32) | u64::from(u[j + n - 2])) {
+            qhat -= 1;
+            rhat += u64::from(v[n - 1]);
+            if rhat >= base {
+                break
+            }
+        }
+
+        // Multiply and subtract
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let product = qhat * u64::from(v[i]) + carry;
+            carry = product >> 32;
+            let diff = i64::from(u[i + j]) - borrow - (product & 0xffff_ffff) as i64;
+            u[i + j] = diff as u32;
+            borrow = if diff < 0 { 1 } else { 0 };
+        }
+        let diff = i64::from(u[j + n]) - borrow - carry as i64;
+        u[j + n] = diff as u32;
+
+        // The estimate was one too big, add the divisor back
+        if diff < 0 {
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u64::from(u[i + j]) + u64::from(v[i]) + carry;
+                u[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+
+        q[j] = qhat as u32;
+    }
+
+    u.truncate(n);
+    (q, shr_digits(&u, shift))
+}
+
+//
+// Traits
+//
+impl From<i64> for BigInt {
+    fn from(x: i64) -> BigInt {
+        let magnitude = x.unsigned_abs();
+        BigInt::new(x < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_digits(&self.digits, &other.digits),
+            (true, true) => cmp_digits(&other.digits, &self.digits)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits)
+    }
+}
+
+impl Add for BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::new(self.negative, add_digits(&self.digits, &rhs.digits))
+        }
+
+        match cmp_digits(&self.digits, &rhs.digits) {
+            Ordering::Less => BigInt::new(rhs.negative, sub_digits(&rhs.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_digits(&self.digits, &rhs.digits))
+        }
+    }
+}
+
+impl Sub for BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: BigInt) -> BigInt {
+        self + (-rhs)
+    }
+}
+
+impl Mul for BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: BigInt) -> BigInt {
+        BigInt::new(self.negative != rhs.negative, mul_digits(&self.digits, &rhs.digits))
+    }
+}
+
+impl Div for BigInt {
+    type Output = BigInt;
+
+    fn div(self, rhs: BigInt) -> BigInt {
+        self.div_rem(&rhs).0
+    }
+}
+
+impl Rem for BigInt {
+    type Output = BigInt;
+
+    fn rem(self, rhs: BigInt) -> BigInt {
+        self.div_rem(&rhs).1
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_str_radix(10))
+    }
+}
@@ -0,0 +1,146 @@
+use std::f64;
+use std::f64::consts::FRAC_PI_2;
+
+use lexer::Token;
+
+/// A complex number in rectangular form. Both parts are real numbers and
+/// they are either both exact or both floats.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Complex {
+    pub re: Token,
+    pub im: Token
+}
+
+/// A complex number as a pair of floats, this is what the transcendental
+/// functions below work on.
+pub type Parts = (f64, f64);
+
+impl Complex {
+    /// Builds `re + im*i` from two real numbers. Exact numbers with a zero
+    /// imaginary part are real, otherwise one inexact part makes the other
+    /// one inexact too.
+    pub fn make(re: Token, im: Token) -> Token {
+        if im == Token::Integer(0) {
+            return re
+        }
+
+        if re.is_exact() && im.is_exact() {
+            Token::Complex(Box::new(Complex { re, im }))
+        } else {
+            Complex::from_floats((re.to_float().unwrap(), im.to_float().unwrap()))
+        }
+    }
+
+    /// Builds a complex number from its magnitude and angle, both have to
+    /// be real numbers.
+    pub fn polar(magnitude: Token, angle: Token) -> Token {
+        if angle == Token::Integer(0) {
+            return magnitude
+        }
+
+        let (m, a) = (magnitude.to_float().unwrap(), angle.to_float().unwrap());
+        Complex::from_floats((m * a.cos(), m * a.sin()))
+    }
+
+    pub fn from_floats(z: Parts) -> Token {
+        Token::Complex(Box::new(Complex {
+            re: Token::Float(z.0),
+            im: Token::Float(z.1)
+        }))
+    }
+
+    pub fn to_floats(&self) -> Parts {
+        (self.re.to_float().unwrap(), self.im.to_float().unwrap())
+    }
+}
+
+//
+// Float arithmetic
+//
+pub fn add(a: Parts, b: Parts) -> Parts {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+pub fn sub(a: Parts, b: Parts) -> Parts {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+pub fn mul(a: Parts, b: Parts) -> Parts {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+pub fn div(a: Parts, b: Parts) -> Parts {
+    let d = b.0 * b.0 + b.1 * b.1;
+    ((a.0 * b.0 + a.1 * b.1) / d, (a.1 * b.0 - a.0 * b.1) / d)
+}
+
+/// Multiplies by `i`.
+fn rotate(z: Parts) -> Parts {
+    (-z.1, z.0)
+}
+
+//
+// Transcendental functions, all of them return the principal value
+//
+pub fn exp(z: Parts) -> Parts {
+    let r = z.0.exp();
+    (r * z.1.cos(), r * z.1.sin())
+}
+
+pub fn ln(z: Parts) -> Parts {
+    (z.0.hypot(z.1).ln(), z.1.atan2(z.0))
+}
+
+pub fn sqrt(z: Parts) -> Parts {
+    let r = z.0.hypot(z.1);
+    let re = ((r + z.0) / 2.0).sqrt();
+    let im = ((r - z.0) / 2.0).sqrt();
+    (re, if z.1.is_sign_negative() { -im } else { im })
+}
+
+/// `z` to the power of `w`. Zero to a power with a positive real part is
+/// zero, other powers of zero are undefined except for `w = 0`.
+pub fn expt(z: Parts, w: Parts) -> Parts {
+    if z == (0.0, 0.0) {
+        return if w == (0.0, 0.0) {
+            (1.0, 0.0)
+        } else if w.0 > 0.0 {
+            (0.0, 0.0)
+        } else {
+            (f64::NAN, f64::NAN)
+        }
+    }
+
+    exp(mul(w, ln(z)))
+}
+
+pub fn sin(z: Parts) -> Parts {
+    (z.0.sin() * z.1.cosh(), z.0.cos() * z.1.sinh())
+}
+
+pub fn cos(z: Parts) -> Parts {
+    (z.0.cos() * z.1.cosh(), -z.0.sin() * z.1.sinh())
+}
+
+pub fn tan(z: Parts) -> Parts {
+    div(sin(z), cos(z))
+}
+
+/// asin z = -i ln(iz + sqrt(1 - z^2))
+pub fn asin(z: Parts) -> Parts {
+    let w = ln(add(rotate(z), sqrt(sub((1.0, 0.0), mul(z, z)))));
+    (w.1, -w.0)
+}
+
+/// acos z = pi/2 - asin z
+pub fn acos(z: Parts) -> Parts {
+    let w = asin(z);
+    (FRAC_PI_2 - w.0, -w.1)
+}
+
+/// atan z = (ln(1 + iz) - ln(1 - iz)) / 2i
+pub fn atan(z: Parts) -> Parts {
+    let iz = rotate(z);
+    let w = sub(ln(add((1.0, 0.0), iz)), ln(sub((1.0, 0.0), iz)));
+    (w.1 / 2.0, -w.0 / 2.0)
+}
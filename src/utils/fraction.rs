@@ -1,51 +1,139 @@
-use std::num::ParseIntError;
-use std::ops::{Add, Sub, Mul, Div};
-use std::str::FromStr;
+use std::cmp::Ordering;
+use std::ops::{Add, Sub, Mul, Div, Neg};
 use std::f64;
 
-use utils::funcs::gcd;
+use utils::bigint::BigInt;
+use serr::{SErr, SResult};
 
-#[derive(Debug, PartialOrd, PartialEq, Clone, Copy)]
+/// An exact rational number. It is always kept reduced and the
+/// denominator is always positive.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Fraction {
-    pub n: i64,
-    pub d: i64
+    pub n: BigInt,
+    pub d: BigInt
 }
 
 impl Fraction {
-    pub fn new(n: i64, d: i64) -> Self {
-        if d == 0 {
-            panic!("Divide by zero dude!")
+    pub fn new(n: BigInt, d: BigInt) -> SResult<Self> {
+        if d.is_zero() {
+            serr!(DivisionByZero)
         }
 
-        if d < 0 {
-            Self { n: -n, d: -d }.reduce()
-        } else {
-            Self { n, d }.reduce()
-        }
+        Ok(Fraction::reduced(n, d))
     }
 
-    pub fn reduce(&self) -> Self {
-        let gcd = gcd(self.n.abs(), self.d.abs());
+    /// Same as `new`, for when `d` is known to be non-zero.
+    fn reduced(n: BigInt, d: BigInt) -> Self {
+        let (n, d) = if d.is_negative() { (-n, -d) } else { (n, d) };
+        let gcd = n.gcd(&d);
         Self {
-            n: self.n / gcd,
-            d: self.d / gcd
+            n: n.div_rem(&gcd).0,
+            d: d.div_rem(&gcd).0
         }
     }
 
     pub fn is_int(&self) -> bool {
-        self.d == 1
+        self.d == BigInt::from(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.n.is_zero()
+    }
+
+    /// Converts a float to the fraction it represents exactly, `None` for
+    /// infinities and NaN.
+    pub fn from_f64(x: f64) -> Option<Fraction> {
+        if !x.is_finite() {
+            return None
+        }
+
+        // Every finite float is mantissa * 2^exponent
+        let bits = x.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mantissa = if exponent == 0 {
+            (bits & ((1 << 52) - 1)) << 1
+        } else {
+            (bits & ((1 << 52) - 1)) | (1 << 52)
+        };
+
+        let mut n = BigInt::from(mantissa as i64);
+        if x < 0.0 {
+            n = -n;
+        }
+
+        let shift = exponent - 1075;
+        let one = BigInt::from(1);
+        Some(if shift >= 0 {
+            Fraction::from(n.shl(shift as usize))
+        } else {
+            Fraction::reduced(n, one.shl((-shift) as usize))
+        })
+    }
+
+    pub fn floor(&self) -> BigInt {
+        let (q, r) = self.n.div_rem(&self.d);
+        if r.is_negative() { q - BigInt::from(1) } else { q }
+    }
+
+    pub fn ceil(&self) -> BigInt {
+        let (q, r) = self.n.div_rem(&self.d);
+        if !r.is_zero() && !r.is_negative() { q + BigInt::from(1) } else { q }
+    }
+
+    pub fn trunc(&self) -> BigInt {
+        self.n.div_rem(&self.d).0
+    }
+
+    /// Rounds to the nearest integer, to the even one on ties.
+    pub fn round(&self) -> BigInt {
+        let floor = self.floor();
+        let rest = self.n.clone() - floor.clone() * self.d.clone();
+        match rest.shl(1).cmp(&self.d) {
+            Ordering::Less => floor,
+            Ordering::Greater => floor + BigInt::from(1),
+            Ordering::Equal if floor.is_even() => floor,
+            Ordering::Equal => floor + BigInt::from(1)
+        }
+    }
+
+    /// Parses `n/d` where both parts are integers in the given radix.
+    pub fn from_str_radix(s: &str, radix: u32) -> Option<Self> {
+        let mut splitted = s.splitn(2, '/');
+        let n = BigInt::from_str_radix(splitted.next()?, radix)?;
+        let d = splitted.next()?;
+        if d.starts_with('-') || d.starts_with('+') {
+            return None
+        }
+
+        Fraction::new(n, BigInt::from_str_radix(d, radix)?).ok()
+    }
+
+    pub fn to_str_radix(&self, radix: u32) -> String {
+        if self.is_int() {
+            self.n.to_str_radix(radix)
+        } else {
+            format!("{}/{}", self.n.to_str_radix(radix), self.d.to_str_radix(radix))
+        }
     }
 }
 
-impl FromStr for Fraction {
-    type Err = ParseIntError;
+impl Ord for Fraction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.n.clone() * other.d.clone()).cmp(&(other.n.clone() * self.d.clone()))
+    }
+}
+
+impl PartialOrd for Fraction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut splitted = s.split('/');
-        let n = splitted.next().unwrap().parse::<i64>()?;
-        let d = splitted.next().unwrap().parse::<i64>()?;
+impl Neg for Fraction {
+    type Output = Self;
 
-        Ok(Fraction::new(n, d))
+    fn neg(self) -> Self {
+        Self { n: -self.n, d: self.d }
     }
 }
 
@@ -53,7 +141,7 @@ impl Add for Fraction {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self {
-        Fraction::new(self.n * rhs.d + rhs.n * self.d, self.d * rhs.d)
+        Fraction::reduced(self.n * rhs.d.clone() + rhs.n * self.d.clone(), self.d * rhs.d)
     }
 }
 
@@ -61,7 +149,7 @@ impl Sub for Fraction {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self {
-        Fraction::new(self.n * rhs.d - rhs.n * self.d, self.d * rhs.d)
+        self + (-rhs)
     }
 }
 
@@ -69,62 +157,59 @@ impl Mul for Fraction {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self {
-        Fraction::new(self.n * rhs.n, self.d * rhs.d)
+        Fraction::reduced(self.n * rhs.n, self.d * rhs.d)
     }
 }
 
+/// Panics if `rhs` is zero, check it with `is_zero` first.
 impl Div for Fraction {
     type Output = Self;
 
     fn div(self, rhs: Self) -> Self {
-        Fraction::new(self.n * rhs.d, self.d * rhs.n)
+        if rhs.is_zero() {
+            panic!("Fraction division by zero")
+        }
+
+        Fraction::reduced(self.n * rhs.d, self.d * rhs.n)
     }
 }
 
-impl From<i64> for Fraction {
-    fn from(i: i64) -> Fraction {
-        Fraction::new(i, 1)
+impl From<BigInt> for Fraction {
+    fn from(n: BigInt) -> Fraction {
+        Fraction { n, d: BigInt::from(1) }
     }
 }
 
-impl From<Fraction> for f64 {
-    fn from(f: Fraction) -> f64 {
-        f.n as f64 / f.d as f64
+impl From<i64> for Fraction {
+    fn from(i: i64) -> Fraction {
+        Fraction::from(BigInt::from(i))
     }
 }
 
-impl From<f64> for Fraction {
-    // https://rosettacode.org/wiki/Convert_decimal_number_to_rational#Rust
-    fn from(mut n: f64) -> Fraction {
-        let flag_neg  = n < 0.0;
-        if flag_neg { n *= -1.0 }
-        if n < f64::MIN_POSITIVE {
-            return Fraction::new(0, 1)
-        }
-        if (n - n.round()).abs() < f64::EPSILON {
-            return Fraction::new(n.round() as i64, 1)
-        }
-        let mut a : isize = 0;
-        let mut b : isize = 1;
-        let mut c : isize = n.ceil() as isize;
-        let mut d : isize = 1;
-        let aux1 = isize::max_value()/2;
-        while c < aux1  && d < aux1 {
-            let aux2 : f64 = (a as f64 + c as f64)/(b as f64 + d as f64);
-            if (n - aux2).abs() < f64::EPSILON { break }
-            if n > aux2 {
-                a += c;
-                b += d;
-            } else {
-                c += a;
-                d += b;
-            }
-        }
-
-        if flag_neg {
-            Fraction::new(-(a+c) as i64, (b+d) as i64)
+impl From<&Fraction> for f64 {
+    fn from(f: &Fraction) -> f64 {
+        // Converting both parts first would give inf/inf for big numbers,
+        // so the quotient is scaled to have 64 significant bits instead.
+        let shift = 64 - (f.n.bits() as i64 - f.d.bits() as i64);
+        let q = if shift >= 0 {
+            f.n.shl(shift as usize) / f.d.clone()
         } else {
-            Fraction::new((a+c) as i64, (b+d) as i64)
+            f.n.clone() / f.d.shl((-shift) as usize)
+        };
+
+        let mut result = q.to_f64();
+        let mut exponent = -shift;
+        while exponent.abs() > 1000 {
+            result *= 2f64.powi(1000 * exponent.signum() as i32);
+            exponent -= 1000 * exponent.signum();
         }
+
+        result * 2f64.powi(exponent as i32)
+    }
+}
+
+impl From<Fraction> for f64 {
+    fn from(f: Fraction) -> f64 {
+        f64::from(&f)
     }
 }
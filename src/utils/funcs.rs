@@ -1,8 +0,0 @@
-pub fn gcd(mut m: i64, mut n: i64) -> i64 {
-    while m != 0 {
-        let old_m = m;
-        m = n % m;
-        n = old_m;
-    }
-    n.abs()
-}
@@ -11,6 +11,18 @@ macro_rules! environment(
     };
 );
 
+#[macro_export]
+macro_rules! special_forms(
+    { $($key:expr => $value:expr),* } => {
+        {
+            use env::EnvValues;
+            use procedure::ProcedureData;
+            let mut m = EnvValues::new();
+            $(m.insert($key.to_string(), ProcedureData::new_special_form($value));)*
+            m
+        }
+    };
+);
 
 #[macro_export]
 macro_rules! slist(
@@ -1,9 +1,9 @@
 #[macro_use]
 pub mod macros;
+pub mod bigint;
 pub mod fraction;
-pub mod funcs;
+pub mod complex;
 pub mod chars;
-pub mod radix;
 
 use std::vec::IntoIter;
 use std::iter::Peekable;